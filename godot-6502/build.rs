@@ -0,0 +1,30 @@
+// Assembles the monitor ROM with the crate's own assembler, so the image in the library always
+// matches rom/monitor.asm. See src/monitor.rs.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[allow(unused)]
+#[path = "src/asm6502/mod.rs"]
+mod asm6502;
+
+fn main() {
+    println!("cargo:rerun-if-changed=rom/monitor.asm");
+    println!("cargo:rerun-if-changed=src/asm6502");
+
+    let output = asm6502::assemble_string(include_str!("rom/monitor.asm"))
+        .unwrap_or_else(|errors| panic!("rom/monitor.asm: {}", asm6502::describe(&errors)));
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+    fs::write(out_dir.join("monitor.bin"), &output.bytes).unwrap();
+    fs::write(
+        out_dir.join("monitor.rs"),
+        format!(
+            "pub const MONITOR_ORIGIN: u16 = {:#06X};\npub const MONITOR_ENTRY: u16 = {:#06X};\n",
+            output.start_address, output.symbols["reset"]
+        ),
+    )
+    .unwrap();
+}
@@ -0,0 +1,222 @@
+; Monitor ROM, in the spirit of Wozmon: examine, deposit and run code over the antenna's serial
+; port, so a program can be uploaded and started without reloading the computer.
+;
+;   0600            print the byte at $0600
+;   0600.060F       print $0600 to $060F, eight bytes per line
+;   0600: A9 01 60  store bytes from $0600 on, a line starting with ':' continues the last store
+;   0600 R          call the code at $0600, an RTS returns to the monitor
+;
+; Commands can share a line, e.g. "0600: A9 01 60 0600 R". Lines end with CR or LF, backspace
+; drops the last character and escape drops the whole line.
+;
+; Assembled into the crate by build.rs. The monitor uses $24-$2C in zero page and $0300-$037F
+; for the line it is reading.
+
+ACIA_DATA = $0215
+ACIA_STATUS = $0216
+ACIA_COMMAND = $0217
+ACIA_CONTROL = $0218
+
+XAML = $24 ; last address examined
+XAMH = $25
+STL = $26 ; next address stored to
+STH = $27
+L = $28 ; hex value being parsed
+H = $29
+YSAV = $2A
+MODE = $2B ; $00 examine, $74 store, $80 examine a block
+DIGIT = $2C
+
+IN = $0300
+
+.org $FE00
+reset:
+    CLD
+    LDX #$FF
+    TXS
+    STA ACIA_STATUS ; programmed reset
+    LDA #$0B
+    STA ACIA_COMMAND ; receiver on, no interrupts
+    LDA #$10
+    STA ACIA_CONTROL ; 8N1 at the fastest rate
+
+escape:
+    LDA #$5C ; "\"
+    JSR echo
+getline:
+    LDA #$0D
+    JSR echo
+    LDY #$01
+backspace:
+    DEY
+    BMI getline
+nextchar:
+    LDA ACIA_STATUS
+    AND #$08 ; RDRF
+    BEQ nextchar
+    LDA ACIA_DATA
+    CMP #$60
+    BCC notlower
+    AND #$DF ; upper case, DEL becomes "_"
+notlower:
+    CMP #$0A
+    BNE notlf
+    LDA #$0D
+notlf:
+    STA IN,Y
+    JSR echo
+    CMP #$0D
+    BEQ parse
+    CMP #$08
+    BEQ backspace
+    CMP #$5F
+    BEQ backspace
+    CMP #$1B
+    BEQ escape
+    INY
+    BPL nextchar
+    BMI escape ; line too long
+
+parse:
+    LDY #$FF
+    LDA #$00
+    TAX
+setstore:
+    ASL
+setmode:
+    STA MODE
+blankskip:
+    INY
+nextitem:
+    LDA IN,Y
+    CMP #$0D
+    BEQ getline
+    CMP #$2E ; "."
+    BCC blankskip
+    BEQ setblock
+    CMP #$3A ; ":"
+    BEQ setstore
+    CMP #$52 ; "R"
+    BEQ run
+    STX L
+    STX H
+    STY YSAV
+nexthex:
+    LDA IN,Y
+    EOR #$30 ; "0"-"9" map to 0-9
+    CMP #$0A
+    BCC digit
+    ADC #$88 ; "A"-"F" map to $FA-$FF
+    CMP #$FA
+    BCC nothex
+digit:
+    AND #$0F
+    STA DIGIT
+    LDX #$04
+hexshift:
+    ASL L
+    ROL H
+    DEX
+    BNE hexshift
+    LDA L
+    ORA DIGIT
+    STA L
+    INY
+    BNE nexthex
+nothex:
+    CPY YSAV
+    BNE number
+    JMP escape ; not a command nor a number
+number:
+    BIT MODE
+    BVC notstore
+    LDA L
+    STA (STL,X)
+    INC STL
+    BNE tonextitem
+    INC STH
+tonextitem:
+    JMP nextitem
+
+setblock:
+    LDA #$80
+    BNE setmode
+
+run:
+    JSR go
+    JMP getline
+go:
+    JMP ($0024) ; XAML
+
+notstore:
+    BMI examinenext
+    LDA L
+    STA STL
+    STA XAML
+    LDA H
+    STA STH
+    STA XAMH
+    LDX #$00
+nextprint:
+    BNE printdata
+    LDA #$0D
+    JSR echo
+    LDA XAMH
+    JSR printbyte
+    LDA XAML
+    JSR printbyte
+    LDA #$3A ; ":"
+    JSR echo
+printdata:
+    LDA #$20
+    JSR echo
+    LDA (XAML,X)
+    JSR printbyte
+examinenext:
+    STX MODE
+    LDA XAML
+    CMP L
+    LDA XAMH
+    SBC H
+    BCS tonextitem
+    INC XAML
+    BNE mod8check
+    INC XAMH
+mod8check:
+    LDA XAML
+    AND #$07
+    BPL nextprint
+
+printbyte:
+    PHA
+    LSR
+    LSR
+    LSR
+    LSR
+    JSR printhex
+    PLA
+printhex:
+    AND #$0F
+    ORA #$30
+    CMP #$3A
+    BCC echo
+    ADC #$06
+
+; Sends A, CR as CR LF. Preserves A, X and Y.
+echo:
+    CMP #$0D
+    BNE putc
+    JSR putc
+    LDA #$0A
+    JSR putc
+    LDA #$0D
+    RTS
+putc:
+    PHA
+txwait:
+    LDA ACIA_STATUS
+    AND #$10 ; TDRE
+    BEQ txwait
+    PLA
+    STA ACIA_DATA
+    RTS
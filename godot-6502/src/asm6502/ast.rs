@@ -14,9 +14,11 @@ use thiserror::Error;
 
 use super::{
     context::Context,
+    diagnostic::Diagnostic,
     directive::{DirectiveEnum, DirectiveType, DirectiveValue, SYSTEM_DIRECTIVES},
-    opcode::{BRANCH_INSTS, INSTS_SIZE},
-    parser::{Parser, Token, TokenType},
+    expression::{BinaryOperator, Expression, ExpressionError, Folded, UnaryOperator},
+    opcode::{BRANCH_INSTS, INSTR_NAMES, INSTS_SIZE},
+    parser::{Operator, Parser, Token, TokenType},
     tool::print_error,
 };
 
@@ -26,6 +28,7 @@ pub enum InstrValue {
     Word(u16),
     Reference(String),
     LocalReference(String),
+    Expression(Expression), // still waiting for a label
 }
 
 #[derive(Debug, PartialEq)]
@@ -63,22 +66,35 @@ pub struct AstInfo {
     pub column: usize,
     pub ast: Ast,
     pub end: usize,
+    pub file_id: usize,
 }
 
 #[derive(Debug, Error)]
 pub enum AstGeneratorError {
     #[error("Syntax issue")]
     SyntaxIssue {
-        #[allow(dead_code)]
         line: usize,
-        #[allow(dead_code)]
         column: usize,
-        #[allow(dead_code)]
         end: usize,
-        #[allow(dead_code)]
         message: String,
     },
 
+    #[error("{error}")]
+    Expression {
+        line: usize,
+        column: usize,
+        end: usize,
+        error: ExpressionError,
+    },
+
+    #[error("Value {value} does not fit in a byte")]
+    ByteOutOfRange {
+        line: usize,
+        column: usize,
+        end: usize,
+        value: u16,
+    },
+
     #[error("Out of scope")]
     OutOfScope,
 
@@ -93,6 +109,18 @@ pub enum AstGeneratorError {
 }
 
 impl AstGeneratorError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AstGeneratorError::SyntaxIssue { .. } => "syntax",
+            AstGeneratorError::Expression { error, .. } => error.code(),
+            AstGeneratorError::ByteOutOfRange { .. } => "out-of-range",
+            AstGeneratorError::OutOfScope => "out-of-scope",
+            AstGeneratorError::InternalError => "internal",
+            AstGeneratorError::IOError(_) => "io",
+            AstGeneratorError::ReferenceAlreadyDefined(_) => "duplicate-reference",
+        }
+    }
+
     pub fn syntax_issue(context: &Context, token_index: usize, message: String) -> Self {
         let token_info = &context.tokens.borrow()[token_index];
         AstGeneratorError::SyntaxIssue {
@@ -102,6 +130,42 @@ impl AstGeneratorError {
             message,
         }
     }
+
+    pub fn expression(context: &Context, token_index: usize, error: ExpressionError) -> Self {
+        let token_info = &context.tokens.borrow()[token_index];
+        AstGeneratorError::Expression {
+            column: token_info.column,
+            end: token_info.end,
+            line: token_info.line,
+            error,
+        }
+    }
+
+    pub fn byte_out_of_range(context: &Context, token_index: usize, value: u16) -> Self {
+        let token_info = &context.tokens.borrow()[token_index];
+        AstGeneratorError::ByteOutOfRange {
+            column: token_info.column,
+            end: token_info.end,
+            line: token_info.line,
+            value,
+        }
+    }
+
+    // Where the error is, for the errors that know the token they are about
+    fn location(&self) -> Option<(usize, usize, usize)> {
+        match self {
+            AstGeneratorError::SyntaxIssue {
+                line, column, end, ..
+            }
+            | AstGeneratorError::Expression {
+                line, column, end, ..
+            }
+            | AstGeneratorError::ByteOutOfRange {
+                line, column, end, ..
+            } => Some((*line, *column, *end)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -109,6 +173,7 @@ pub struct AstGenerator {
     pub index: Cell<usize>,
     pub(crate) size: Cell<usize>,
     pub include_asm: RefCell<Option<DirectiveValue>>,
+    errors: RefCell<Vec<Diagnostic>>,
 }
 
 impl AstGenerator {
@@ -117,6 +182,7 @@ impl AstGenerator {
             index: Cell::new(0),
             size: Cell::new(0),
             include_asm: Default::default(),
+            errors: Default::default(),
         }
     }
 
@@ -176,6 +242,32 @@ impl AstGenerator {
         Ok(())
     }
 
+    fn immediate_follows(&self, context: &Context) -> bool {
+        let tokens = context.tokens.borrow();
+        let index = self.index.get();
+        matches!(
+            (
+                tokens.get(index).map(|info| &info.token),
+                tokens.get(index + 1).map(|info| &info.token)
+            ),
+            (Some(Token::Space(_)), Some(Token::Sharp))
+        )
+    }
+
+    fn operand_follows(&self, context: &Context) -> bool {
+        let tokens = context.tokens.borrow();
+        let index = self.index.get();
+        match (
+            tokens.get(index).map(|info| &info.token),
+            tokens.get(index + 1).map(|info| &info.token),
+        ) {
+            (Some(Token::Space(_)), Some(token)) => {
+                !matches!(token, Token::NewLine(_) | Token::Comment(_) | Token::End)
+            }
+            _ => false,
+        }
+    }
+
     fn eat_assign(&self, context: &Context) -> Result<(), AstGeneratorError> {
         let token_index = self.eat()?;
         let token = &context.tokens.borrow()[token_index];
@@ -208,91 +300,183 @@ impl AstGenerator {
         context: &Context,
         validator: impl Fn(DirectiveType) -> bool,
     ) -> Result<Vec<DirectiveValue>, AstGeneratorError> {
-        let tokens = context.tokens.borrow();
-
-        let mut token_found = false;
-        let mut finish = false;
-
         self.cleanup_space(context)?;
         let mut values = Vec::new();
 
         while self.size.get() > self.index.get() {
-            let value_index = self.eat()?;
-            let value_token = &tokens.get(value_index).map(|item| &item.token);
-
-            if token_found {
-                /* comma, space, new line, end or comment expected */
-                match value_token {
-                    Some(Token::NewLine(_)) => finish = true,
-                    Some(Token::Comment(_)) => finish = true,
-                    Some(Token::End) => finish = true,
-                    Some(Token::Space(_)) => (),
-                    Some(Token::Comma) => token_found = false,
-                    _ => {
-                        return Err(AstGeneratorError::syntax_issue(
-                            context,
-                            value_index,
-                            format!("Unexpected syntax ({:?})", value_token),
-                        ))
-                    }
+            /* Expected a string, an expression or the end of the list */
+            let value_index = self.peek()?;
+            let value_token = context.tokens.borrow()[value_index].token.clone();
+            match &value_token {
+                Token::NewLine(_) | Token::Comment(_) | Token::End => {
+                    self.eat()?;
+                    break;
+                }
+                Token::Comma => {
+                    return Err(AstGeneratorError::syntax_issue(
+                        context,
+                        value_index,
+                        "',' not expected".to_string(),
+                    ))
+                }
+                Token::String(string) => {
+                    self.eat()?;
+                    values.push(DirectiveValue::String(string.clone()));
+                }
+                _ => {
+                    let expression = self.parse_expression(context)?;
+                    values.push(match self.fold(context, value_index, expression)? {
+                        Folded::Byte(byte) => DirectiveValue::Byte(byte),
+                        Folded::Word(word) => DirectiveValue::Word(word),
+                        Folded::Expression(expression) => DirectiveValue::Expression(expression),
+                    });
                 }
-            } else {
-                /* Expected parseable token */
-                match value_token {
-                    Some(Token::Keyword(keyword)) => {
-                        values.push(DirectiveValue::Reference(keyword.clone()));
-                        token_found = true;
-                    }
-                    Some(Token::Word(number)) => {
-                        values.push(DirectiveValue::Word(*number));
-                        token_found = true;
-                    }
-                    Some(Token::Byte(number)) => {
-                        values.push(DirectiveValue::Byte(*number));
-                        token_found = true;
-                    }
-                    Some(Token::String(string)) => {
-                        values.push(DirectiveValue::String(string.clone()));
-                        token_found = true;
-                    }
-                    Some(Token::NewLine(_)) => finish = true,
-                    Some(Token::Comment(_)) => finish = true,
-                    Some(Token::End) => finish = true,
-                    Some(Token::Space(_)) => (),
-                    Some(Token::Comma) => {
-                        return Err(AstGeneratorError::syntax_issue(
-                            context,
-                            value_index,
-                            "',' not expected".to_string(),
-                        ))
-                    }
-                    Some(_) => {
-                        return Err(AstGeneratorError::syntax_issue(
-                            context,
-                            value_index,
-                            format!("Unexpected syntax ({:?})", value_token),
-                        ))
-                    }
-                    None => return Err(AstGeneratorError::InternalError),
-                };
             }
 
-            if token_found && !validator(DirectiveType::from(&values[values.len() - 1])) {
+            if !validator(DirectiveType::from(&values[values.len() - 1])) {
                 return Err(AstGeneratorError::syntax_issue(
                     context,
                     value_index,
-                    format!("3. Unexpected syntax ({:?})", value_token),
+                    format!("Unexpected syntax ({:?})", value_token),
                 ));
             }
 
-            if finish {
-                break;
+            /* comma, space, new line, end or comment expected */
+            self.cleanup_space(context)?;
+            let next_index = self.eat()?;
+            let next_token = context.tokens.borrow()[next_index].token.clone();
+            match next_token {
+                Token::Comma => self.cleanup_space(context)?,
+                Token::NewLine(_) | Token::Comment(_) | Token::End => break,
+                _ => {
+                    return Err(AstGeneratorError::syntax_issue(
+                        context,
+                        next_index,
+                        format!("Unexpected syntax ({:?})", next_token),
+                    ))
+                }
             }
         }
 
         Ok(values)
     }
 
+    // Index of the next token that is not a space
+    fn peek_value(&self, context: &Context) -> Option<usize> {
+        let tokens = context.tokens.borrow();
+        (self.index.get()..tokens.len())
+            .find(|index| !matches!(tokens[*index].token, Token::Space(_)))
+    }
+
+    // An expression from the next tokens, up to the first that cannot continue it
+    fn parse_expression(&self, context: &Context) -> Result<Expression, AstGeneratorError> {
+        self.parse_binary(context, 0)
+    }
+
+    // Operands joined by operators binding at least as tightly as `min_precedence`
+    fn parse_binary(
+        &self,
+        context: &Context,
+        min_precedence: u8,
+    ) -> Result<Expression, AstGeneratorError> {
+        let mut left = self.parse_unary(context)?;
+
+        while let Some(index) = self.peek_value(context) {
+            let operator = match &context.tokens.borrow()[index].token {
+                Token::Operator(operator) => match binary_operator(*operator) {
+                    Some(operator) if operator.precedence() >= min_precedence => operator,
+                    _ => break,
+                },
+                _ => break,
+            };
+            self.index.set(index + 1);
+            let right = self.parse_binary(context, operator.precedence() + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&self, context: &Context) -> Result<Expression, AstGeneratorError> {
+        let index = self
+            .peek_value(context)
+            .ok_or(AstGeneratorError::OutOfScope)?;
+        self.index.set(index + 1);
+        let token = context.tokens.borrow()[index].token.clone();
+
+        let unary = match token {
+            Token::Operator(Operator::Minus) => UnaryOperator::Negate,
+            Token::Operator(Operator::Less) => UnaryOperator::LowByte,
+            Token::Operator(Operator::Greater) => UnaryOperator::HighByte,
+            Token::Operator(Operator::Star) => return Ok(Expression::Pc),
+            Token::Byte(byte) => return Ok(Expression::Byte(byte)),
+            Token::Word(word) => return Ok(Expression::Word(word)),
+            Token::Keyword(name) => return Ok(Expression::Symbol(name)),
+            Token::OpenParenthesis => {
+                let expression = self.parse_binary(context, 0)?;
+                let close = self.peek_value(context);
+                let tokens = context.tokens.borrow();
+                return match close.map(|close| (close, &tokens[close].token)) {
+                    Some((close, Token::CloseParenthesis)) => {
+                        self.index.set(close + 1);
+                        Ok(expression)
+                    }
+                    _ => Err(AstGeneratorError::syntax_issue(
+                        context,
+                        index,
+                        "Expected ')'".to_string(),
+                    )),
+                };
+            }
+            _ => {
+                return Err(AstGeneratorError::syntax_issue(
+                    context,
+                    index,
+                    "Expected a value".to_string(),
+                ))
+            }
+        };
+        Ok(Expression::Unary(
+            unary,
+            Box::new(self.parse_unary(context)?),
+        ))
+    }
+
+    // Whether the parenthesis at `open` groups part of an expression rather than asking for
+    // indirect addressing, which it does when an operator follows its closing parenthesis
+    fn groups_expression(&self, context: &Context, open: usize) -> bool {
+        let tokens = context.tokens.borrow();
+        let mut depth = 0;
+        for (index, info) in tokens.iter().enumerate().skip(open) {
+            match info.token {
+                Token::OpenParenthesis => depth += 1,
+                Token::CloseParenthesis if depth == 1 => {
+                    return tokens
+                        .iter()
+                        .skip(index + 1)
+                        .find(|info| !matches!(info.token, Token::Space(_)))
+                        .is_some_and(|info| matches!(info.token, Token::Operator(_)));
+                }
+                Token::CloseParenthesis => depth -= 1,
+                Token::NewLine(_) | Token::End => return false,
+                _ => (),
+            }
+        }
+        false
+    }
+
+    // `expression` with the constants known so far put in, a number if that is all it needs
+    fn fold(
+        &self,
+        context: &Context,
+        token_index: usize,
+        expression: Expression,
+    ) -> Result<Folded, AstGeneratorError> {
+        let references = context.references.borrow();
+        expression
+            .fold(&references)
+            .map_err(|error| AstGeneratorError::expression(context, token_index, error))
+    }
+
     fn generate_directive(
         &self,
         context: &Context,
@@ -305,7 +489,7 @@ impl AstGenerator {
             .find(|item| item.name == &directive_name[..])
         {
             let values = self.parse_list(context, |directive_type| -> bool {
-                return directive_type == DirectiveType::Reference
+                return directive_type == DirectiveType::Expression
                     || directive.values.iter().any(|mode| *mode == directive_type);
             })?;
 
@@ -400,7 +584,14 @@ impl AstGenerator {
             let new_context = Context::default();
 
             let mut parser = Parser::new(context.last_file_id(), &code[..], new_context);
-            parser.parse().unwrap();
+            if let Err(errors) = parser.parse() {
+                // The included file's own context does not know its path
+                let file = path.display().to_string();
+                self.errors.borrow_mut().extend(errors.into_iter().map(|error| Diagnostic {
+                    file: file.clone(),
+                    ..error
+                }));
+            }
 
             let new_context = parser.context;
 
@@ -433,7 +624,7 @@ impl AstGenerator {
     fn generate_assign(
         &self,
         context: &Context,
-        _: usize,
+        token_index: usize,
         name: &String,
     ) -> Result<(), AstGeneratorError> {
         self.cleanup_space(context)?;
@@ -441,13 +632,27 @@ impl AstGenerator {
         self.cleanup_space(context)?;
 
         let values = self.parse_list(context, |_| true)?;
+
+        // A constant has no address of its own, and where it is used may be anywhere
+        let uses_pc = |value: &DirectiveValue| match value {
+            DirectiveValue::Expression(expression) => expression.contains_pc(),
+            _ => false,
+        };
+        if values.iter().any(uses_pc) {
+            return Err(AstGeneratorError::syntax_issue(
+                context,
+                token_index,
+                "'*' cannot be used in an assignment".to_string(),
+            ));
+        }
         let has_reference = context
             .references
             .borrow_mut()
             .insert(name.to_owned(), values)
             .is_some();
 
-        if has_reference {
+        // A name given by the caller, like a device register, gives way to the program's own
+        if has_reference && !context.predefined.borrow_mut().remove(name) {
             return Err(AstGeneratorError::ReferenceAlreadyDefined(name.to_owned()));
         }
         Ok(())
@@ -460,7 +665,7 @@ impl AstGenerator {
         self.cleanup_space(context)?;
         let tokens = context.tokens.borrow();
 
-        let token_index = self.eat()?;
+        let token_index = self.peek()?;
         let mut token = &tokens[token_index];
 
         let mut inst_info = InstrInfo {
@@ -472,69 +677,66 @@ impl AstGenerator {
 
         let mut parenthesis_open = false;
 
+        // `(` asks for indirect addressing, unless it only groups the start of an expression
         if let Token::OpenParenthesis = token.token {
-            inst_info.in_parenthesis = true;
-            parenthesis_open = true;
+            if !self.groups_expression(context, token_index) {
+                inst_info.in_parenthesis = true;
+                parenthesis_open = true;
 
-            self.cleanup_space(context)?;
-            let token_index = self.eat()?;
-            token = &tokens[token_index];
+                self.eat()?;
+                self.cleanup_space(context)?;
+            }
         }
 
-        if let Token::Sharp = &token.token {
+        let mut value_index = self.peek()?;
+        if let Token::Sharp = tokens[value_index].token {
             inst_info.is_immediate = true;
 
-            let token_index = self.eat()?;
-            token = &tokens[token_index];
+            self.eat()?;
+            value_index = self
+                .peek_value(context)
+                .ok_or(AstGeneratorError::OutOfScope)?;
         }
 
-        match &token.token {
-            Token::Keyword(keyword) => {
-                let references = context.references.borrow();
-                if let Some(values) = references.get(keyword) {
-                    if values.len() != 1 {
-                        return Err(AstGeneratorError::syntax_issue(
-                            context,
-                            token_index,
-                            "Only one token required".to_string(),
-                        ));
-                    }
-
-                    let first_value = &values[0];
-                    match first_value {
-                        DirectiveValue::Byte(byte) => inst_info.value = InstrValue::Byte(*byte),
-                        DirectiveValue::Word(word) => inst_info.value = InstrValue::Word(*word),
-                        _ => {
+        if let Token::LocalKeyword(keyword) = &tokens[value_index].token {
+            self.index.set(value_index + 1);
+            inst_info.value = InstrValue::LocalReference(keyword.to_owned());
+        } else {
+            inst_info.value = match self.parse_expression(context)? {
+                // A label on its own keeps to the jump tables
+                Expression::Symbol(keyword) => {
+                    match context.references.borrow().get(&keyword).map(Vec::as_slice) {
+                        None => InstrValue::Reference(keyword),
+                        Some([DirectiveValue::String(_)]) => {
                             return Err(AstGeneratorError::syntax_issue(
                                 context,
-                                token_index,
+                                value_index,
                                 "Invalid token for number".to_string(),
                             ))
                         }
-                    };
-                } else {
-                    inst_info.value = InstrValue::Reference(keyword.to_owned());
+                        Some([_]) => self.fold_instr_value(
+                            context,
+                            value_index,
+                            Expression::Symbol(keyword),
+                        )?,
+                        Some(_) => {
+                            return Err(AstGeneratorError::syntax_issue(
+                                context,
+                                value_index,
+                                "Only one token required".to_string(),
+                            ))
+                        }
+                    }
                 }
-            }
-            Token::LocalKeyword(keyword) => {
-                inst_info.value = InstrValue::LocalReference(keyword.to_owned())
-            }
-            Token::Byte(byte) => inst_info.value = InstrValue::Byte(*byte),
-            Token::Word(word) => inst_info.value = InstrValue::Word(*word),
-            _ => {
-                return Err(AstGeneratorError::syntax_issue(
-                    context,
-                    token_index,
-                    "Invalid numbering number format".to_string(),
-                ))
-            }
-        };
+                expression => self.fold_instr_value(context, value_index, expression)?,
+            };
+        }
 
         self.cleanup_space(context)?;
 
         if let Ok(token_index) = self.peek() {
             token = &tokens[token_index];
-            if let Token::CloseParenthesis = token.token {
+            if parenthesis_open && matches!(token.token, Token::CloseParenthesis) {
                 let _ = self.eat()?;
                 parenthesis_open = false;
                 self.cleanup_space(context)?;
@@ -597,34 +799,71 @@ impl AstGenerator {
             )?;
         }
 
-        if inst_info.is_immediate
-            && !inst_info.in_parenthesis
-            && inst_info.register == InstrInfoRegister::None
-        {
-            if let InstrValue::Word(word) = inst_info.value {
-                inst_info.value = InstrValue::Byte(word as u8);
+        // Nothing may follow the operand but a comment
+        if let Some(index) = self.peek_value(context) {
+            match &tokens[index].token {
+                Token::NewLine(_) | Token::Comment(_) | Token::End => (),
+                token => {
+                    return Err(AstGeneratorError::syntax_issue(
+                        context,
+                        index,
+                        format!("Unexpected syntax ({:?})", token),
+                    ))
+                }
             }
         }
 
-        if !inst_info.is_immediate
-            && inst_info.in_parenthesis
-            && inst_info.register != InstrInfoRegister::None
-        {
-            if let InstrValue::Word(word) = inst_info.value {
-                inst_info.value = InstrValue::Byte(word as u8);
+        let byte_slot = (inst_info.is_immediate
+            && !inst_info.in_parenthesis
+            && inst_info.register == InstrInfoRegister::None)
+            || (!inst_info.is_immediate
+                && inst_info.in_parenthesis
+                && inst_info.register != InstrInfoRegister::None);
+
+        if byte_slot {
+            match &inst_info.value {
+                InstrValue::Word(word) if *word <= 0xFF => {
+                    inst_info.value = InstrValue::Byte(*word as u8)
+                }
+                InstrValue::Word(word) => {
+                    return Err(AstGeneratorError::byte_out_of_range(
+                        context,
+                        value_index,
+                        *word,
+                    ))
+                }
+                _ => (),
             }
         }
 
         Ok(inst_info)
     }
 
+    fn fold_instr_value(
+        &self,
+        context: &Context,
+        token_index: usize,
+        expression: Expression,
+    ) -> Result<InstrValue, AstGeneratorError> {
+        Ok(match self.fold(context, token_index, expression)? {
+            Folded::Byte(byte) => InstrValue::Byte(byte),
+            Folded::Word(word) => InstrValue::Word(word),
+            Folded::Expression(expression) => InstrValue::Expression(expression),
+        })
+    }
+
     fn generate_code_block(
         &self,
         context: &Context,
         token_index: usize,
         positon: usize,
     ) -> Result<(), AstGeneratorError> {
-        if INSTS_SIZE[positon] == 1 {
+        // BRK may carry a signature byte, `BRK #n`, which syscalls use
+        let signature = INSTR_NAMES[positon] == "BRK" && self.immediate_follows(context);
+        // ASL, LSR, ROL and ROR shift memory when given an operand, the accumulator without one
+        let shifts_memory = matches!(INSTR_NAMES[positon], "ASL" | "LSR" | "ROL" | "ROR")
+            && self.operand_follows(context);
+        if INSTS_SIZE[positon] == 1 && !signature && !shifts_memory {
             context.add_ast(token_index, Ast::InstrImplied(positon));
         } else if BRANCH_INSTS.contains(&positon) {
             // Branch inst
@@ -639,6 +878,9 @@ impl AstGenerator {
                 InstrValue::LocalReference(_) => {
                     context.add_ast(token_index, Ast::Instr(positon, value))
                 }
+                InstrValue::Expression(_) => {
+                    context.add_ast(token_index, Ast::Instr(positon, value))
+                }
                 _ => {
                     return Err(AstGeneratorError::syntax_issue(
                         context,
@@ -730,6 +972,13 @@ impl AstGenerator {
                             "',' not expected".to_string(),
                         ))
                     }
+                    Some(Token::Operator(operator)) => {
+                        return Err(AstGeneratorError::syntax_issue(
+                            context,
+                            token_index,
+                            format!("'{}' not expected", operator.as_str()),
+                        ))
+                    }
                     Some(Token::String(_)) => {
                         return Err(AstGeneratorError::syntax_issue(
                             context,
@@ -758,19 +1007,78 @@ impl AstGenerator {
         Ok(())
     }
 
-    pub fn generate(&self, context: Context) -> Result<Context, AstGeneratorError> {
-        match self.inline_generate(&context) {
-            Ok(_) => Ok(context),
-            Err(error) => {
-                let tokens = context.tokens.borrow();
-                let token = &tokens[self.index.get() - 1];
+    // Resumes on the line after each error, returning every error found
+    pub fn generate(&self, context: Context) -> Result<Context, Vec<Diagnostic>> {
+        while let Err(error) = self.inline_generate(&context) {
+            let diagnostic = self.diagnostic(&context, &error);
+            self.errors.borrow_mut().push(diagnostic);
+            self.include_asm.replace(None);
+            self.skip_line(&context);
+        }
+
+        match self.errors.take() {
+            errors if errors.is_empty() => Ok(context),
+            errors => Err(errors),
+        }
+    }
 
-                if !context.silent {
-                    let code_file = &context.code_files.borrow()[token.file_id];
-                    print_error(&code_file.data, &error, token.line, token.column, token.end);
+    fn diagnostic(&self, context: &Context, error: &AstGeneratorError) -> Diagnostic {
+        let token_index = self.index.get().saturating_sub(1);
+        let tokens = context.tokens.borrow();
+        let token = &tokens[token_index];
+
+        if !context.silent {
+            let code_file = &context.code_files.borrow()[token.file_id];
+            print_error(&code_file.data, error, token.line, token.column, token.end);
+        }
+
+        // Some errors know the token they are about, which may not be the last eaten
+        let message = match error {
+            AstGeneratorError::SyntaxIssue { message, .. } => message.clone(),
+            _ => error.to_string(),
+        };
+        match error.location() {
+            Some(location) => {
+                Diagnostic::error(context, token.file_id, location, error.code(), message)
+            }
+            None => Diagnostic::at_token(context, token_index, error.code(), message),
+        }
+    }
+
+    // Move past the line break ending the statement that failed, unless it was the last token
+    // eaten. The end token is left for inline_generate to stop at.
+    fn skip_line(&self, context: &Context) {
+        let tokens = context.tokens.borrow();
+        let last = self.index.get().checked_sub(1).map(|index| &tokens[index].token);
+        if let Some(Token::NewLine(_)) = last {
+            return;
+        }
+        while let Some(token) = tokens.get(self.index.get()) {
+            match token.token {
+                Token::End => break,
+                Token::NewLine(_) => {
+                    self.index.set(self.index.get() + 1);
+                    break;
                 }
-                Err(error)
+                _ => self.index.set(self.index.get() + 1),
             }
         }
     }
 }
+
+// The operator a token stands for between two values, `<` and `>` only come before one
+fn binary_operator(operator: Operator) -> Option<BinaryOperator> {
+    Some(match operator {
+        Operator::Plus => BinaryOperator::Add,
+        Operator::Minus => BinaryOperator::Subtract,
+        Operator::Star => BinaryOperator::Multiply,
+        Operator::Slash => BinaryOperator::Divide,
+        Operator::Percent => BinaryOperator::Remainder,
+        Operator::Ampersand => BinaryOperator::And,
+        Operator::Pipe => BinaryOperator::Or,
+        Operator::Caret => BinaryOperator::Xor,
+        Operator::ShiftLeft => BinaryOperator::ShiftLeft,
+        Operator::ShiftRight => BinaryOperator::ShiftRight,
+        Operator::Less | Operator::Greater => return None,
+    })
+}
@@ -11,6 +11,8 @@ use thiserror::Error;
 
 use super::ast::{InstrInfo, InstrInfoRegister, InstrValue};
 use super::context::Context;
+use super::diagnostic::Diagnostic;
+use super::expression::{Expression, ExpressionError, MAX_DEPTH, WORD_RANGE};
 use super::opcode::BRANCH_INSTS;
 use super::tool::print_error;
 use super::{
@@ -29,8 +31,8 @@ pub enum CodeGeneratorError {
     IllegalOpcode,
     #[error("Number not applicable")]
     NumberNotApplicable,
-    #[error("Branch information not found")]
-    UnresolvedBranches,
+    #[error("Label '{0}' not found")]
+    UnresolvedLabel(String),
     #[error("Reference information not found")]
     UnresolvedReference,
     #[error("Expected &String")]
@@ -43,6 +45,66 @@ pub enum CodeGeneratorError {
     ExpectedThis(&'static str),
     #[error("{0}")]
     ProgramFailed(String),
+    #[error("{0}")]
+    Expression(ExpressionError),
+    #[error("Value {0} does not fit in a byte")]
+    ByteOutOfRange(i64),
+    #[error("Two-byte value where one byte goes, take its low or high byte with < or >")]
+    WordNotByte,
+    #[error("Branch of {0} bytes is out of range, branches reach -128 to 127 bytes")]
+    BranchOutOfRange(i64),
+}
+
+impl From<ExpressionError> for CodeGeneratorError {
+    fn from(error: ExpressionError) -> Self {
+        match error {
+            ExpressionError::Unresolved(name) => CodeGeneratorError::UnresolvedLabel(name),
+            error => CodeGeneratorError::Expression(error),
+        }
+    }
+}
+
+impl CodeGeneratorError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodeGeneratorError::UnsupportedDirectiveValue => "unsupported-value",
+            CodeGeneratorError::InternalError => "internal",
+            CodeGeneratorError::IllegalOpcode => "illegal-opcode",
+            CodeGeneratorError::NumberNotApplicable => "number-not-applicable",
+            CodeGeneratorError::UnresolvedLabel(_) => "unresolved-label",
+            CodeGeneratorError::UnresolvedReference => "unresolved-reference",
+            CodeGeneratorError::StringExpected => "string-expected",
+            CodeGeneratorError::IOError(_) => "io",
+            CodeGeneratorError::Utf8Error(_) => "invalid-text",
+            CodeGeneratorError::ExpectedThis(_) => "unexpected-value",
+            CodeGeneratorError::ProgramFailed(_) => "fail",
+            CodeGeneratorError::Expression(error) => error.code(),
+            CodeGeneratorError::ByteOutOfRange(_)
+            | CodeGeneratorError::WordNotByte
+            | CodeGeneratorError::BranchOutOfRange(_) => "out-of-range",
+        }
+    }
+}
+
+// Where the value of an expression goes, once the labels it needs are known
+#[derive(Debug)]
+pub struct UnresolvedExpression {
+    pub expression: Expression,
+    pub position: usize,
+    pub size: usize,
+    pub pc: u16, // address of the statement, for `*` and relative branches
+    pub relative: bool,
+    pub ast_index: usize,
+}
+
+// A branch or jump to a label further down
+#[derive(Debug)]
+pub struct UnresolvedBranch {
+    pub name: String,
+    pub position: usize,
+    pub next_instruction: usize, // address the branch offset counts from
+    pub relative: bool,
+    pub ast_index: usize,
 }
 
 #[derive(Debug)]
@@ -55,9 +117,10 @@ pub struct CodeGenerator {
     pub fillvalue: u8,
     pub branches: HashMap<String, usize>,
     pub local_branches: HashMap<String, usize>,
-    pub unresolved_relative_jump: Vec<(String, usize, usize)>,
-    pub unresolved_absolute_jumps: Vec<(String, usize, usize)>,
-    pub unresolved_local_branches: Vec<(String, usize, usize)>,
+    pub unresolved_relative_jump: Vec<UnresolvedBranch>,
+    pub unresolved_local_branches: Vec<UnresolvedBranch>,
+    pub unresolved_expressions: Vec<UnresolvedExpression>,
+    pub warnings: Vec<(usize, String)>, // .warning messages with their ast index
 }
 
 impl CodeGenerator {
@@ -72,7 +135,8 @@ impl CodeGenerator {
             local_branches: Default::default(),
             unresolved_local_branches: Default::default(),
             unresolved_relative_jump: Default::default(),
-            unresolved_absolute_jumps: Default::default(),
+            unresolved_expressions: Default::default(),
+            warnings: Default::default(),
         }
     }
 
@@ -116,36 +180,61 @@ impl CodeGenerator {
         Ok(())
     }
 
+    // The signed byte a branch at `next_instruction` needs to reach `address`
+    fn branch_offset(address: usize, next_instruction: usize) -> Result<u8, CodeGeneratorError> {
+        let offset = address as i64 - next_instruction as i64;
+        match (-128..=127).contains(&offset) {
+            true => Ok(offset as u8),
+            false => Err(CodeGeneratorError::BranchOutOfRange(offset)),
+        }
+    }
+
     fn build_relative_jump(
+        &mut self,
         target: &mut [u8],
         ast_index: usize,
         reference: &String,
-        branches: &mut HashMap<String, usize>,
-        unresolved_jump: &mut Vec<(String, usize, usize)>,
-    ) -> (u16, ModeType) {
+        local: bool,
+    ) -> Result<(u16, ModeType), CodeGeneratorError> {
+        let next_instruction = self.start_point as usize + target.len() + 2;
+        let (branches, unresolved_jump) = match local {
+            true => (&self.local_branches, &mut self.unresolved_local_branches),
+            false => (&self.branches, &mut self.unresolved_relative_jump),
+        };
         match branches.get(reference) {
-            Some(branch_position) => (
-                (*branch_position as i16 - (target.len() + 2) as i16) as u16,
+            Some(branch_position) => Ok((
+                Self::branch_offset(*branch_position, next_instruction)? as u16,
                 ModeType::Relative,
-            ),
+            )),
             None => {
-                unresolved_jump.push((reference.clone(), target.len() + 1, ast_index));
-                (0, ModeType::Relative)
+                unresolved_jump.push(UnresolvedBranch {
+                    name: reference.clone(),
+                    position: target.len() + 1,
+                    next_instruction,
+                    relative: true,
+                    ast_index,
+                });
+                Ok((0, ModeType::Relative))
             }
         }
     }
 
     fn build_absolute_jump(
+        &mut self,
         target: &mut [u8],
         ast_index: usize,
         reference: &String,
-        branches: &mut HashMap<String, usize>,
-        unresolved_jump: &mut Vec<(String, usize, usize)>,
     ) -> (u16, ModeType) {
-        match branches.get(reference) {
+        match self.local_branches.get(reference) {
             Some(branch_position) => (*branch_position as u16, ModeType::Absolute),
             None => {
-                unresolved_jump.push((reference.clone(), target.len() + 1, ast_index));
+                self.unresolved_local_branches.push(UnresolvedBranch {
+                    name: reference.clone(),
+                    position: target.len() + 1,
+                    next_instruction: self.start_point as usize + target.len() + 3,
+                    relative: false,
+                    ast_index,
+                });
                 (0, ModeType::Absolute)
             }
         }
@@ -154,6 +243,7 @@ impl CodeGenerator {
     fn generate_instr(
         &mut self,
         target: &mut Vec<u8>,
+        references: &HashMap<String, Vec<DirectiveValue>>,
         ast_index: usize,
         instr: usize,
         value: &InstrInfo,
@@ -161,42 +251,37 @@ impl CodeGenerator {
         let modes = MODES[instr];
         let mut found = false;
         let relative_jump = BRANCH_INSTS.contains(&instr);
+        let byte_slot = !relative_jump
+            && (value.is_immediate
+                || (value.in_parenthesis && value.register != InstrInfoRegister::None));
+
+        // Labels and constants defined further down are written like expressions, once known
+        let expression = match &value.value {
+            InstrValue::Expression(expression) => {
+                Some(expression.clone().with_constants(references))
+            }
+            InstrValue::Reference(name) if !relative_jump => {
+                Some(Self::reference_expression(references, name, byte_slot))
+            }
+            _ => None,
+        };
 
-        let (number, mut possible_mode) = match &value.value {
-            InstrValue::Byte(byte) => (*byte as u16, ModeType::ZeroPage),
-            InstrValue::Word(word) => (*word, ModeType::Absolute),
-            InstrValue::Reference(reference) => match relative_jump {
-                true => Self::build_relative_jump(
-                    target,
-                    ast_index,
-                    reference,
-                    &mut self.branches,
-                    &mut self.unresolved_relative_jump,
-                ),
-                false => Self::build_absolute_jump(
-                    target,
-                    ast_index,
-                    reference,
-                    &mut self.branches,
-                    &mut self.unresolved_absolute_jumps,
-                ),
-            },
-            InstrValue::LocalReference(reference) => match relative_jump {
-                true => Self::build_relative_jump(
-                    target,
-                    ast_index,
-                    reference,
-                    &mut self.local_branches,
-                    &mut self.unresolved_local_branches,
-                ),
-                false => Self::build_absolute_jump(
-                    target,
-                    ast_index,
-                    reference,
-                    &mut self.local_branches,
-                    &mut self.unresolved_local_branches,
-                ),
+        let (number, mut possible_mode) = match (&value.value, &expression) {
+            (_, Some(expression)) if byte_slot && expression.is_word() => {
+                return Err(CodeGeneratorError::WordNotByte)
+            }
+            (_, Some(expression)) if expression.is_word() => (0, ModeType::Absolute),
+            (_, Some(_)) => (0, ModeType::ZeroPage),
+            (InstrValue::Byte(byte), _) => (*byte as u16, ModeType::ZeroPage),
+            (InstrValue::Word(word), _) => (*word, ModeType::Absolute),
+            (InstrValue::Reference(reference), _) => {
+                self.build_relative_jump(target, ast_index, reference, false)?
+            }
+            (InstrValue::LocalReference(reference), _) => match relative_jump {
+                true => self.build_relative_jump(target, ast_index, reference, true)?,
+                false => self.build_absolute_jump(target, ast_index, reference),
             },
+            (InstrValue::Expression(_), None) => return Err(CodeGeneratorError::InternalError),
         };
 
         if value.in_parenthesis {
@@ -227,6 +312,8 @@ impl CodeGenerator {
             possible_mode = ModeType::Relative;
         }
 
+        let pc = self.start_point.wrapping_add(target.len() as u16);
+        let position = target.len() + 1; // of the operand, after the opcode
         for search_mode in modes.iter() {
             if search_mode.mode == possible_mode {
                 target.push(search_mode.opcode);
@@ -239,9 +326,140 @@ impl CodeGenerator {
         if !found {
             return Err(CodeGeneratorError::IllegalOpcode);
         }
+
+        if let Some(expression) = expression {
+            let unresolved = UnresolvedExpression {
+                expression,
+                position,
+                size: target.len() - position,
+                pc,
+                relative: relative_jump,
+                ast_index,
+            };
+            self.place(target, references, unresolved)?;
+        }
+        Ok(())
+    }
+
+    // The operand for the label or constant `name`, sized as if a constant came before its use
+    fn reference_expression(
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        name: &str,
+        byte_slot: bool,
+    ) -> Expression {
+        match Expression::Symbol(name.to_owned()).with_constants(references) {
+            Expression::Word(word) if byte_slot && word <= 0xFF => Expression::Byte(word as u8),
+            expression => expression,
+        }
+    }
+
+    // The value of the label or constant `name`, constants may be defined in terms of others
+    pub fn symbol(
+        &self,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        name: &str,
+        depth: usize,
+    ) -> Result<i64, ExpressionError> {
+        if depth > MAX_DEPTH {
+            return Err(ExpressionError::Circular(name.to_owned()));
+        }
+        match references.get(name).map(Vec::as_slice) {
+            Some([DirectiveValue::Byte(byte)]) => Ok(*byte as i64),
+            Some([DirectiveValue::Word(word)]) => Ok(*word as i64),
+            Some([DirectiveValue::Expression(expression)]) => {
+                expression.evaluate(None, &|name| self.symbol(references, name, depth + 1))
+            }
+            _ => match self.branches.get(name) {
+                Some(address) => Ok(*address as i64),
+                None => Err(ExpressionError::Unresolved(name.to_owned())),
+            },
+        }
+    }
+
+    fn evaluate(
+        &self,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        expression: &Expression,
+        pc: u16,
+    ) -> Result<i64, CodeGeneratorError> {
+        Ok(expression.evaluate(Some(pc), &|name| self.symbol(references, name, 0))?)
+    }
+
+    // Writes the value of the expression where it goes
+    fn write_expression(
+        &self,
+        target: &mut [u8],
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        unresolved: &UnresolvedExpression,
+    ) -> Result<(), CodeGeneratorError> {
+        let mut value = self.evaluate(references, &unresolved.expression, unresolved.pc)?;
+        if unresolved.relative {
+            value -= unresolved.pc as i64 + 2;
+        }
+
+        let position = unresolved.position;
+        match unresolved.size {
+            1 if unresolved.relative && !(-128..=127).contains(&value) => {
+                return Err(CodeGeneratorError::ByteOutOfRange(value))
+            }
+            1 if !(-128..=255).contains(&value) => {
+                return Err(CodeGeneratorError::ByteOutOfRange(value))
+            }
+            1 => target[position] = value as u8,
+            _ if !WORD_RANGE.contains(&value) => {
+                return Err(ExpressionError::OutOfRange(value).into())
+            }
+            _ => {
+                target[position] = value as u8;
+                target[position + 1] = (value >> 8) as u8;
+            }
+        };
         Ok(())
     }
 
+    // Writes the value of the expression now, or once every label is known if it needs one
+    // further down
+    fn place(
+        &mut self,
+        target: &mut [u8],
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        unresolved: UnresolvedExpression,
+    ) -> Result<(), CodeGeneratorError> {
+        match self.write_expression(target, references, &unresolved) {
+            Err(CodeGeneratorError::UnresolvedLabel(_)) => {
+                self.unresolved_expressions.push(unresolved);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    // Values for directives that cannot wait for the labels further down
+    fn resolve_values(
+        &self,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        values: &[DirectiveValue],
+        pc: u16,
+    ) -> Result<Vec<DirectiveValue>, CodeGeneratorError> {
+        values
+            .iter()
+            .map(|value| match value {
+                DirectiveValue::Expression(expression) => {
+                    let number = self.evaluate(references, expression, pc)?;
+                    let fits = !expression.is_word() && (-128..=255).contains(&number);
+                    match fits {
+                        true => Ok(DirectiveValue::Byte(number as u8)),
+                        false if WORD_RANGE.contains(&number) => {
+                            Ok(DirectiveValue::Word(number as u16))
+                        }
+                        false => Err(ExpressionError::OutOfRange(number).into()),
+                    }
+                }
+                value => Ok(value.clone()),
+            })
+            .collect()
+    }
+
     fn generate_implied(
         &mut self,
         target: &mut Vec<u8>,
@@ -249,7 +467,8 @@ impl CodeGenerator {
     ) -> Result<(), CodeGeneratorError> {
         let modes = MODES[position];
         for search_mode in modes.iter() {
-            if search_mode.mode == ModeType::Implied {
+            // ASL, LSR, ROL and ROR without an operand shift the accumulator
+            if search_mode.mode == ModeType::Implied || search_mode.mode == ModeType::Accumulator {
                 target.push(search_mode.opcode);
                 break;
             }
@@ -270,52 +489,55 @@ impl CodeGenerator {
                 self.local_branches.clear();
             }
             BranchType::Local => {
-                self.local_branches.insert(name.to_owned(), target.len());
+                self.local_branches
+                    .insert(name.to_owned(), absolute_address);
                 self.build_unresolved_local_branches(target)?;
             }
         };
         Ok(())
     }
 
+    // Returns the ast index of each branch to a missing label or out of reach, and why
     fn build_unresolved_relative_jump(
         &mut self,
         target: &mut [u8],
-    ) -> Result<(), CodeGeneratorError> {
-        for (branch_name, position, _) in self.unresolved_relative_jump.iter() {
-            match self.branches.get(branch_name) {
+    ) -> Vec<(usize, CodeGeneratorError)> {
+        let mut errors = Vec::new();
+        for branch in self.unresolved_relative_jump.iter() {
+            match self.branches.get(&branch.name) {
                 Some(branch_position) => {
-                    target[*position] = (*branch_position as i8 - *position as i8 - 1) as u8
+                    match Self::branch_offset(*branch_position, branch.next_instruction) {
+                        Ok(offset) => target[branch.position] = offset,
+                        Err(error) => errors.push((branch.ast_index, error)),
+                    }
                 }
-                None => return Err(CodeGeneratorError::UnresolvedBranches),
+                None => errors.push((
+                    branch.ast_index,
+                    CodeGeneratorError::UnresolvedLabel(branch.name.clone()),
+                )),
             };
         }
 
-        Ok(())
+        errors
     }
 
+    // Patches the branches waiting for a local label just defined, the others keep waiting
     fn build_unresolved_local_branches(
         &mut self,
         target: &mut [u8],
     ) -> Result<(), CodeGeneratorError> {
-        for (branch_name, position, _) in self.unresolved_local_branches.iter() {
-            if let Some(branch_position) = self.local_branches.get(branch_name) {
-                target[*position] = (*branch_position as i8 - *position as i8 - 1) as u8;
-            };
-        }
-
-        Ok(())
-    }
-
-    fn build_unresolved_jumps(&mut self, target: &mut [u8]) -> Result<(), CodeGeneratorError> {
-        for (branch_name, position, _) in self.unresolved_absolute_jumps.iter() {
-            match self.branches.get(branch_name) {
+        let unresolved = std::mem::take(&mut self.unresolved_local_branches);
+        for branch in unresolved {
+            match self.local_branches.get(&branch.name) {
+                Some(branch_position) if branch.relative => {
+                    target[branch.position] =
+                        Self::branch_offset(*branch_position, branch.next_instruction)?
+                }
                 Some(branch_position) => {
-                    let jump_position = *branch_position as u16;
-
-                    target[*position] = jump_position as u8;
-                    target[*position + 1] = (jump_position >> 8) as u8;
+                    target[branch.position] = *branch_position as u8;
+                    target[branch.position + 1] = (*branch_position >> 8) as u8;
                 }
-                None => return Err(CodeGeneratorError::UnresolvedBranches),
+                None => self.unresolved_local_branches.push(branch),
             };
         }
 
@@ -352,14 +574,34 @@ impl CodeGenerator {
     fn directive_byte(
         &mut self,
         target: &mut Vec<u8>,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        ast_index: usize,
         values: &[DirectiveValue],
     ) -> Result<(), CodeGeneratorError> {
+        let pc = self.start_point.wrapping_add(target.len() as u16);
         for value in values.iter() {
             match value {
                 DirectiveValue::Byte(byte) => target.push(*byte),
                 DirectiveValue::String(string) => {
                     string.as_bytes().iter().for_each(|byte| target.push(*byte))
                 }
+                DirectiveValue::Expression(expression) => {
+                    // One byte each, an address needs < or >
+                    let expression = expression.clone().with_constants(references);
+                    if expression.is_word() {
+                        return Err(CodeGeneratorError::WordNotByte);
+                    }
+                    target.push(0x00);
+                    let unresolved = UnresolvedExpression {
+                        expression,
+                        position: target.len() - 1,
+                        size: 1,
+                        pc,
+                        relative: false,
+                        ast_index,
+                    };
+                    self.place(target, references, unresolved)?;
+                }
                 _ => return Err(CodeGeneratorError::ExpectedThis("byte or &String")),
             };
         }
@@ -369,8 +611,11 @@ impl CodeGenerator {
     fn directive_word(
         &mut self,
         target: &mut Vec<u8>,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        ast_index: usize,
         values: &[DirectiveValue],
     ) -> Result<(), CodeGeneratorError> {
+        let pc = self.start_point.wrapping_add(target.len() as u16);
         for value in values.iter() {
             match value {
                 DirectiveValue::Byte(word) => {
@@ -381,6 +626,18 @@ impl CodeGenerator {
                     target.push(*word as u8);
                     target.push((*word >> 8) as u8);
                 }
+                DirectiveValue::Expression(expression) => {
+                    target.extend([0x00, 0x00]);
+                    let unresolved = UnresolvedExpression {
+                        expression: expression.clone(),
+                        position: target.len() - 2,
+                        size: 2,
+                        pc,
+                        relative: false,
+                        ast_index,
+                    };
+                    self.place(target, references, unresolved)?;
+                }
                 _ => return Err(CodeGeneratorError::ExpectedThis("word")),
             }
         }
@@ -424,6 +681,7 @@ impl CodeGenerator {
         if !self.silent {
             warn!("{}", message);
         }
+        self.warnings.push((self.index - 1, message));
         Ok(())
     }
 
@@ -517,14 +775,27 @@ impl CodeGenerator {
     fn generate_directive(
         &mut self,
         target: &mut Vec<u8>,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+        ast_index: usize,
         option: DirectiveEnum,
         values: &[DirectiveValue],
     ) -> Result<(), CodeGeneratorError> {
+        // .byte and .word may wait for labels further down, the others need their values now
+        let resolved;
+        let values = match option {
+            DirectiveEnum::Byte | DirectiveEnum::Word => values,
+            _ => {
+                let pc = self.start_point.wrapping_add(target.len() as u16);
+                resolved = self.resolve_values(references, values, pc)?;
+                &resolved[..]
+            }
+        };
+
         match option {
             DirectiveEnum::Org => self.directive_org(values)?,
             DirectiveEnum::Incbin => self.directive_incbin(target, values)?,
-            DirectiveEnum::Byte => self.directive_byte(target, values)?,
-            DirectiveEnum::Word => self.directive_word(target, values)?,
+            DirectiveEnum::Byte => self.directive_byte(target, references, ast_index, values)?,
+            DirectiveEnum::Word => self.directive_word(target, references, ast_index, values)?,
             DirectiveEnum::Ascii => self.directive_ascii(target, values, false)?,
             DirectiveEnum::Asciiz => self.directive_ascii(target, values, true)?,
             DirectiveEnum::Warning => self.directive_warning(values)?,
@@ -541,6 +812,7 @@ impl CodeGenerator {
     fn inner_generate(&mut self, context: &mut Context) -> Result<(), CodeGeneratorError> {
         self.size = context.asts.borrow().len();
         let asts = context.asts.borrow();
+        let references = context.references.borrow();
 
         while self.size > self.index {
             let ast_index = self.eat()?;
@@ -551,15 +823,23 @@ impl CodeGenerator {
                 Some(Ast::InstrImplied(position)) => {
                     self.generate_implied(&mut context.target, *position)?
                 }
-                Some(Ast::Instr(position, value)) => {
-                    self.generate_instr(&mut context.target, ast_index, *position, value)?
-                }
+                Some(Ast::Instr(position, value)) => self.generate_instr(
+                    &mut context.target,
+                    &references,
+                    ast_index,
+                    *position,
+                    value,
+                )?,
                 Some(Ast::Branch(name, branch_type)) => {
                     self.generate_branch(&mut context.target, name, *branch_type)?
                 }
-                Some(Ast::Directive(option, values)) => {
-                    self.generate_directive(&mut context.target, *option, values)?
-                }
+                Some(Ast::Directive(option, values)) => self.generate_directive(
+                    &mut context.target,
+                    &references,
+                    ast_index,
+                    *option,
+                    values,
+                )?,
                 None => return Err(CodeGeneratorError::InternalError),
             };
 
@@ -575,26 +855,68 @@ impl CodeGenerator {
             }
         }
 
-        self.build_unresolved_relative_jump(&mut context.target)?;
-        self.build_unresolved_jumps(&mut context.target)?;
         Ok(())
     }
 
-    pub fn generate(&mut self, context: Context) -> Result<Context, CodeGeneratorError> {
+    // Stops at the first error, except for missing labels, which are all reported
+    pub fn generate(&mut self, context: Context) -> Result<Context, Vec<Diagnostic>> {
         let mut context = context;
 
-        match self.inner_generate(&mut context) {
-            Ok(_) => Ok(context),
-            Err(error) => {
-                let asts = context.asts.borrow();
-                let ast = &asts[self.index - 1];
-                if !context.silent {
-                    let code_file = &context.code_files.borrow()[0];
-                    print_error(&code_file.data, &error, ast.line, ast.column, ast.end);
-                }
-                Err(error)
-            }
+        if let Err(error) = self.inner_generate(&mut context) {
+            let ast_index = self.index.saturating_sub(1);
+            return Err(vec![Self::diagnostic(&context, ast_index, error)]);
+        }
+
+        let references = context.references.borrow();
+        let mut errors = self.build_unresolved_relative_jump(&mut context.target);
+        errors.extend(self.build_unresolved_expressions(&mut context.target, &references));
+        errors.sort_by_key(|(ast_index, _)| *ast_index);
+        drop(references);
+
+        match errors.is_empty() {
+            true => Ok(context),
+            false => Err(errors
+                .into_iter()
+                .map(|(ast_index, error)| Self::diagnostic(&context, ast_index, error))
+                .collect()),
+        }
+    }
+
+    // Writes the expressions that waited for labels further down, returns why the others fail
+    fn build_unresolved_expressions(
+        &mut self,
+        target: &mut [u8],
+        references: &HashMap<String, Vec<DirectiveValue>>,
+    ) -> Vec<(usize, CodeGeneratorError)> {
+        let unresolved = std::mem::take(&mut self.unresolved_expressions);
+        unresolved
+            .iter()
+            .filter_map(|unresolved| {
+                self.write_expression(target, references, unresolved)
+                    .err()
+                    .map(|error| (unresolved.ast_index, error))
+            })
+            .collect()
+    }
+
+    fn diagnostic(context: &Context, ast_index: usize, error: CodeGeneratorError) -> Diagnostic {
+        if !context.silent {
+            let asts = context.asts.borrow();
+            let ast = &asts[ast_index];
+            let code_file = &context.code_files.borrow()[ast.file_id];
+            print_error(&code_file.data, &error, ast.line, ast.column, ast.end);
         }
+        Diagnostic::at_ast(context, ast_index, error.code(), error.to_string())
+    }
+
+    // The messages of .warning directives
+    pub fn warning_diagnostics(&self, context: &Context) -> Vec<Diagnostic> {
+        self.warnings
+            .iter()
+            .map(|(ast_index, message)| {
+                Diagnostic::at_ast(context, *ast_index, "warning", message.clone()).warning()
+            })
+            .collect()
     }
 
     pub fn dump(&self, context: &Context) {
@@ -1,4 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use super::{
     ast::{Ast, AstInfo},
@@ -12,6 +16,7 @@ pub struct Context {
     pub tokens: RefCell<Vec<TokenInfo>>,
     pub asts: RefCell<Vec<AstInfo>>,
     pub references: RefCell<HashMap<String, Vec<DirectiveValue>>>,
+    pub predefined: RefCell<HashSet<String>>, // references the source may still redefine once
     pub files: RefCell<Vec<PathBuf>>,
     pub work_directory: PathBuf,
     pub silent: bool,
@@ -35,6 +40,7 @@ impl Context {
             column: token_info.column,
             end: token_info.end,
             ast,
+            file_id: token_info.file_id,
         };
 
         self.asts.borrow_mut().push(info);
@@ -81,6 +87,7 @@ impl Default for Context {
             tokens: Default::default(),
             asts: Default::default(),
             references: Default::default(),
+            predefined: Default::default(),
             files: Default::default(),
             silent: false,
             code_files: Default::default(),
@@ -0,0 +1,112 @@
+// Errors and warnings located in the source, for editors to underline the token they are about.
+
+use std::fmt;
+
+use super::context::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: String, // empty for the assembled string itself, otherwise the included file
+    pub line: usize,  // 0-based, like offset_to_line
+    pub column: usize,
+    pub end: usize, // column just after the token
+    pub message: String,
+    pub code: &'static str, // stable name of the kind of problem, e.g. "unknown-token"
+}
+
+impl Diagnostic {
+    pub fn error(
+        context: &Context,
+        file_id: usize,
+        (line, column, end): (usize, usize, usize),
+        code: &'static str,
+        message: String,
+    ) -> Self {
+        // File 0 is the string being assembled, the others come from .include
+        let file = match (file_id, context.files.borrow().get(file_id)) {
+            (0, _) | (_, None) => String::new(),
+            (_, Some(path)) => path.display().to_string(),
+        };
+        Self {
+            severity: Severity::Error,
+            file,
+            line,
+            column,
+            end: end.max(column + 1), // at least the character the problem starts at
+            message,
+            code,
+        }
+    }
+
+    // Located at the token `token_index` of the context
+    pub fn at_token(
+        context: &Context,
+        token_index: usize,
+        code: &'static str,
+        message: String,
+    ) -> Self {
+        let token = &context.tokens.borrow()[token_index];
+        let location = (token.line, token.column, token.end);
+        Self::error(context, token.file_id, location, code, message)
+    }
+
+    // Located at the statement `ast_index` of the context
+    pub fn at_ast(
+        context: &Context,
+        ast_index: usize,
+        code: &'static str,
+        message: String,
+    ) -> Self {
+        let ast = &context.asts.borrow()[ast_index];
+        let location = (ast.line, ast.column, ast.end);
+        Self::error(context, ast.file_id, location, code, message)
+    }
+
+    pub fn warning(self) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line + 1,
+            self.column + 1,
+            self.severity.as_str(),
+            self.message
+        )
+    }
+}
+
+// One diagnostic per line, for callers that only want text
+pub fn describe(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
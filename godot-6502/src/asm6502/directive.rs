@@ -1,6 +1,7 @@
 use strum_macros::EnumDiscriminants;
 
 use super::code_gen::CodeGeneratorError;
+use super::expression::Expression;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum DirectiveEnum {
@@ -25,7 +26,7 @@ pub enum DirectiveValue {
     Byte(u8),
     Word(u16),
     String(String),
-    Reference(String),
+    Expression(Expression), // refers to a label or constant not known yet
 }
 
 impl DirectiveValue {
@@ -0,0 +1,216 @@
+// Expressions in operands, `=` assignments and data directives.
+//
+// Numbers, labels, constants and `*`, the address of the current statement, combine with
+// + - * / % & | ^ << >> and parentheses, binding like in C, and the unary operators -, < (low
+// byte) and > (high byte). Whatever is known when the AST is generated is folded into a plain
+// number; an expression still waiting for a label is evaluated by the code generator, and if the
+// label comes later, patched once every label is known. Such an expression takes two bytes,
+// unless it is the low or high byte of something.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use super::directive::DirectiveValue;
+
+// Constants referring to constants this deep are taken for a loop
+pub const MAX_DEPTH: usize = 16;
+
+// Values a word takes, negative ones in two's complement
+pub const WORD_RANGE: std::ops::RangeInclusive<i64> = -0x8000..=0xFFFF;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Byte(u8),
+    Word(u16),
+    Symbol(String), // label or constant
+    Pc,
+    Unary(UnaryOperator, Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    LowByte,
+    HighByte,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExpressionError {
+    #[error("'{0}' is not known")]
+    Unresolved(String),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("'{0}' is defined in terms of itself")]
+    Circular(String),
+    #[error("Value {0} does not fit in two bytes")]
+    OutOfRange(i64),
+}
+
+impl ExpressionError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExpressionError::Unresolved(_) => "unresolved-label",
+            ExpressionError::OutOfRange(_) => "out-of-range",
+            ExpressionError::DivisionByZero | ExpressionError::Circular(_) => "expression",
+        }
+    }
+}
+
+// What an expression came to when the AST was generated
+#[derive(Debug, PartialEq)]
+pub enum Folded {
+    Byte(u8),
+    Word(u16),
+    Expression(Expression),
+}
+
+impl BinaryOperator {
+    // Higher binds tighter
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 5,
+            BinaryOperator::Add | BinaryOperator::Subtract => 4,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 3,
+            BinaryOperator::And => 2,
+            BinaryOperator::Xor => 1,
+            BinaryOperator::Or => 0,
+        }
+    }
+
+    fn apply(self, left: i64, right: i64) -> Result<i64, ExpressionError> {
+        Ok(match self {
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::Multiply => left.wrapping_mul(right),
+            BinaryOperator::Divide | BinaryOperator::Remainder if right == 0 => {
+                return Err(ExpressionError::DivisionByZero)
+            }
+            BinaryOperator::Divide => left.wrapping_div(right),
+            BinaryOperator::Remainder => left.wrapping_rem(right),
+            BinaryOperator::And => left & right,
+            BinaryOperator::Or => left | right,
+            BinaryOperator::Xor => left ^ right,
+            BinaryOperator::ShiftLeft => left.wrapping_shl(right as u32),
+            BinaryOperator::ShiftRight => left.wrapping_shr(right as u32),
+        })
+    }
+}
+
+impl Expression {
+    // `pc` is None while generating the AST, `lookup` gives the value of a symbol
+    pub fn evaluate(
+        &self,
+        pc: Option<u16>,
+        lookup: &dyn Fn(&str) -> Result<i64, ExpressionError>,
+    ) -> Result<i64, ExpressionError> {
+        Ok(match self {
+            Expression::Byte(byte) => *byte as i64,
+            Expression::Word(word) => *word as i64,
+            Expression::Symbol(name) => lookup(name)?,
+            Expression::Pc => {
+                pc.ok_or_else(|| ExpressionError::Unresolved("*".to_string()))? as i64
+            }
+            Expression::Unary(operator, operand) => {
+                let value = operand.evaluate(pc, lookup)?;
+                match operator {
+                    UnaryOperator::Negate => value.wrapping_neg(),
+                    UnaryOperator::LowByte => value & 0xFF,
+                    UnaryOperator::HighByte => (value >> 8) & 0xFF,
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                operator.apply(left.evaluate(pc, lookup)?, right.evaluate(pc, lookup)?)?
+            }
+        })
+    }
+
+    // Takes two bytes whatever its value: a word number, or an address, unless only one of its
+    // bytes is taken
+    pub fn is_word(&self) -> bool {
+        match self {
+            Expression::Byte(_) => false,
+            Expression::Word(_) | Expression::Symbol(_) | Expression::Pc => true,
+            Expression::Unary(UnaryOperator::Negate, operand) => operand.is_word(),
+            Expression::Unary(_, _) => false,
+            Expression::Binary(_, left, right) => left.is_word() || right.is_word(),
+        }
+    }
+
+    pub fn contains_pc(&self) -> bool {
+        match self {
+            Expression::Pc => true,
+            Expression::Unary(_, operand) => operand.contains_pc(),
+            Expression::Binary(_, left, right) => left.contains_pc() || right.contains_pc(),
+            _ => false,
+        }
+    }
+
+    // Replace the symbols `constant` knows with their expressions
+    fn substitute(self, constant: &dyn Fn(&str) -> Option<Expression>, depth: usize) -> Self {
+        match self {
+            Expression::Symbol(name) if depth < MAX_DEPTH => match constant(&name) {
+                Some(expression) => expression.substitute(constant, depth + 1),
+                None => Expression::Symbol(name),
+            },
+            Expression::Unary(operator, operand) => {
+                Expression::Unary(operator, Box::new(operand.substitute(constant, depth)))
+            }
+            Expression::Binary(operator, left, right) => Expression::Binary(
+                operator,
+                Box::new(left.substitute(constant, depth)),
+                Box::new(right.substitute(constant, depth)),
+            ),
+            expression => expression,
+        }
+    }
+
+    // With the constants defined so far put in
+    pub fn with_constants(self, references: &HashMap<String, Vec<DirectiveValue>>) -> Self {
+        self.substitute(&|name| constant(references, name), 0)
+    }
+
+    // A number if everything in it is known, one byte if it fits and no part of it takes two.
+    // Negative bytes are stored in two's complement.
+    pub fn fold(
+        self,
+        references: &HashMap<String, Vec<DirectiveValue>>,
+    ) -> Result<Folded, ExpressionError> {
+        let expression = self.with_constants(references);
+        let unresolved = |name: &str| Err(ExpressionError::Unresolved(name.to_string()));
+        match expression.evaluate(None, &unresolved) {
+            Ok(value) if !expression.is_word() && (-128..=255).contains(&value) => {
+                Ok(Folded::Byte(value as u8))
+            }
+            Ok(value) if WORD_RANGE.contains(&value) => Ok(Folded::Word(value as u16)),
+            Ok(value) => Err(ExpressionError::OutOfRange(value)),
+            Err(ExpressionError::Unresolved(_)) => Ok(Folded::Expression(expression)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+// The expression of a constant holding a single number or expression
+fn constant(references: &HashMap<String, Vec<DirectiveValue>>, name: &str) -> Option<Expression> {
+    match references.get(name).map(Vec::as_slice) {
+        Some([DirectiveValue::Byte(byte)]) => Some(Expression::Byte(*byte)),
+        Some([DirectiveValue::Word(word)]) => Some(Expression::Word(*word)),
+        Some([DirectiveValue::Expression(expression)]) => Some(expression.clone()),
+        _ => None,
+    }
+}
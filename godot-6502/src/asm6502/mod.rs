@@ -3,27 +3,48 @@
 mod ast;
 mod code_gen;
 mod context;
+mod diagnostic;
 mod directive;
+mod expression;
 mod opcode;
 mod parser;
 mod tool;
-use godot::prelude::*;
 
-use ast::{AstGenerator, AstGeneratorError};
-use code_gen::{CodeGenerator, CodeGeneratorError};
+use ast::AstGenerator;
+use code_gen::CodeGenerator;
 use context::Context;
-use parser::{ParseError, Parser};
+pub use diagnostic::{describe, Diagnostic, Severity};
+pub use directive::DirectiveValue;
+use parser::Parser;
 use std::collections::HashMap;
 
 pub struct AssemblyOutput {
     pub bytes: Vec<u8>,
     pub start_address: u16,
     pub offset_to_line: HashMap<u16, u32>,
+    pub symbols: HashMap<String, u16>, // label or constant name -> value
+    pub labels: HashMap<String, u16>,  // labels only, name -> address
+    pub warnings: Vec<Diagnostic>,
 }
 
-pub fn assemble_string(code: &str) -> Result<AssemblyOutput, String> {
+pub fn assemble_string(code: &str) -> Result<AssemblyOutput, Vec<Diagnostic>> {
+    assemble_string_with(code, &[])
+}
+
+// Assembles with constants defined before the first line, the source may redefine each once
+pub fn assemble_string_with(
+    code: &str,
+    predefined: &[(String, DirectiveValue)],
+) -> Result<AssemblyOutput, Vec<Diagnostic>> {
     let data = code.as_bytes().to_vec();
     let context = Context::default();
+    for (name, value) in predefined {
+        context
+            .references
+            .borrow_mut()
+            .insert(name.clone(), vec![value.clone()]);
+        context.predefined.borrow_mut().insert(name.clone());
+    }
 
     // Use a placeholder file ID for the string input
     let file_id = 0;
@@ -31,24 +52,30 @@ pub fn assemble_string(code: &str) -> Result<AssemblyOutput, String> {
     context.code_files.borrow_mut()[file_id].data = data.clone();
 
     // Parse the code
+    // Lines that do not tokenize are left out, so the AST generator still reports the others
     let mut parser = Parser::new(file_id, &data, context);
-    parser.parse().map_err(|e: ParseError| e.to_string())?;
+    let mut errors = parser.parse().err().unwrap_or_default();
 
     let context = parser.context;
 
     // Generate AST
     let ast_generator = AstGenerator::new();
-    let context = ast_generator
-        .generate(context)
-        .map_err(|e: AstGeneratorError| e.to_string())?;
+    let context = match ast_generator.generate(context) {
+        Ok(context) if errors.is_empty() => context,
+        Ok(_) => return Err(errors),
+        Err(ast_errors) => {
+            errors.extend(ast_errors);
+            errors.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+            return Err(errors);
+        }
+    };
 
-    // Generate code
+    // Generate code, only once every line made it, or labels on the lines left out would be missing
     let mut generator = CodeGenerator::new();
     generator.silent = true;
 
-    let context = generator
-        .generate(context)
-        .map_err(|e: CodeGeneratorError| e.to_string())?;
+    let context = generator.generate(context)?;
+    let warnings = generator.warning_diagnostics(&context);
 
     let mut output = format!("Assembled binary ({} bytes):\n", context.target.len());
     for (i, byte) in context.target.iter().enumerate() {
@@ -60,11 +87,30 @@ pub fn assemble_string(code: &str) -> Result<AssemblyOutput, String> {
     if context.target.len() % 16 != 0 {
         output.push('\n');
     }
+
+    // Labels resolve to absolute addresses, constants only when they come to a single number
+    let labels: HashMap<String, u16> = generator
+        .branches
+        .iter()
+        .map(|(name, address)| (name.clone(), *address as u16))
+        .collect();
+    let mut symbols = labels.clone();
+    let references = context.references.borrow();
+    for name in references.keys() {
+        if let Ok(value) = generator.symbol(&references, name, 0) {
+            symbols.insert(name.clone(), value as u16);
+        }
+    }
+    drop(references);
+
     // Return compiled binary with mapping
     Ok(AssemblyOutput {
         bytes: context.target,
         start_address: generator.start_point,
         offset_to_line: context.offset_to_line.into_inner(),
+        symbols,
+        labels,
+        warnings,
     })
 }
 
@@ -81,10 +127,265 @@ mod tests {
         assert_eq!(out.bytes, vec![0xA9, 0x00]);
     }
 
+    #[test]
+    fn test_assemble_string_symbols() {
+        let code = "PORT = $0200\n.org $0600\nstart:\n  LDA #$01\nloop:\n  STA PORT\n  JMP loop\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(out.symbols.get("PORT"), Some(&0x0200));
+        assert_eq!(out.symbols.get("start"), Some(&0x0600));
+        assert_eq!(out.symbols.get("loop"), Some(&0x0602));
+    }
+
+    #[test]
+    fn test_assemble_string_accumulator_shifts() {
+        let code = "ASL\nLSR\nROL\nROR\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(out.bytes, vec![0x0A, 0x4A, 0x2A, 0x6A]);
+
+        let code = "L = $28\nASL L\nROL $29,X\nLSR $0300 ; halve\nROR\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(
+            out.bytes,
+            vec![0x06, 0x28, 0x36, 0x29, 0x4E, 0x00, 0x03, 0x6A]
+        );
+    }
+
+    #[test]
+    fn test_assemble_string_branches() {
+        // Offsets count from the next instruction, wherever the program starts
+        let out =
+            assemble_string(".org $0610\nback:\n  BNE back\n  BEQ ahead\n  NOP\nahead:\n").unwrap();
+        assert_eq!(out.bytes, vec![0xD0, 0xFE, 0xF0, 0x01, 0xEA]);
+
+        // Local labels reused in the next routine leave the first routine's branches alone
+        let code = "one:\n  BNE @done\n  NOP\n@done:\n  RTS\ntwo:\n  BNE @done\n@done:\n  RTS\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(out.bytes, vec![0xD0, 0x01, 0xEA, 0x60, 0xD0, 0x00, 0x60]);
+
+        let messages = |code: &str| -> Vec<(&'static str, String)> {
+            let errors = assemble_string(code).err().unwrap();
+            errors.into_iter().map(|e| (e.code, e.message)).collect()
+        };
+        let out_of_range = |offset: i64| {
+            vec![(
+                "out-of-range",
+                format!(
+                    "Branch of {} bytes is out of range, branches reach -128 to 127 bytes",
+                    offset
+                ),
+            )]
+        };
+
+        let code = format!("back:\n{}  BNE back\n", "  NOP\n".repeat(127));
+        assert_eq!(messages(&code), out_of_range(-129));
+
+        let code = format!("  BNE ahead\n{}ahead:\n", "  NOP\n".repeat(128));
+        assert_eq!(messages(&code), out_of_range(128));
+
+        let code = format!("  BNE @ahead\n{}@ahead:\n", "  NOP\n".repeat(128));
+        assert_eq!(messages(&code), out_of_range(128));
+    }
+
+    #[test]
+    fn test_assemble_string_brk_signature() {
+        let code = "BRK\nBRK #$2A\nNOP\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(out.bytes, vec![0x00, 0x00, 0x2A, 0xEA]);
+    }
+
+    #[test]
+    fn test_assemble_string_with_predefined() {
+        let predefined = [
+            ("PORT".to_string(), DirectiveValue::Word(0x0200)),
+            ("MASK".to_string(), DirectiveValue::Byte(0x80)),
+        ];
+        let code = "MASK = $40\nLDA #MASK\nSTA PORT\n";
+        let out = assemble_string_with(code, &predefined).unwrap();
+        assert_eq!(out.bytes, vec![0xA9, 0x40, 0x8D, 0x00, 0x02]);
+
+        let code = "MASK = $40\nMASK = $20\n";
+        assert!(assemble_string_with(code, &predefined).is_err());
+    }
+
     #[test]
     fn test_assemble_string_with_errors() {
-        let code = "LDA #$00";
+        let code = "LDA #";
         let result = assemble_string(code);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_assemble_string_diagnostics() {
+        let errors = assemble_string("NOP\n  LDA #$00,Q\n").err().unwrap();
+        assert_eq!(
+            errors,
+            vec![Diagnostic {
+                severity: Severity::Error,
+                file: String::new(),
+                line: 1,
+                column: 11,
+                end: 12,
+                message: "Expected X or Y".to_string(),
+                code: "syntax",
+            }]
+        );
+
+        let errors = assemble_string("NOP\n  LDA ~\n").err().unwrap();
+        assert_eq!((errors[0].line, errors[0].column, errors[0].end), (1, 6, 7));
+        assert_eq!(errors[0].code, "unknown-token");
+        assert_eq!(describe(&errors), "2:7: error: Unknown token");
+
+        let output = assemble_string("NOP\n  .warning \"check\"\n").unwrap();
+        assert_eq!(output.warnings[0].severity, Severity::Warning);
+        assert_eq!((output.warnings[0].line, output.warnings[0].column), (1, 2));
+        assert_eq!(output.warnings[0].message, "check");
+    }
+
+    #[test]
+    fn test_assemble_string_reports_every_error() {
+        let code = "start:\n  LDA ~\n  LDA #$00,Q\n  NOP\n  LDA #$12345\n  STA $10,\n  JMP start\n";
+        let errors = assemble_string(code).err().unwrap();
+        let found: Vec<_> = errors.iter().map(|e| (e.line, e.code)).collect();
+        assert_eq!(
+            found,
+            [
+                (1, "unknown-token"),
+                (2, "syntax"),
+                (4, "invalid-number"),
+                (5, "syntax")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_string_reports_every_missing_label() {
+        let code = "start:\n  JMP nowhere\n  BNE start\n  BEQ elsewhere\n  JSR nowhere\n";
+        let errors = assemble_string(code).err().unwrap();
+        let found: Vec<_> = errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (1, "Label 'nowhere' not found"),
+                (3, "Label 'elsewhere' not found"),
+                (4, "Label 'nowhere' not found")
+            ]
+        );
+        assert!(errors.iter().all(|e| e.code == "unresolved-label"));
+    }
+
+    #[test]
+    fn test_assemble_string_expressions() {
+        let code =
+            "LDA #2+3*4\nLDA #(2+3)*4\nLDA #$F0 >> 4 | 1\nLDA #-1\nLDA #17 % 5\nLDA #%00000101\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(
+            out.bytes,
+            vec![0xA9, 0x0E, 0xA9, 0x14, 0xA9, 0x0F, 0xA9, 0xFF, 0xA9, 0x02, 0xA9, 0x05]
+        );
+
+        // A parenthesis only asks for indirect addressing when no operator follows it
+        let code = "JMP ($1234)\nLDA ($10),Y\nLDA ($10+1)*2\nLDA ($10+1),Y\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(
+            out.bytes,
+            vec![0x6C, 0x34, 0x12, 0xB1, 0x10, 0xA5, 0x22, 0xB1, 0x11]
+        );
+    }
+
+    #[test]
+    fn test_assemble_string_label_arithmetic() {
+        let code = ".org $0600\nstart:\n  LDA #<table\n  LDX #>table\n  LDA table+1,X\n  JMP *\ntable:\n  .byte <(end-table), <start\n  .word table+1, *\nend:\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(
+            out.bytes,
+            vec![
+                0xA9, 0x0A, 0xA2, 0x06, 0xBD, 0x0B, 0x06, 0x4C, 0x07, 0x06, 0x06, 0x00, 0x0B, 0x06,
+                0x0C, 0x06
+            ]
+        );
+
+        let code = "size = end-start\nstart:\n  .byte <size, <size*2\nend:\n  LDA #<size\n";
+        let out = assemble_string(code).unwrap();
+        assert_eq!(out.bytes, vec![0x02, 0x04, 0xA9, 0x02]);
+        assert_eq!(out.symbols.get("size"), Some(&0x02));
+    }
+
+    #[test]
+    fn test_assemble_string_expression_errors() {
+        let messages = |code: &str| -> Vec<(&'static str, String)> {
+            let errors = assemble_string(code).err().unwrap();
+            errors.into_iter().map(|e| (e.code, e.message)).collect()
+        };
+        let error = |code: &'static str, message: &str| vec![(code, message.to_string())];
+
+        assert_eq!(
+            messages("LDA #1/0\n"),
+            error("expression", "Division by zero")
+        );
+        assert_eq!(messages("LDA #(1+2\n"), error("syntax", "Expected ')'"));
+        assert_eq!(
+            messages("here = *\n"),
+            error("syntax", "'*' cannot be used in an assignment")
+        );
+        assert_eq!(
+            messages(".byte <table+$FF\ntable = $1234\n"),
+            error("out-of-range", "Value 307 does not fit in a byte")
+        );
+        assert_eq!(
+            messages("a = b+1\nb = a+1\nLDA a\n"),
+            error("expression", "'b' is defined in terms of itself")
+        );
+        assert_eq!(
+            messages("LDA nowhere+1\n"),
+            error("unresolved-label", "Label 'nowhere' not found")
+        );
+        assert_eq!(
+            messages("LDA #2 )\n"),
+            error("syntax", "Unexpected syntax (CloseParenthesis)")
+        );
+    }
+
+    #[test]
+    fn test_assemble_string_byte_operands() {
+        let messages = |code: &str| -> Vec<(&'static str, String)> {
+            let errors = assemble_string(code).err().unwrap();
+            errors.into_iter().map(|e| (e.code, e.message)).collect()
+        };
+        let too_big = |value: u16| {
+            vec![(
+                "out-of-range",
+                format!("Value {} does not fit in a byte", value),
+            )]
+        };
+        let word = vec![(
+            "out-of-range",
+            "Two-byte value where one byte goes, take its low or high byte with < or >".to_string(),
+        )];
+
+        assert_eq!(messages("LDA #$FF+1\n"), too_big(256));
+        assert_eq!(messages("LDA #300\n"), too_big(300));
+        assert_eq!(messages("LDA #$100\n"), too_big(256));
+        assert_eq!(messages("start:\n  LDA #start\n"), word);
+        assert_eq!(messages(".byte end\nend:\n"), word);
+        assert_eq!(messages("LDA (ptr),Y\nptr = $1234\n"), word);
+
+        let out = assemble_string("start:\n  LDA #<start\n  LDX #>start\n").unwrap();
+        assert_eq!(out.bytes, vec![0xA9, 0x00, 0xA2, 0x00]);
+    }
+
+    #[test]
+    fn test_assemble_string_forward_constants() {
+        // Sized as if the constant came first, without spilling into the next instruction
+        let out = assemble_string("LDA (ptr),Y\nNOP\nptr = $10\n").unwrap();
+        assert_eq!(out.bytes, vec![0xB1, 0x10, 0xEA]);
+
+        let out = assemble_string("LDA (ptr,X)\nNOP\nptr = $10\n").unwrap();
+        assert_eq!(out.bytes, vec![0xA1, 0x10, 0xEA]);
+
+        let out = assemble_string("LDA ptr,X\nNOP\nptr = $10\n").unwrap();
+        assert_eq!(out.bytes, vec![0xB5, 0x10, 0xEA]);
+
+        let out = assemble_string("LDA (ptr),Y\nptr = $0010\n").unwrap();
+        assert_eq!(out.bytes, vec![0xB1, 0x10]);
+    }
 }
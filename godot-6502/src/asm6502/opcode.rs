@@ -44,7 +44,7 @@ pub const BIT_MODES: [ModeInfo; 2] = [ModeInfo { mode: ModeType::ZeroPage, opcod
 pub const BMI_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Relative, opcode: 0x30}];
 pub const BNE_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Relative, opcode: 0xD0}];
 pub const BPL_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Relative, opcode: 0x10}];
-pub const BRK_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Implied, opcode: 0x00}];
+pub const BRK_MODES: [ModeInfo; 2] = [ModeInfo { mode: ModeType::Implied, opcode: 0x00}, ModeInfo { mode: ModeType::Immediate, opcode: 0x00}];
 pub const BVC_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Relative, opcode: 0x50}];
 pub const BVS_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Relative, opcode: 0x70}];
 pub const CLC_MODES: [ModeInfo; 1] = [ModeInfo { mode: ModeType::Implied, opcode: 0x18}];
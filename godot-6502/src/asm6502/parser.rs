@@ -3,6 +3,7 @@ use std::str::Utf8Error;
 
 use super::{
     context::Context,
+    diagnostic::Diagnostic,
     opcode::INSTS,
     tool::{print_error, upper_case_byte},
 };
@@ -37,6 +38,7 @@ pub struct Parser<'a> {
     pub context: Context,
     pub data: &'a [u8],
     pub file_id: usize,
+    errors: Vec<Diagnostic>,
 }
 
 #[derive(Debug, PartialEq, Clone, EnumDiscriminants)]
@@ -57,11 +59,50 @@ pub enum Token {
     LocalBranch(String),
     Byte(u8),
     Word(u16),
+    Operator(Operator),
     NewLine(usize),
     Space(usize),
     End,
 }
 
+// Characters of the operators, which also end numbers and names
+const OPERATOR_BYTES: &[u8] = b"+-*/%&|^<>";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star, // multiply, or the program counter where a value is expected
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
+    Less,    // low byte
+    Greater, // high byte
+}
+
+impl Operator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Percent => "%",
+            Operator::Ampersand => "&",
+            Operator::Pipe => "|",
+            Operator::Caret => "^",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TokenInfo {
     pub line: usize,
@@ -104,6 +145,23 @@ pub enum ParseError {
     Utf8Error(#[from] Utf8Error),
 }
 
+impl ParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::OutOfScope => "out-of-scope",
+            ParseError::UnexpectedSymbol => "unexpected-symbol",
+            ParseError::UnknownToken => "unknown-token",
+            ParseError::InvalidNumberFormat => "invalid-number",
+            ParseError::InvalidCommentFormat => "invalid-comment",
+            ParseError::InvalidKeyword => "invalid-keyword",
+            ParseError::MissingColon => "missing-colon",
+            ParseError::InvalidDirective => "invalid-directive",
+            ParseError::InvalidString => "invalid-string",
+            ParseError::Utf8Error(_) => "invalid-text",
+        }
+    }
+}
+
 impl<'a> Parser<'a> {
     pub fn new(file_id: usize, data: &'a [u8], context: Context) -> Self {
         let size = data.len();
@@ -117,6 +175,7 @@ impl<'a> Parser<'a> {
             context,
             data,
             file_id,
+            errors: Vec::new(),
         }
     }
 
@@ -130,10 +189,16 @@ impl<'a> Parser<'a> {
         });
     }
 
-    fn inner_parse(&mut self) -> Result<(), ParseError> {
+    fn inner_parse(&mut self) {
         while self.size > self.index {
             let mut total_lines = 0;
-            let token = self.next()?;
+            let token = match self.next() {
+                Ok(token) => token,
+                Err(error) => {
+                    self.skip_line(error);
+                    continue;
+                }
+            };
 
             if let Token::NewLine(lines) = token {
                 total_lines = lines;
@@ -150,19 +215,39 @@ impl<'a> Parser<'a> {
         }
 
         self.add_token(Token::End);
-        Ok(())
     }
 
-    pub fn parse(&mut self) -> Result<(), ParseError> {
-        match self.inner_parse() {
-            Ok(_) => Ok(()),
-            Err(error) => {
-                if !self.context.silent {
-                    print_error(self.data, &error, self.line, self.column, self.end);
-                }
-
-                Err(error)
+    // Note the error and drop the rest of the line along with its tokens so far, so that the
+    // AST generator never sees half of it. Tokenizing goes on from the line break.
+    fn skip_line(&mut self, error: ParseError) {
+        if !self.context.silent {
+            print_error(self.data, &error, self.line, self.column, self.end);
+        }
+        let location = (self.line, self.column, self.end);
+        let diagnostic =
+            Diagnostic::error(&self.context, self.file_id, location, error.code(), error.to_string());
+        self.errors.push(diagnostic);
+
+        let mut tokens = self.context.tokens.borrow_mut();
+        while let Some(token) = tokens.last() {
+            if let Token::NewLine(_) = token.token {
+                break;
             }
+            tokens.pop();
+        }
+        while self.index < self.size && !matches!(self.data[self.index], b'\r' | b'\n') {
+            self.index += 1;
+            self.end += 1;
+        }
+        self.column = self.end;
+    }
+
+    // Tokenizes everything, returning the errors of every line that could not be
+    pub fn parse(&mut self) -> Result<(), Vec<Diagnostic>> {
+        self.inner_parse();
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(std::mem::take(&mut self.errors)),
         }
     }
 
@@ -219,6 +304,7 @@ impl<'a> Parser<'a> {
 
         match first {
             b'$' => self.parse_hex(),
+            b'%' if self.after_value() => self.parse_operator(),
             b'%' => self.parse_binary(),
             b'0'..=b'9' => self.parse_absolute_decimal(),
             b'#' => self.parse_sharp(),
@@ -233,6 +319,7 @@ impl<'a> Parser<'a> {
             b',' => self.parse_comma(),
             b'\r' | b'\n' => self.parse_newline(),
             b' ' | b'\t' => self.parse_whitespace(),
+            n if OPERATOR_BYTES.contains(&n) => self.parse_operator(),
             n => {
                 println!("{}", n);
                 Err(ParseError::UnknownToken)
@@ -247,6 +334,7 @@ impl<'a> Parser<'a> {
             let number = match n {
                 n @ b'0'..=b'9' => n - b'0',
                 b' ' | b'\r' | b'\t' | b'\n' | b',' | b')' => break,
+                n if OPERATOR_BYTES.contains(&n) => break,
                 _ => return Err(ParseError::InvalidNumberFormat),
             };
 
@@ -278,6 +366,7 @@ impl<'a> Parser<'a> {
                 b'A'..=b'F' => (n - b'A') + 10,
                 b'a'..=b'f' => (n - b'a') + 10,
                 b' ' | b'\r' | b'\t' | b'\n' | b',' | b')' => break,
+                n if OPERATOR_BYTES.contains(&n) => break,
                 _ => return Err(ParseError::InvalidNumberFormat),
             };
 
@@ -286,13 +375,10 @@ impl<'a> Parser<'a> {
             let _ = self.eat();
         }
 
-        if count != 2 && count != 4 {
-            return Err(ParseError::InvalidNumberFormat);
-        }
-
-        match count / 2 {
-            1 => Ok(Token::Byte(hex_number as u8)),
-            2 => Ok(Token::Word(hex_number)),
+        // Up to two digits is a byte, so $100 is a word that may not fit where a byte goes
+        match count {
+            1..=2 => Ok(Token::Byte(hex_number as u8)),
+            3..=4 => Ok(Token::Word(hex_number)),
             _ => Err(ParseError::InvalidNumberFormat),
         }
     }
@@ -308,6 +394,7 @@ impl<'a> Parser<'a> {
                 b'0' => 0,
                 b'1' => 1,
                 b' ' | b'\r' | b'\t' | b'\n' | b',' | b')' => break,
+                n if OPERATOR_BYTES.contains(&n) => break,
                 _ => return Err(ParseError::InvalidNumberFormat),
             };
 
@@ -316,13 +403,9 @@ impl<'a> Parser<'a> {
             let _ = self.eat();
         }
 
-        if count != 8 && count != 16 {
-            return Err(ParseError::InvalidNumberFormat);
-        }
-
-        match count / 8 {
-            1 => Ok(Token::Byte(binary_number as u8)),
-            2 => Ok(Token::Word(binary_number)),
+        match count {
+            1..=8 => Ok(Token::Byte(binary_number as u8)),
+            9..=16 => Ok(Token::Word(binary_number)),
             _ => Err(ParseError::InvalidNumberFormat),
         }
     }
@@ -358,6 +441,7 @@ impl<'a> Parser<'a> {
                         b'_' => (),
                         b' ' | b',' | b')' | b'=' | b'\t' => break,
                         b'\n' | b'\r' => break,
+                        n if OPERATOR_BYTES.contains(&n) => break,
                         b':' => {
                             branch = true;
                             self.eat()?;
@@ -538,6 +622,49 @@ impl<'a> Parser<'a> {
         Ok(Token::Assign)
     }
 
+    fn parse_operator(&mut self) -> Result<Token, ParseError> {
+        let operator = match (self.eat()?, self.peek()) {
+            (b'<', Ok(b'<')) | (b'>', Ok(b'>')) => {
+                let shift = self.eat()?;
+                match shift {
+                    b'<' => Operator::ShiftLeft,
+                    _ => Operator::ShiftRight,
+                }
+            }
+            (b'+', _) => Operator::Plus,
+            (b'-', _) => Operator::Minus,
+            (b'*', _) => Operator::Star,
+            (b'/', _) => Operator::Slash,
+            (b'%', _) => Operator::Percent,
+            (b'&', _) => Operator::Ampersand,
+            (b'|', _) => Operator::Pipe,
+            (b'^', _) => Operator::Caret,
+            (b'<', _) => Operator::Less,
+            (b'>', _) => Operator::Greater,
+            _ => return Err(ParseError::UnexpectedSymbol),
+        };
+        Ok(Token::Operator(operator))
+    }
+
+    // Whether the last token ends a value, making % the remainder rather than a binary number
+    fn after_value(&self) -> bool {
+        let tokens = self.context.tokens.borrow();
+        let last = tokens
+            .iter()
+            .rev()
+            .find(|info| !matches!(info.token, Token::Space(_)));
+        matches!(
+            last.map(|info| &info.token),
+            Some(
+                Token::Byte(_)
+                    | Token::Word(_)
+                    | Token::Keyword(_)
+                    | Token::LocalKeyword(_)
+                    | Token::CloseParenthesis
+            )
+        )
+    }
+
     fn parse_comma(&mut self) -> Result<Token, ParseError> {
         self.eat_expected(b',', ParseError::UnexpectedSymbol)?;
         Ok(Token::Comma)
@@ -593,6 +720,7 @@ impl<'a> Parser<'a> {
                 Token::Comma => "COMMA",
                 Token::LocalBranch(_) => "LOCAL BR",
                 Token::LocalKeyword(_) => "LOCAL KEY",
+                Token::Operator(_) => "OPERATOR",
             };
 
             if ast.line != line {
@@ -0,0 +1,22 @@
+// Headless Debug Adapter Protocol server for ship programs.
+//
+// Point a DAP client (e.g. VS Code) at this executable, or start it with --port and connect
+// over TCP. The program to debug is given by the client in its `launch` request.
+
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(about = "Debug Adapter Protocol server for 6502 ship programs")]
+struct Args {
+    /// Listen on this TCP port instead of using stdin/stdout
+    #[arg(long)]
+    port: Option<u16>,
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    match args.port {
+        Some(port) => godot_6502::dap::serve_tcp(port),
+        None => godot_6502::dap::serve_stdio(),
+    }
+}
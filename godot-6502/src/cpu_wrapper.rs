@@ -0,0 +1,396 @@
+use rv6502emu::cpu::{Cpu, CpuFlags};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::asm6502::AssemblyOutput;
+use crate::cycles;
+use crate::devices::access::{self, Access};
+use crate::devices::rng::{self, Rng, SeededRng};
+use crate::devices::{self, DeviceBus, DeviceError, DeviceInfo, ShipDevice};
+use crate::hot_reload::{self, LoadedProgram, ReloadError};
+use crate::syscall::{self, Syscall, SyscallHandler, SyscallRegisters};
+
+#[derive(Clone)]
+pub struct CPUWrapper {
+    pub cpu: Rc<RefCell<Cpu>>,
+    pub start_address: Rc<RefCell<u16>>, // program load address
+    pub offset_to_line: Rc<RefCell<HashMap<u16, u32>>>, // offset in program -> line
+    pub cycles: Rc<RefCell<u64>>,        // cycles executed since creation
+    pub source: Rc<RefCell<String>>,     // assembly the program was built from, if any
+    pub program: Rc<RefCell<Option<LoadedProgram>>>, // as assembled from `source`
+    pub breakpoints: Rc<RefCell<HashSet<u16>>>,
+    pub halted: Rc<RefCell<bool>>, // stopped on a breakpoint or by a debugger
+    pub devices: Rc<RefCell<Vec<Box<dyn ShipDevice>>>>,
+    pub pending_cycles: Rc<RefCell<u32>>, // cycles outside instructions devices have not seen yet
+    pub seed: Rc<RefCell<u64>>,
+    pub noise: Rc<RefCell<SeededRng>>, // random stream for sensor noise
+    pub syscalls: Rc<RefCell<HashMap<u8, SyscallHandler>>>,
+    pub syscall_trap: Rc<RefCell<Option<u16>>>, // JSR target that makes a syscall
+}
+
+impl CPUWrapper {
+    pub fn new(start_address: u16, program: Vec<u8>, mapping: HashMap<u16, u32>) -> Self {
+        // create a MOS6502 (default) CPU with default bus and 64k memory
+        let mut cpu_obj = Cpu::new_default(None);
+        // load program bytes into memory
+        let mem = cpu_obj.bus.get_memory();
+        for (i, b) in program.iter().enumerate() {
+            let _ = mem.write_byte(start_address as usize + i, *b);
+        }
+        // reset CPU so SP/flags/PC are correctly initialized
+        let _ = cpu_obj.reset(Some(start_address));
+
+        Self {
+            cpu: Rc::new(RefCell::new(cpu_obj)),
+            start_address: Rc::new(RefCell::new(start_address)),
+            offset_to_line: Rc::new(RefCell::new(mapping)),
+            cycles: Rc::new(RefCell::new(0)),
+            source: Rc::new(RefCell::new(String::new())),
+            program: Rc::new(RefCell::new(None)),
+            breakpoints: Rc::new(RefCell::new(HashSet::new())),
+            halted: Rc::new(RefCell::new(false)),
+            devices: Rc::new(RefCell::new(Vec::new())),
+            pending_cycles: Rc::new(RefCell::new(0)),
+            seed: Rc::new(RefCell::new(rng::DEFAULT_SEED)),
+            noise: Rc::new(RefCell::new(SeededRng::stream(
+                rng::DEFAULT_SEED,
+                rng::STREAM_NOISE,
+            ))),
+            syscalls: Rc::new(RefCell::new(HashMap::new())),
+            syscall_trap: Rc::new(RefCell::new(Some(syscall::DEFAULT_TRAP_ADDRESS))),
+        }
+    }
+
+    pub fn get_cpu(&self) -> Rc<RefCell<Cpu>> {
+        self.cpu.clone()
+    }
+
+    // Execute a single instruction and return the number of cycles it took
+    pub fn run_step(&self) -> u32 {
+        let (mut cycles, access) = if let Some(syscall) = self.next_syscall() {
+            (self.call_syscall(syscall), None)
+        } else {
+            let mut cpu = self.cpu.borrow_mut();
+            let pc = cpu.regs.pc;
+            let opcode = cpu.bus.get_memory().read_byte(pc as usize).unwrap_or(0);
+            let data_access = access::next_access(&mut cpu);
+
+            // run with a limit of 1 stops after the first instruction
+            let _ = cpu.run(None, 1);
+
+            (
+                cycles::instruction_cycles(opcode, pc, cpu.regs.pc),
+                data_access,
+            )
+        };
+
+        let pending = std::mem::take(&mut *self.pending_cycles.borrow_mut());
+        let extra_cycles = self.tick_devices(cycles + pending, access);
+        *self.pending_cycles.borrow_mut() = extra_cycles;
+
+        cycles += extra_cycles;
+        *self.cycles.borrow_mut() += cycles as u64;
+        cycles
+    }
+
+    fn next_syscall(&self) -> Option<Syscall> {
+        let syscalls = self.syscalls.borrow();
+        if syscalls.is_empty() {
+            return None;
+        }
+        let mut cpu = self.cpu.borrow_mut();
+        let pc = cpu.regs.pc;
+        let memory = cpu.bus.get_memory();
+        syscall::decode(
+            pc,
+            *self.syscall_trap.borrow(),
+            |address| memory.read_byte(address as usize).unwrap_or(0),
+            |number| syscalls.contains_key(&number),
+        )
+    }
+
+    // Run the handler with nothing borrowed, so it can read and write memory
+    fn call_syscall(&self, syscall: Syscall) -> u32 {
+        let Some(handler) = self.syscalls.borrow().get(&syscall.number).cloned() else {
+            return 0;
+        };
+        let mut registers = {
+            let cpu = self.cpu.borrow();
+            SyscallRegisters {
+                a: cpu.regs.a,
+                x: cpu.regs.x,
+                y: cpu.regs.y,
+                p: cpu.regs.p.bits(),
+            }
+        };
+        handler(syscall.number, &mut registers);
+
+        let mut cpu = self.cpu.borrow_mut();
+        cpu.regs.a = registers.a;
+        cpu.regs.x = registers.x;
+        cpu.regs.y = registers.y;
+        cpu.regs.p = CpuFlags::from_bits_truncate(registers.p);
+        cpu.regs.pc = syscall.resume_address;
+        syscall.cycles
+    }
+
+    pub fn register_syscall(&self, number: u8, handler: SyscallHandler) {
+        self.syscalls.borrow_mut().insert(number, handler);
+    }
+
+    pub fn unregister_syscall(&self, number: u8) -> bool {
+        self.syscalls.borrow_mut().remove(&number).is_some()
+    }
+
+    // None leaves JSR alone, syscalls are then only made with BRK
+    pub fn set_syscall_trap(&self, address: Option<u16>) {
+        *self.syscall_trap.borrow_mut() = address;
+    }
+
+    // Let every device catch up with the CPU, then service the interrupts they raised.
+    // Returns the cycles stolen by devices plus those spent entering an interrupt handler.
+    fn tick_devices(&self, cycles: u32, access: Option<Access>) -> u32 {
+        let mut devices = self.devices.borrow_mut();
+        if devices.is_empty() {
+            return 0;
+        }
+
+        let mut cpu = self.cpu.borrow_mut();
+        let mut bus = DeviceBus::new(&mut cpu).with_access(access);
+        for device in devices.iter_mut() {
+            device.tick(&mut bus, cycles);
+        }
+        let signals = bus.signals();
+
+        let interrupt_cycles = if signals.reset {
+            let vector = DeviceBus::new(&mut cpu).read_word(devices::RESET_VECTOR);
+            let start = match vector {
+                0 => *self.start_address.borrow(),
+                vector => vector,
+            };
+            let _ = cpu.reset(Some(start));
+            0
+        } else if signals.nmi {
+            devices::interrupt(&mut cpu, devices::NMI_VECTOR);
+            devices::INTERRUPT_CYCLES
+        } else if signals.irq && !devices::irq_disabled(&cpu) {
+            devices::interrupt(&mut cpu, devices::IRQ_VECTOR);
+            devices::INTERRUPT_CYCLES
+        } else {
+            0
+        };
+
+        signals.stolen_cycles + interrupt_cycles
+    }
+
+    // Refuses a device that overlaps one already attached or does not fit in memory
+    pub fn attach_device(&self, device: Box<dyn ShipDevice>) -> Result<(), DeviceError> {
+        let mut devices = self.devices.borrow_mut();
+        devices::check_placement(device.as_ref(), &devices)?;
+        devices.push(device);
+        Ok(())
+    }
+
+    pub fn detach_device(&self, base_address: u16) -> bool {
+        let mut devices = self.devices.borrow_mut();
+        let count = devices.len();
+        devices.retain(|device| device.base_address() != base_address);
+        devices.len() != count
+    }
+
+    // Attached devices, by base address
+    pub fn device_info(&self) -> Vec<DeviceInfo> {
+        let mut info: Vec<DeviceInfo> = self
+            .devices
+            .borrow()
+            .iter()
+            .map(|device| DeviceInfo::of(device.as_ref()))
+            .collect();
+        info.sort_by_key(|device| device.base_address);
+        info
+    }
+
+    // Run `f` on the attached device called `name` if it is a `T`
+    pub fn with_device<T: 'static, R>(&self, name: &str, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut devices = self.devices.borrow_mut();
+        devices
+            .iter_mut()
+            .filter(|device| device.name() == name)
+            .find_map(|device| device.as_any_mut().downcast_mut::<T>())
+            .map(f)
+    }
+
+    // Run `f` on the device attached at `base_address` if it is a `T`
+    pub fn with_device_at<T: 'static, R>(
+        &self,
+        base_address: u16,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        let mut devices = self.devices.borrow_mut();
+        devices
+            .iter_mut()
+            .filter(|device| device.base_address() == base_address)
+            .find_map(|device| device.as_any_mut().downcast_mut::<T>())
+            .map(f)
+    }
+
+    // Execute whole instructions until at least `cycles` cycles have elapsed, for timing devices
+    pub fn run_cycles(&self, cycles: u32) {
+        let mut elapsed = 0;
+        while elapsed < cycles && !self.is_halted() {
+            elapsed += self.run_step();
+        }
+    }
+
+    // Execute `steps` instructions, whatever their cycle counts.
+    // Stops early, without executing it, when the next instruction has a breakpoint.
+    pub fn run_steps_async(&self, steps: u32) {
+        for _ in 0..steps {
+            if self.is_halted() {
+                break;
+            }
+            self.run_step();
+
+            let pc = self.cpu.borrow().regs.pc;
+            if self.breakpoints.borrow().contains(&pc) {
+                self.set_halted(true);
+            }
+        }
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        *self.seed.borrow()
+    }
+
+    // Restart every random stream of this CPU from `seed`
+    pub fn set_seed(&self, seed: u64) {
+        *self.seed.borrow_mut() = seed;
+        *self.noise.borrow_mut() = SeededRng::stream(seed, rng::STREAM_NOISE);
+        for device in self.devices.borrow_mut().iter_mut() {
+            if let Some(rng) = device.as_any_mut().downcast_mut::<Rng>() {
+                rng.reseed(seed);
+            }
+        }
+    }
+
+    pub fn random_f64(&self) -> f64 {
+        self.noise.borrow_mut().next_f64()
+    }
+
+    pub fn random_normal(&self, mean: f64, deviation: f64) -> f64 {
+        self.noise.borrow_mut().next_normal(mean, deviation)
+    }
+
+    pub fn is_halted(&self) -> bool {
+        *self.halted.borrow()
+    }
+
+    pub fn set_halted(&self, halted: bool) {
+        *self.halted.borrow_mut() = halted;
+    }
+
+    pub fn wait_until_done(&self) {
+        // No-op (synchronous run)
+    }
+
+    pub fn get_cycles(&self) -> u64 {
+        *self.cycles.borrow()
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        let mut cpu = self.cpu.borrow_mut();
+        cpu.bus
+            .get_memory()
+            .read_byte(address as usize)
+            .unwrap_or(0)
+    }
+
+    // All 64 KB of memory
+    pub fn read_all(&self) -> Vec<u8> {
+        let mut cpu = self.cpu.borrow_mut();
+        let memory = cpu.bus.get_memory();
+        (0..=0xFFFF)
+            .map(|address| memory.read_byte(address).unwrap_or(0))
+            .collect()
+    }
+
+    pub fn write_byte(&self, address: u16, value: u8) {
+        let mut cpu = self.cpu.borrow_mut();
+        let _ = cpu.bus.get_memory().write_byte(address as usize, value);
+    }
+
+    pub fn get_status(&self) -> u8 {
+        self.cpu.borrow().regs.p.bits()
+    }
+
+    pub fn set_status(&self, status: u8) {
+        self.cpu.borrow_mut().regs.p = CpuFlags::from_bits_truncate(status);
+    }
+
+    pub fn set_mapping(&self, start_address: u16, mapping: HashMap<u16, u32>) {
+        *self.start_address.borrow_mut() = start_address;
+        let mut map = self.offset_to_line.borrow_mut();
+        map.clear();
+        map.extend(mapping);
+    }
+
+    pub fn set_source(&self, source: &str) {
+        *self.source.borrow_mut() = source.to_string();
+    }
+
+    pub fn set_program(&self, output: &AssemblyOutput) {
+        let load_address = *self.start_address.borrow();
+        *self.program.borrow_mut() = Some(LoadedProgram::new(output, load_address));
+    }
+
+    // Replace the code of the program loaded from source with `output`, keeping everything else,
+    // and move the PC to the same place in the new code. See hot_reload.rs.
+    pub fn hot_reload(&self, output: &AssemblyOutput, source: &str) -> Result<(), ReloadError> {
+        let load_address = *self.start_address.borrow();
+        let new = LoadedProgram::new(output, load_address);
+        let old_size = {
+            let old = self.program.borrow();
+            let old = old.as_ref().ok_or(ReloadError::NothingLoaded)?;
+            let pc = self.cpu.borrow().regs.pc;
+            let new_pc = hot_reload::remap_pc(pc, old, &new)?;
+            self.cpu.borrow_mut().regs.pc = new_pc;
+            old.bytes.len()
+        };
+
+        // Bytes the old program used past the end of the new one are cleared
+        {
+            let mut cpu = self.cpu.borrow_mut();
+            let memory = cpu.bus.get_memory();
+            for i in 0..old_size.max(new.bytes.len()) {
+                let byte = new.bytes.get(i).copied().unwrap_or(0);
+                let _ = memory.write_byte(load_address as usize + i, byte);
+            }
+        }
+
+        self.set_mapping(load_address, output.offset_to_line.clone());
+        self.set_source(source);
+        *self.program.borrow_mut() = Some(new);
+        Ok(())
+    }
+
+    // First address generated for a source line, the inverse of `get_line_number`
+    pub fn get_address_for_line(&self, line: u32) -> Option<u16> {
+        let start: u16 = *self.start_address.borrow();
+        let map = self.offset_to_line.borrow();
+        map.iter()
+            .filter(|(_, l)| **l == line)
+            .map(|(offset, _)| start.wrapping_add(*offset))
+            .min()
+    }
+
+    pub fn get_line_number(&self, pc: u16) -> Option<u32> {
+        let start: u16 = *self.start_address.borrow();
+        if pc < start {
+            return None;
+        }
+        let offset = pc.wrapping_sub(start);
+        let map = self.offset_to_line.borrow();
+        map.get(&offset).copied()
+    }
+}
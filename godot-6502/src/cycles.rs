@@ -0,0 +1,47 @@
+// Instruction timing for the NMOS 6502.
+// rv6502emu only exposes whole instructions through `run`, so the wrapper keeps its own clock
+// by looking up the base cost of every opcode it executes.
+
+#[rustfmt::skip]
+pub const BASE_CYCLES: [u8; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  A  B  C  D  E  F
+    7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 0
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 1
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 2
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 3
+    6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 4
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 5
+    6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 6
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 7
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 8
+    2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 9
+    2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // A
+    2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // B
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // C
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // D
+    2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // E
+    2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // F
+];
+
+fn is_branch(opcode: u8) -> bool {
+    opcode & 0x1F == 0x10
+}
+
+// Cycles taken by the instruction `opcode` that moved the PC from `pc_before` to `pc_after`.
+// Taken branches cost one extra cycle, two when they land on another page.
+// The page-crossing penalty of indexed reads is not modelled.
+pub fn instruction_cycles(opcode: u8, pc_before: u16, pc_after: u16) -> u32 {
+    let mut cycles = BASE_CYCLES[opcode as usize] as u32;
+
+    if is_branch(opcode) {
+        let next = pc_before.wrapping_add(2);
+        if pc_after != next {
+            cycles += 1;
+            if pc_after & 0xFF00 != next & 0xFF00 {
+                cycles += 1;
+            }
+        }
+    }
+
+    cycles
+}
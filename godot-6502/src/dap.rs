@@ -0,0 +1,740 @@
+// Debug Adapter Protocol server for ship programs.
+//
+// A `DapSession` answers DAP requests against a `CPUWrapper`. It runs either headless, where a
+// `launch` request assembles a file into a fresh CPU and the session drives it (`serve`), or
+// attached to a ship in the running game, where the game keeps running the CPU and the session
+// only watches for breakpoints (`DapListener`, polled from `Emulator6502::dap_poll`).
+//
+// Messages are framed as in the DAP spec: a `Content-Length` header, a blank line, then JSON.
+
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::asm6502;
+use crate::cpu_wrapper::CPUWrapper;
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REFERENCE: i64 = 1;
+const FLAGS_REFERENCE: i64 = 2;
+const STACK_REFERENCE: i64 = 3;
+
+// Instructions executed between two checks for incoming requests when running headless
+const RUN_CHUNK: u32 = 10_000;
+const STEP_OVER_LIMIT: u32 = 1_000_000;
+
+const FLAG_NAMES: [(&str, u8); 7] = [
+    ("N", 0x80),
+    ("V", 0x40),
+    ("B", 0x10),
+    ("D", 0x08),
+    ("I", 0x04),
+    ("Z", 0x02),
+    ("C", 0x01),
+];
+
+// Accumulates raw bytes and splits them into DAP messages
+#[derive(Default)]
+pub struct MessageBuffer {
+    data: Vec<u8>,
+}
+
+impl MessageBuffer {
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn next_message(&mut self) -> Option<Value> {
+        loop {
+            let header_end = self.data.windows(4).position(|w| w == b"\r\n\r\n")?;
+            let header = String::from_utf8_lossy(&self.data[..header_end]).to_string();
+            let length = header.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                match name.trim().eq_ignore_ascii_case("Content-Length") {
+                    true => value.trim().parse::<usize>().ok(),
+                    false => None,
+                }
+            });
+
+            let body_start = header_end + 4;
+            let length = match length {
+                Some(length) => length,
+                None => {
+                    // Malformed header, drop it and look for the next one
+                    self.data.drain(..body_start);
+                    continue;
+                }
+            };
+
+            if self.data.len() < body_start + length {
+                return None;
+            }
+
+            let body: Vec<u8> = self
+                .data
+                .drain(..body_start + length)
+                .skip(body_start)
+                .collect();
+            if let Ok(message) = serde_json::from_slice(&body) {
+                return Some(message);
+            }
+        }
+    }
+}
+
+pub fn encode_message(message: &Value) -> Vec<u8> {
+    let body = message.to_string();
+    let mut out = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    out.extend_from_slice(body.as_bytes());
+    out
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn parse_address(reference: &str) -> Option<u16> {
+    let reference = reference.trim();
+    if let Some(hex) = reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix('$'))
+    {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    reference.parse::<u16>().ok()
+}
+
+pub struct DapSession {
+    cpu: Option<CPUWrapper>,
+    headless: bool,
+    source_path: Option<String>,
+    stop_on_entry: bool,
+    running: bool,
+    terminated: bool,
+    seq: i64,
+}
+
+impl DapSession {
+    pub fn headless() -> Self {
+        Self {
+            cpu: None,
+            headless: true,
+            source_path: None,
+            stop_on_entry: false,
+            running: false,
+            terminated: false,
+            seq: 1,
+        }
+    }
+
+    pub fn attached(cpu: CPUWrapper) -> Self {
+        Self {
+            cpu: Some(cpu),
+            headless: false,
+            ..Self::headless()
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq - 1
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        json!({ "seq": self.next_seq(), "type": "event", "event": event, "body": body })
+    }
+
+    fn response(&mut self, request: &Value, result: Result<Value, String>) -> Value {
+        let mut response = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        response
+    }
+
+    fn stopped(&mut self, reason: &str) -> Value {
+        self.running = false;
+        if let Some(cpu) = &self.cpu {
+            cpu.set_halted(true);
+        }
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )
+    }
+
+    fn cpu(&self) -> Result<&CPUWrapper, String> {
+        self.cpu
+            .as_ref()
+            .ok_or_else(|| "No program loaded".to_string())
+    }
+
+    // Handle one incoming request, returning the response followed by any events
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or("").to_string();
+        let arguments = &request["arguments"];
+        let mut events = Vec::new();
+
+        let result = match command.as_str() {
+            "initialize" => {
+                events.push(self.event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsSteppingGranularity": false,
+                    "supportsTerminateRequest": true,
+                }))
+            }
+            "launch" => self.launch(arguments),
+            "attach" => match self.cpu {
+                Some(_) => Ok(json!({})),
+                None => Err("Nothing to attach to, use launch".to_string()),
+            },
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(self.stopped("entry"));
+                } else if let Ok(cpu) = self.cpu() {
+                    cpu.set_halted(false);
+                    self.running = true;
+                }
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "6502" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Flags", "variablesReference": FLAGS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ]})),
+            "variables" => self.variables(arguments),
+            "readMemory" => self.read_memory(arguments),
+            "source" => match self.cpu() {
+                Ok(cpu) => Ok(json!({ "content": cpu.source.borrow().clone() })),
+                Err(message) => Err(message),
+            },
+            "continue" => match self.cpu() {
+                Ok(cpu) => {
+                    cpu.set_halted(false);
+                    self.running = true;
+                    Ok(json!({ "allThreadsContinued": true }))
+                }
+                Err(message) => Err(message),
+            },
+            "next" | "stepIn" | "stepOut" => {
+                let result = self.step(&command);
+                if result.is_ok() {
+                    events.push(self.stopped("step"));
+                }
+                result
+            }
+            "pause" => {
+                events.push(self.stopped("pause"));
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                if let Some(cpu) = &self.cpu {
+                    cpu.set_halted(false);
+                    cpu.breakpoints.borrow_mut().clear();
+                }
+                self.running = false;
+                self.terminated = true;
+                events.push(self.event("terminated", json!({})));
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request '{}'", command)),
+        };
+
+        let mut messages = vec![self.response(request, result)];
+        messages.extend(events);
+        messages
+    }
+
+    // Report a breakpoint hit by the CPU since the last call
+    pub fn poll(&mut self) -> Vec<Value> {
+        let halted = self.cpu.as_ref().map(|cpu| cpu.is_halted());
+        match (self.running, halted) {
+            (true, Some(true)) => vec![self.stopped("breakpoint")],
+            _ => Vec::new(),
+        }
+    }
+
+    // Run the CPU for a while when the session owns it
+    pub fn run_chunk(&mut self) -> Vec<Value> {
+        if let (true, true, Some(cpu)) = (self.headless, self.running, &self.cpu) {
+            cpu.run_steps_async(RUN_CHUNK);
+        }
+        self.poll()
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        if !self.headless {
+            return Ok(json!({}));
+        }
+
+        let path = arguments["program"]
+            .as_str()
+            .ok_or_else(|| "Missing 'program' argument".to_string())?;
+        let source = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let output = asm6502::assemble_string(&source)
+            .map_err(|diagnostics| asm6502::describe(&diagnostics))?;
+        // Labels and line numbers are worked out from the .org origin, so load there by default
+        let load_address = arguments["loadAddress"]
+            .as_u64()
+            .map(|address| address as u16)
+            .unwrap_or(output.start_address);
+
+        let cpu = CPUWrapper::new(load_address, output.bytes, output.offset_to_line);
+        cpu.set_source(&source);
+        self.cpu = Some(cpu);
+        self.source_path = Some(path.to_string());
+        Ok(json!({}))
+    }
+
+    fn source(&self) -> Value {
+        match &self.source_path {
+            Some(path) => json!({ "path": path }),
+            None => json!({ "name": "ship.asm", "sourceReference": 1 }),
+        }
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let cpu = self.cpu()?;
+        let mut addresses = HashSet::new();
+        let mut breakpoints = Vec::new();
+
+        for breakpoint in arguments["breakpoints"].as_array().unwrap_or(&Vec::new()) {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as u32;
+            // DAP lines start at 1, the assembler counts from 0
+            let address = match line {
+                0 => None,
+                line => cpu.get_address_for_line(line - 1),
+            };
+            if let Some(address) = address {
+                addresses.insert(address);
+            }
+            breakpoints.push(json!({
+                "verified": address.is_some(),
+                "line": line,
+                "instructionReference": address.map(|a| format!("0x{:04X}", a)),
+            }));
+        }
+
+        *cpu.breakpoints.borrow_mut() = addresses;
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn frame(&self, id: i64, name: String, address: u16) -> Value {
+        let line = self
+            .cpu
+            .as_ref()
+            .and_then(|cpu| cpu.get_line_number(address))
+            .map(|line| line as i64 + 1)
+            .unwrap_or(0);
+        json!({
+            "id": id,
+            "name": name,
+            "source": self.source(),
+            "line": line,
+            "column": 1,
+            "instructionPointerReference": format!("0x{:04X}", address),
+        })
+    }
+
+    // The current PC, then every return address on the stack that points right after a JSR
+    fn stack_trace(&self) -> Result<Value, String> {
+        let cpu = self.cpu()?;
+        let (pc, sp) = {
+            let cpu = cpu.get_cpu();
+            let cpu = cpu.borrow();
+            (cpu.regs.pc, cpu.regs.s)
+        };
+
+        let mut frames = vec![self.frame(0, format!("${:04X}", pc), pc)];
+        let mut position = sp as u16 + 1;
+        while position < 0xFF {
+            let low = cpu.read_byte(0x0100 | position) as u16;
+            let high = cpu.read_byte(0x0100 | (position + 1)) as u16;
+            let call_site = (high << 8 | low).wrapping_sub(2);
+
+            if cpu.read_byte(call_site) == 0x20 {
+                let id = frames.len() as i64;
+                frames.push(self.frame(id, format!("JSR from ${:04X}", call_site), call_site));
+                position += 2;
+            } else {
+                position += 1;
+            }
+        }
+
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let cpu = self.cpu()?;
+        let reference = arguments["variablesReference"].as_i64().unwrap_or(0);
+        let (a, x, y, sp, pc, p) = {
+            let cpu = cpu.get_cpu();
+            let cpu = cpu.borrow();
+            (
+                cpu.regs.a,
+                cpu.regs.x,
+                cpu.regs.y,
+                cpu.regs.s,
+                cpu.regs.pc,
+                cpu.regs.p.bits(),
+            )
+        };
+
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let variables: Vec<Value> = match reference {
+            REGISTERS_REFERENCE => vec![
+                variable("A".to_string(), format!("${:02X}", a)),
+                variable("X".to_string(), format!("${:02X}", x)),
+                variable("Y".to_string(), format!("${:02X}", y)),
+                variable("SP".to_string(), format!("${:02X}", sp)),
+                variable("PC".to_string(), format!("${:04X}", pc)),
+                variable("P".to_string(), format!("%{:08b}", p)),
+            ],
+            FLAGS_REFERENCE => FLAG_NAMES
+                .iter()
+                .map(|(name, mask)| variable(name.to_string(), (p & mask != 0).to_string()))
+                .collect(),
+            STACK_REFERENCE => (sp as u16 + 1..=0xFF)
+                .map(|offset| {
+                    let address = 0x0100 | offset;
+                    variable(
+                        format!("${:04X}", address),
+                        format!("${:02X}", cpu.read_byte(address)),
+                    )
+                })
+                .collect(),
+            _ => return Err(format!("Unknown variables reference {}", reference)),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let cpu = self.cpu()?;
+        let reference = arguments["memoryReference"].as_str().unwrap_or("");
+        let base = parse_address(reference)
+            .ok_or_else(|| format!("Invalid memory reference '{}'", reference))?;
+        let offset = arguments["offset"].as_i64().unwrap_or(0);
+        let count = arguments["count"].as_u64().unwrap_or(0).min(0x10000) as usize;
+
+        let address = (base as i64 + offset).clamp(0, 0xFFFF) as u16;
+        let count = count.min(0x10000 - address as usize);
+        let data: Vec<u8> = (0..count)
+            .map(|i| cpu.read_byte(address.wrapping_add(i as u16)))
+            .collect();
+
+        Ok(json!({
+            "address": format!("0x{:04X}", address),
+            "data": base64_encode(&data),
+        }))
+    }
+
+    fn step(&mut self, command: &str) -> Result<Value, String> {
+        let cpu = self.cpu()?;
+        let (pc, sp) = {
+            let cpu = cpu.get_cpu();
+            let cpu = cpu.borrow();
+            (cpu.regs.pc, cpu.regs.s)
+        };
+
+        match command {
+            // Step over a JSR by running until it returns
+            "next" if cpu.read_byte(pc) == 0x20 => {
+                let return_to = pc.wrapping_add(3);
+                run_until(cpu, |pc, _| pc == return_to)
+            }
+            // Run until the stack unwinds past the current routine's return address
+            "stepOut" => run_until(cpu, |_, s| s > sp.wrapping_add(1)),
+            _ => {
+                cpu.run_step();
+            }
+        }
+
+        self.running = false;
+        Ok(json!({}))
+    }
+}
+
+fn run_until(cpu: &CPUWrapper, done: impl Fn(u16, u8) -> bool) {
+    let mut elapsed = 0;
+    while elapsed < STEP_OVER_LIMIT {
+        elapsed += cpu.run_step();
+
+        let (pc, sp) = {
+            let cpu = cpu.get_cpu();
+            let cpu = cpu.borrow();
+            (cpu.regs.pc, cpu.regs.s)
+        };
+        if done(pc, sp) || cpu.breakpoints.borrow().contains(&pc) {
+            return;
+        }
+    }
+}
+
+// Run a headless session over any byte stream until the client disconnects
+pub fn serve<R, W>(reader: R, mut writer: W) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+    W: Write,
+{
+    // Requests are read on a separate thread so a running program can still be paused
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = reader;
+        let mut buffer = MessageBuffer::default();
+        let mut chunk = [0u8; 4096];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => buffer.feed(&chunk[..n]),
+            }
+            while let Some(message) = buffer.next_message() {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut session = DapSession::headless();
+    while !session.is_terminated() {
+        let request = match session.is_running() {
+            true => match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            },
+            false => match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            },
+        };
+
+        let messages = match request {
+            Some(request) => session.handle(&request),
+            None => session.run_chunk(),
+        };
+        for message in messages {
+            writer.write_all(&encode_message(&message))?;
+        }
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+pub fn serve_stdio() -> io::Result<()> {
+    serve(io::stdin(), io::stdout())
+}
+
+pub fn serve_tcp(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let (stream, _) = listener.accept()?;
+    serve(stream.try_clone()?, stream)
+}
+
+// Non-blocking server attached to a CPU that the game keeps running
+pub struct DapListener {
+    listener: TcpListener,
+    cpu: CPUWrapper,
+    client: Option<(TcpStream, MessageBuffer, DapSession)>,
+}
+
+impl DapListener {
+    pub fn bind(port: u16, cpu: CPUWrapper) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            cpu,
+            client: None,
+        })
+    }
+
+    pub fn poll(&mut self) {
+        if self.client.is_none() {
+            if let Ok((stream, _)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    let session = DapSession::attached(self.cpu.clone());
+                    self.client = Some((stream, MessageBuffer::default(), session));
+                }
+            }
+        }
+
+        let Some((stream, buffer, session)) = &mut self.client else {
+            return;
+        };
+
+        let mut closed = false;
+        let mut chunk = [0u8; 4096];
+        loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(n) => buffer.feed(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut messages = Vec::new();
+        while let Some(request) = buffer.next_message() {
+            messages.extend(session.handle(&request));
+        }
+        messages.extend(session.poll());
+
+        for message in messages {
+            if stream.write_all(&encode_message(&message)).is_err() {
+                closed = true;
+            }
+        }
+
+        if closed || session.is_terminated() {
+            // Never leave the ship frozen once the debugger is gone
+            self.cpu.set_halted(false);
+            self.cpu.breakpoints.borrow_mut().clear();
+            self.client = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = ".org $0600\nstart:\n  LDX #$03\nloop:\n  DEX\n  BNE loop\n  BRK\n";
+
+    fn request(seq: i64, command: &str, arguments: Value) -> Value {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    fn attached_session() -> (DapSession, CPUWrapper) {
+        let output = asm6502::assemble_string(SOURCE).unwrap();
+        let cpu = CPUWrapper::new(0x0600, output.bytes, output.offset_to_line);
+        (DapSession::attached(cpu.clone()), cpu)
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let message = json!({ "seq": 1, "type": "request", "command": "threads" });
+        let encoded = encode_message(&message);
+
+        let mut buffer = MessageBuffer::default();
+        buffer.feed(&encoded[..10]);
+        assert_eq!(buffer.next_message(), None);
+        buffer.feed(&encoded[10..]);
+        assert_eq!(buffer.next_message(), Some(message));
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_encode(&[0xFF]), "/w==");
+    }
+
+    #[test]
+    fn test_breakpoint_stops_cpu() {
+        let (mut session, cpu) = attached_session();
+        let response = &session.handle(&request(
+            1,
+            "setBreakpoints",
+            json!({ "breakpoints": [{ "line": 5 }] }),
+        ))[0];
+        assert_eq!(response["body"]["breakpoints"][0]["verified"], true);
+
+        session.handle(&request(2, "configurationDone", json!({})));
+        cpu.run_steps_async(1000);
+
+        let events = session.poll();
+        assert_eq!(events[0]["event"], "stopped");
+        assert_eq!(events[0]["body"]["reason"], "breakpoint");
+        assert_eq!(cpu.get_cpu().borrow().regs.pc, 0x0602);
+    }
+
+    #[test]
+    fn test_registers_and_step() {
+        let (mut session, cpu) = attached_session();
+        let messages = session.handle(&request(1, "next", json!({})));
+        assert_eq!(messages[1]["body"]["reason"], "step");
+        assert_eq!(cpu.get_cpu().borrow().regs.pc, 0x0602);
+
+        let response = &session.handle(&request(
+            2,
+            "variables",
+            json!({ "variablesReference": REGISTERS_REFERENCE }),
+        ))[0];
+        assert_eq!(response["body"]["variables"][1]["value"], "$03");
+    }
+
+    #[test]
+    fn test_launch_at_origin() {
+        let path = std::env::temp_dir().join("spess-dap-launch-at-origin.asm");
+        std::fs::write(&path, SOURCE.replace("$0600", "$0800")).unwrap();
+
+        let mut session = DapSession::headless();
+        let arguments = json!({ "program": path.to_str().unwrap(), "stopOnEntry": true });
+        session.handle(&request(1, "launch", arguments));
+        let response = &session.handle(&request(
+            2,
+            "setBreakpoints",
+            json!({ "breakpoints": [{ "line": 5 }] }),
+        ))[0];
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            response["body"]["breakpoints"][0]["instructionReference"],
+            "0x0802"
+        );
+        let cpu = session.cpu().unwrap();
+        assert_eq!(cpu.get_cpu().borrow().regs.pc, 0x0800);
+    }
+}
@@ -0,0 +1,145 @@
+// Which memory-mapped register an instruction touches.
+//
+// Devices only see memory between instructions, which is not enough for registers with side
+// effects: writing the value a register already holds looks like no write at all, and reads are
+// invisible. Decoding the effective address of the instruction about to run fills that gap.
+// Only documented NMOS opcodes are decoded, and only their data access (not the stack or vectors).
+
+use rv6502emu::cpu::Cpu;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub address: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    IndirectX,
+    IndirectY,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Read,
+    Write,
+    Modify,
+}
+
+fn decode(opcode: u8) -> Option<(Mode, Kind)> {
+    let (aaa, bbb, cc) = (opcode >> 5, (opcode >> 2) & 0b111, opcode & 0b11);
+
+    match cc {
+        // ORA AND EOR ADC STA LDA CMP SBC
+        0b01 => {
+            let mode = match bbb {
+                0b000 => Mode::IndirectX,
+                0b001 => Mode::ZeroPage,
+                0b011 => Mode::Absolute,
+                0b100 => Mode::IndirectY,
+                0b101 => Mode::ZeroPageX,
+                0b110 => Mode::AbsoluteY,
+                0b111 => Mode::AbsoluteX,
+                _ => return None,
+            };
+            let kind = match aaa {
+                0b100 => Kind::Write,
+                _ => Kind::Read,
+            };
+            Some((mode, kind))
+        }
+        // ASL ROL LSR ROR STX LDX DEC INC
+        0b10 => {
+            let (kind, indexed_by_y) = match aaa {
+                0b100 => (Kind::Write, true),
+                0b101 => (Kind::Read, true),
+                _ => (Kind::Modify, false),
+            };
+            let mode = match (bbb, indexed_by_y) {
+                (0b001, _) => Mode::ZeroPage,
+                (0b011, _) => Mode::Absolute,
+                (0b101, false) => Mode::ZeroPageX,
+                (0b101, true) => Mode::ZeroPageY,
+                (0b111, false) => Mode::AbsoluteX,
+                (0b111, true) if aaa == 0b101 => Mode::AbsoluteY,
+                _ => return None,
+            };
+            Some((mode, kind))
+        }
+        // BIT STY LDY CPY CPX
+        0b00 => match opcode {
+            0x24 | 0xA4 | 0xC4 | 0xE4 => Some((Mode::ZeroPage, Kind::Read)),
+            0x2C | 0xAC | 0xCC | 0xEC => Some((Mode::Absolute, Kind::Read)),
+            0xB4 => Some((Mode::ZeroPageX, Kind::Read)),
+            0xBC => Some((Mode::AbsoluteX, Kind::Read)),
+            0x84 => Some((Mode::ZeroPage, Kind::Write)),
+            0x8C => Some((Mode::Absolute, Kind::Write)),
+            0x94 => Some((Mode::ZeroPageX, Kind::Write)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn read(cpu: &mut Cpu, address: u16) -> u8 {
+    cpu.bus
+        .get_memory()
+        .read_byte(address as usize)
+        .unwrap_or(0)
+}
+
+fn read_zero_page_word(cpu: &mut Cpu, address: u8) -> u16 {
+    read(cpu, address as u16) as u16 | (read(cpu, address.wrapping_add(1) as u16) as u16) << 8
+}
+
+// Data access of the instruction at PC, to be called before it executes
+pub fn next_access(cpu: &mut Cpu) -> Option<Access> {
+    let pc = cpu.regs.pc;
+    let (mode, kind) = decode(read(cpu, pc))?;
+    let low = read(cpu, pc.wrapping_add(1));
+    let word = low as u16 | (read(cpu, pc.wrapping_add(2)) as u16) << 8;
+    let (x, y) = (cpu.regs.x, cpu.regs.y);
+
+    let address = match mode {
+        Mode::ZeroPage => low as u16,
+        Mode::ZeroPageX => low.wrapping_add(x) as u16,
+        Mode::ZeroPageY => low.wrapping_add(y) as u16,
+        Mode::Absolute => word,
+        Mode::AbsoluteX => word.wrapping_add(x as u16),
+        Mode::AbsoluteY => word.wrapping_add(y as u16),
+        Mode::IndirectX => read_zero_page_word(cpu, low.wrapping_add(x)),
+        Mode::IndirectY => read_zero_page_word(cpu, low).wrapping_add(y as u16),
+    };
+
+    Some(Access {
+        address,
+        read: kind != Kind::Write,
+        write: kind != Kind::Read,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(0x8D), Some((Mode::Absolute, Kind::Write))); // STA abs
+        assert_eq!(decode(0xB1), Some((Mode::IndirectY, Kind::Read))); // LDA (zp),Y
+        assert_eq!(decode(0xBE), Some((Mode::AbsoluteY, Kind::Read))); // LDX abs,Y
+        assert_eq!(decode(0x96), Some((Mode::ZeroPageY, Kind::Write))); // STX zp,Y
+        assert_eq!(decode(0xFE), Some((Mode::AbsoluteX, Kind::Modify))); // INC abs,X
+        assert_eq!(decode(0x2C), Some((Mode::Absolute, Kind::Read))); // BIT abs
+        assert_eq!(decode(0xA9), None); // LDA #
+        assert_eq!(decode(0x0A), None); // ASL A
+        assert_eq!(decode(0x4C), None); // JMP abs
+        assert_eq!(decode(0x9E), None); // SHX, undocumented
+    }
+}
@@ -0,0 +1,273 @@
+// MOS 6551 ACIA, the serial port behind the ship's antenna.
+//
+// Bytes written to DATA are shifted out at the configured baud rate into a FIFO Godot pops,
+// bytes Godot pushes are shifted in one at a time and wait in DATA until the program reads it.
+// The line has implicit flow control: nothing is received while DATA is still full, so the
+// overrun, framing and parity error bits always read 0. Modem lines are not emulated.
+
+use std::any::Any;
+use std::collections::VecDeque;
+
+use super::RegisterAccess::ReadWrite;
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const ACIA_DATA: u16 = 0x0; // write: transmit, read: last received byte
+pub const ACIA_STATUS: u16 = 0x1; // write: programmed reset
+pub const ACIA_COMMAND: u16 = 0x2;
+pub const ACIA_CONTROL: u16 = 0x3;
+pub const ACIA_SIZE: u16 = 4;
+
+pub const ACIA_STATUS_IRQ: u8 = 0b1000_0000;
+pub const ACIA_STATUS_TDRE: u8 = 0b0001_0000; // transmit data register empty
+pub const ACIA_STATUS_RDRF: u8 = 0b0000_1000; // receive data register full
+
+pub const ACIA_COMMAND_DTR: u8 = 0b0000_0001; // enable the receiver and interrupts
+pub const ACIA_COMMAND_RX_IRQ_DISABLE: u8 = 0b0000_0010;
+pub const ACIA_COMMAND_TX_MASK: u8 = 0b0000_1100;
+pub const ACIA_COMMAND_TX_IRQ: u8 = 0b0000_0100;
+pub const ACIA_COMMAND_PARITY: u8 = 0b0010_0000;
+
+// Baud rates selected by the low nibble of CONTROL, 0 (external clock) uses the fastest rate
+pub const ACIA_BAUD_RATES: [u32; 16] = [
+    115200, 50, 75, 110, 135, 150, 300, 600, 1200, 1800, 2400, 3600, 4800, 7200, 9600, 19200,
+];
+
+// Bytes kept in each direction before the oldest are dropped
+pub const ACIA_FIFO_CAPACITY: usize = 4096;
+
+pub struct Acia {
+    base_address: u16,
+    clock: u32, // CPU cycles per emulated second
+    initialized: bool,
+    receive_data: Option<u8>,
+    transmit_data: Option<u8>,
+    shifting_out: Option<(u8, u32)>, // byte being transmitted, cycles left
+    shifting_in: Option<(u8, u32)>,
+    incoming: VecDeque<u8>, // pushed by Godot
+    outgoing: VecDeque<u8>, // popped by Godot
+}
+
+impl Acia {
+    pub fn new(base_address: u16, clock: u32) -> Self {
+        Self {
+            base_address,
+            clock,
+            initialized: false,
+            receive_data: None,
+            transmit_data: None,
+            shifting_out: None,
+            shifting_in: None,
+            incoming: VecDeque::new(),
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: u32) {
+        self.clock = clock;
+    }
+
+    pub fn push_received(&mut self, bytes: &[u8]) {
+        self.incoming.extend(bytes);
+        while self.incoming.len() > ACIA_FIFO_CAPACITY {
+            self.incoming.pop_front();
+        }
+    }
+
+    pub fn pop_transmitted(&mut self) -> Vec<u8> {
+        self.outgoing.drain(..).collect()
+    }
+
+    // Cycles to shift one frame: start bit, data bits, optional parity and stop bits
+    fn frame_cycles(&self, command: u8, control: u8) -> u32 {
+        let data_bits = 8 - ((control >> 5) & 0b11) as u32;
+        let stop_bits = 1 + (control >> 7) as u32;
+        let parity_bits = (command & ACIA_COMMAND_PARITY != 0) as u32;
+        let frame_bits = 1 + data_bits + parity_bits + stop_bits;
+        let baud = ACIA_BAUD_RATES[(control & 0x0F) as usize];
+        (self.clock as u64 * frame_bits as u64 / baud as u64).max(1) as u32
+    }
+}
+
+impl ShipDevice for Acia {
+    fn name(&self) -> &str {
+        "acia"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        ACIA_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("DATA", ACIA_DATA, 1, ReadWrite),
+            Register::new("STATUS", ACIA_STATUS, 1, ReadWrite).with_fields(&[
+                BitField("IRQ", ACIA_STATUS_IRQ),
+                BitField("TDRE", ACIA_STATUS_TDRE),
+                BitField("RDRF", ACIA_STATUS_RDRF),
+            ]),
+            Register::new("COMMAND", ACIA_COMMAND, 1, ReadWrite).with_fields(&[
+                BitField("DTR", ACIA_COMMAND_DTR),
+                BitField("RX_IRQ_DISABLE", ACIA_COMMAND_RX_IRQ_DISABLE),
+                BitField("TX", ACIA_COMMAND_TX_MASK),
+                BitField("PARITY", ACIA_COMMAND_PARITY),
+            ]),
+            Register::new("CONTROL", ACIA_CONTROL, 1, ReadWrite),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+
+        // Hardware reset values: receiver interrupts disabled, 115200 baud
+        if !self.initialized {
+            bus.write(base + ACIA_COMMAND, ACIA_COMMAND_RX_IRQ_DISABLE);
+            bus.write(base + ACIA_CONTROL, 0);
+            self.initialized = true;
+        }
+
+        if bus.was_written(base + ACIA_DATA) {
+            self.transmit_data = Some(bus.read(base + ACIA_DATA));
+        }
+        if bus.was_read(base + ACIA_DATA) {
+            self.receive_data = None;
+        }
+        if bus.was_written(base + ACIA_STATUS) {
+            let command = bus.read(base + ACIA_COMMAND);
+            bus.write(base + ACIA_COMMAND, command & 0b1110_0000);
+        }
+
+        let command = bus.read(base + ACIA_COMMAND);
+        let control = bus.read(base + ACIA_CONTROL);
+        let frame_cycles = self.frame_cycles(command, control);
+
+        // Transmitter
+        if let Some((byte, left)) = self.shifting_out {
+            match left.checked_sub(cycles) {
+                Some(left) if left > 0 => self.shifting_out = Some((byte, left)),
+                _ => {
+                    self.outgoing.push_back(byte);
+                    if self.outgoing.len() > ACIA_FIFO_CAPACITY {
+                        self.outgoing.pop_front();
+                    }
+                    self.shifting_out = None;
+                }
+            }
+        }
+        if self.shifting_out.is_none() {
+            if let Some(byte) = self.transmit_data.take() {
+                self.shifting_out = Some((byte, frame_cycles));
+            }
+        }
+
+        // Receiver
+        if command & ACIA_COMMAND_DTR != 0 {
+            if let Some((byte, left)) = self.shifting_in {
+                match left.checked_sub(cycles) {
+                    Some(left) if left > 0 => self.shifting_in = Some((byte, left)),
+                    _ if self.receive_data.is_none() => {
+                        self.receive_data = Some(byte);
+                        self.shifting_in = None;
+                    }
+                    _ => self.shifting_in = Some((byte, 0)),
+                }
+            }
+            if self.shifting_in.is_none() {
+                if let Some(byte) = self.incoming.pop_front() {
+                    self.shifting_in = Some((byte, frame_cycles));
+                }
+            }
+        }
+
+        let mut status = 0;
+        if self.transmit_data.is_none() {
+            status |= ACIA_STATUS_TDRE;
+        }
+        if self.receive_data.is_some() {
+            status |= ACIA_STATUS_RDRF;
+        }
+        let rx_irq = status & ACIA_STATUS_RDRF != 0 && command & ACIA_COMMAND_RX_IRQ_DISABLE == 0;
+        let tx_irq =
+            status & ACIA_STATUS_TDRE != 0 && command & ACIA_COMMAND_TX_MASK == ACIA_COMMAND_TX_IRQ;
+        if command & ACIA_COMMAND_DTR != 0 && (rx_irq || tx_irq) {
+            status |= ACIA_STATUS_IRQ;
+            bus.irq();
+        }
+
+        bus.write(base + ACIA_STATUS, status);
+        if let Some(byte) = self.receive_data {
+            bus.write(base + ACIA_DATA, byte);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_wrapper::CPUWrapper;
+    use crate::testing::device_cpu;
+
+    const BASE: u16 = 0x0215;
+
+    // Echoes every received byte back, 1200 baud 8N1 on a 1 MHz clock
+    const ECHO: &str = "
+.org $0600
+    LDA #$08
+    STA $0218 ; CONTROL: 1200 baud
+    LDA #$0B
+    STA $0217 ; COMMAND: DTR, no interrupts
+wait:
+    LDA $0216
+    AND #$08
+    BEQ wait
+    LDA $0215
+    STA $0215
+    JMP wait
+";
+
+    #[test]
+    fn test_echo_at_baud_rate() {
+        let (cpu, _) = device_cpu(ECHO, Box::new(Acia::new(BASE, 1_000_000)));
+        cpu.with_device("acia", |acia: &mut Acia| acia.push_received(b"hi"));
+
+        // 10 bits per byte at 1200 baud is about 8333 cycles
+        cpu.run_cycles(10_000);
+        let sent = cpu.with_device("acia", |acia: &mut Acia| acia.pop_transmitted());
+        assert_eq!(sent, Some(vec![]));
+
+        cpu.run_cycles(30_000);
+        let sent = cpu.with_device("acia", |acia: &mut Acia| acia.pop_transmitted());
+        assert_eq!(sent, Some(b"hi".to_vec()));
+    }
+
+    #[test]
+    fn test_receive_irq() {
+        let cpu = CPUWrapper::new(0x0600, vec![0x58, 0x4C, 0x01, 0x06], Default::default()); // CLI; JMP *
+        cpu.write_byte(0xFFFE, 0x00);
+        cpu.write_byte(0xFFFF, 0x07);
+        cpu.write_byte(0x0700, 0x40); // RTI
+        cpu.attach_device(Box::new(Acia::new(BASE, 1_000_000)))
+            .unwrap();
+        cpu.run_step();
+        cpu.write_byte(BASE + ACIA_CONTROL, 0x0F); // 19200 baud
+        cpu.write_byte(BASE + ACIA_COMMAND, ACIA_COMMAND_DTR);
+        cpu.with_device("acia", |acia: &mut Acia| acia.push_received(&[0x55]));
+
+        let mut entered = false;
+        for _ in 0..500 {
+            cpu.run_step();
+            entered |= cpu.get_cpu().borrow().regs.pc == 0x0700;
+        }
+        assert!(entered);
+        assert_eq!(cpu.read_byte(BASE + ACIA_DATA), 0x55);
+        assert_ne!(cpu.read_byte(BASE + ACIA_STATUS) & ACIA_STATUS_IRQ, 0);
+    }
+}
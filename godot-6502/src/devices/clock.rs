@@ -0,0 +1,196 @@
+// Mission clock: elapsed time in milliseconds and in CPU cycles since the clock was attached.
+//
+// The counters are only copied into the registers when the program writes LATCH, so a multi-byte
+// value cannot change halfway through reading it. The alarm raises an IRQ once the mission time
+// reaches ALARM, and fires once per value written to ALARM.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const CLOCK_LATCH: u16 = 0x00; // write anything to latch MET and CYCLES
+pub const CLOCK_MET: u16 = 0x01; // 32 bit, milliseconds
+pub const CLOCK_CYCLES: u16 = 0x05; // 32 bit, wraps
+pub const CLOCK_ALARM: u16 = 0x09; // 32 bit, milliseconds
+pub const CLOCK_ALARM_CONTROL: u16 = 0x0D;
+pub const CLOCK_SIZE: u16 = 0x0E;
+
+pub const CLOCK_ALARM_ENABLE: u8 = 0b0000_0001;
+pub const CLOCK_ALARM_FIRED: u8 = 0b1000_0000; // set by the clock, write the register with bit 7 clear to acknowledge
+
+pub struct Clock {
+    base_address: u16,
+    frequency: u32, // CPU cycles per emulated second
+    cycles: u64,
+    milliseconds: u64,
+    remainder: u64, // cycles * 1000 not yet counted as a millisecond
+    alarm_armed: bool,
+}
+
+impl Clock {
+    pub fn new(base_address: u16, frequency: u32) -> Self {
+        Self {
+            base_address,
+            frequency,
+            cycles: 0,
+            milliseconds: 0,
+            remainder: 0,
+            alarm_armed: false,
+        }
+    }
+
+    // Later cycles count at the new rate, time already elapsed is kept
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.frequency = frequency;
+    }
+
+    pub fn milliseconds(&self) -> u64 {
+        self.milliseconds
+    }
+}
+
+fn write_long(bus: &mut DeviceBus, address: u16, value: u32) {
+    bus.write_word(address, value as u16);
+    bus.write_word(address + 2, (value >> 16) as u16);
+}
+
+fn read_long(bus: &mut DeviceBus, address: u16) -> u32 {
+    bus.read_word(address) as u32 | (bus.read_word(address + 2) as u32) << 16
+}
+
+impl ShipDevice for Clock {
+    fn name(&self) -> &str {
+        "clock"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        CLOCK_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("LATCH", CLOCK_LATCH, 1, WriteOnly),
+            Register::new("MET", CLOCK_MET, 4, ReadOnly),
+            Register::new("CYCLES", CLOCK_CYCLES, 4, ReadOnly),
+            Register::new("ALARM", CLOCK_ALARM, 4, ReadWrite),
+            Register::new("ALARM_CONTROL", CLOCK_ALARM_CONTROL, 1, ReadWrite).with_fields(&[
+                BitField("ENABLE", CLOCK_ALARM_ENABLE),
+                BitField("FIRED", CLOCK_ALARM_FIRED),
+            ]),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+
+        self.cycles += cycles as u64;
+        self.remainder += cycles as u64 * 1000;
+        let frequency = self.frequency.max(1) as u64;
+        self.milliseconds += self.remainder / frequency;
+        self.remainder %= frequency;
+
+        if bus.was_written(base + CLOCK_LATCH) {
+            write_long(bus, base + CLOCK_MET, self.milliseconds as u32);
+            write_long(bus, base + CLOCK_CYCLES, self.cycles as u32);
+        }
+
+        if (0..4).any(|i| bus.was_written(base + CLOCK_ALARM + i)) {
+            self.alarm_armed = true;
+        }
+
+        let control = bus.read(base + CLOCK_ALARM_CONTROL);
+        if control & CLOCK_ALARM_ENABLE != 0
+            && self.alarm_armed
+            && self.milliseconds >= read_long(bus, base + CLOCK_ALARM) as u64
+        {
+            self.alarm_armed = false;
+            bus.write(base + CLOCK_ALARM_CONTROL, control | CLOCK_ALARM_FIRED);
+        }
+
+        let control = bus.read(base + CLOCK_ALARM_CONTROL);
+        if control & CLOCK_ALARM_ENABLE != 0 && control & CLOCK_ALARM_FIRED != 0 {
+            bus.irq();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm6502::AssemblyOutput;
+    use crate::cpu_wrapper::CPUWrapper;
+    use crate::testing::device_cpu;
+
+    // 10 kHz clock, so a millisecond is 10 cycles
+    fn cpu(source: &str) -> (CPUWrapper, AssemblyOutput) {
+        device_cpu(source, Box::new(Clock::new(0x0290, 10_000)))
+    }
+
+    #[test]
+    fn test_latch() {
+        let (cpu, _) = cpu("
+.org $0600
+loop:
+    JMP loop
+latch:
+    STA $0290
+    JMP latch
+");
+        cpu.run_cycles(1_000);
+        assert_eq!(cpu.read_byte(0x0291), 0, "counters only move on a latch");
+
+        cpu.get_cpu().borrow_mut().regs.pc = 0x0603;
+        cpu.run_step();
+        let met = (0..4)
+            .map(|i| (cpu.read_byte(0x0291 + i) as u32) << (8 * i))
+            .sum::<u32>();
+        let cycles = (0..4)
+            .map(|i| (cpu.read_byte(0x0295 + i) as u32) << (8 * i))
+            .sum::<u32>();
+        assert_eq!(met, cycles / 10);
+        assert!((1_000..1_010).contains(&cycles));
+    }
+
+    #[test]
+    fn test_alarm_irq() {
+        let (cpu, output) = cpu("
+.org $0600
+    LDA #$64
+    STA $0299 ; alarm at 100 ms
+    LDA #$01
+    STA $029D ; enable
+    CLI
+loop:
+    JMP loop
+irq:
+    LDA #$01
+    STA $029D ; acknowledge
+    LDA $0600
+    STA $10
+    RTI
+");
+        let irq = output.symbols["irq"];
+        cpu.write_byte(0xFFFE, irq as u8);
+        cpu.write_byte(0xFFFF, (irq >> 8) as u8);
+
+        cpu.run_cycles(990);
+        assert_eq!(cpu.read_byte(0x10), 0);
+        cpu.run_cycles(30);
+        assert_eq!(cpu.read_byte(0x10), 0xA9);
+
+        // fires once per alarm
+        cpu.write_byte(0x10, 0);
+        cpu.run_cycles(2_000);
+        assert_eq!(cpu.read_byte(0x10), 0);
+    }
+}
@@ -0,0 +1,304 @@
+// Bitmap display: a framebuffer in memory, shown through a palette of 16 colors.
+//
+// The framebuffer follows the registers, one row after the other, with the leftmost pixel of each
+// byte in its highest bits. Rows start on a byte boundary. DISPLAY_REFRESH_HZ times per emulated
+// second the display scans out a frame: it sets VSYNC in STATUS, raises an IRQ when enabled, and
+// renders the framebuffer again if it or the palette changed since the last frame. Godot only has
+// to upload the pixels of frames that were rendered.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const DISPLAY_CONTROL: u16 = 0x00;
+pub const DISPLAY_STATUS: u16 = 0x01;
+pub const DISPLAY_WIDTH: u16 = 0x02; // pixels
+pub const DISPLAY_HEIGHT: u16 = 0x03;
+pub const DISPLAY_DEPTH: u16 = 0x04; // bits per pixel
+pub const DISPLAY_FRAME: u16 = 0x05; // frames scanned out, wraps
+pub const DISPLAY_PALETTE: u16 = 0x10; // 16 colors of 3 bytes: red, green, blue
+pub const DISPLAY_FRAMEBUFFER: u16 = 0x40;
+
+pub const DISPLAY_COLORS: usize = 16;
+
+pub const DISPLAY_CONTROL_VSYNC_IRQ: u8 = 0b0000_0001;
+pub const DISPLAY_STATUS_VSYNC: u8 = 0b1000_0000; // write the register with bit 7 clear to acknowledge
+
+pub const DISPLAY_REFRESH_HZ: u32 = 60;
+
+// The palette after power on, the colors of the C64 with black and white first
+pub const DEFAULT_PALETTE: [[u8; 3]; DISPLAY_COLORS] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0x88, 0x00, 0x00],
+    [0xAA, 0xFF, 0xEE],
+    [0xCC, 0x44, 0xCC],
+    [0x00, 0xCC, 0x55],
+    [0x00, 0x00, 0xAA],
+    [0xEE, 0xEE, 0x77],
+    [0xDD, 0x88, 0x55],
+    [0x66, 0x44, 0x00],
+    [0xFF, 0x77, 0x77],
+    [0x33, 0x33, 0x33],
+    [0x77, 0x77, 0x77],
+    [0xAA, 0xFF, 0x66],
+    [0x00, 0x88, 0xFF],
+    [0xBB, 0xBB, 0xBB],
+];
+
+pub fn valid_depth(depth: u8) -> bool {
+    matches!(depth, 1 | 2 | 4)
+}
+
+pub struct Display {
+    base_address: u16,
+    width: u8,
+    height: u8,
+    depth: u8,
+    frequency: u32, // CPU cycles per emulated second
+    cycles: u32,    // since the last frame
+    powered: bool,
+    scanned: Vec<u8>, // palette and framebuffer as of the last rendered frame
+    pixels: Vec<u8>,  // RGBA8
+    dirty: bool,
+}
+
+impl Display {
+    // `depth` has to pass valid_depth
+    pub fn new(base_address: u16, width: u8, height: u8, depth: u8, frequency: u32) -> Self {
+        Self {
+            base_address,
+            width,
+            height,
+            depth,
+            frequency,
+            cycles: 0,
+            powered: false,
+            scanned: Vec::new(),
+            pixels: [0, 0, 0, 0xFF].repeat(width as usize * height as usize),
+            dirty: true,
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.frequency = frequency;
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    fn row_bytes(&self) -> usize {
+        (self.width as usize * self.depth as usize).div_ceil(8)
+    }
+
+    fn framebuffer_size(&self) -> u16 {
+        (self.row_bytes() * self.height as usize) as u16
+    }
+
+    // The frame as RGBA8 pixels if it changed since the last call
+    pub fn take_frame(&mut self) -> Option<Vec<u8>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(self.pixels.clone())
+    }
+
+    fn power_on(&mut self, bus: &mut DeviceBus) {
+        let base = self.base_address;
+        for (i, color) in DEFAULT_PALETTE.iter().flatten().enumerate() {
+            bus.write(base + DISPLAY_PALETTE + i as u16, *color);
+        }
+        self.powered = true;
+    }
+
+    fn scan_out(&mut self, bus: &mut DeviceBus) {
+        let base = self.base_address;
+        bus.write(base + DISPLAY_WIDTH, self.width);
+        bus.write(base + DISPLAY_HEIGHT, self.height);
+        bus.write(base + DISPLAY_DEPTH, self.depth);
+        let frame = bus.read(base + DISPLAY_FRAME);
+        bus.write(base + DISPLAY_FRAME, frame.wrapping_add(1));
+        let status = bus.read(base + DISPLAY_STATUS);
+        bus.write(base + DISPLAY_STATUS, status | DISPLAY_STATUS_VSYNC);
+
+        let end = DISPLAY_FRAMEBUFFER + self.framebuffer_size();
+        let scanned: Vec<u8> = (DISPLAY_PALETTE..end)
+            .map(|offset| bus.read(base + offset))
+            .collect();
+        if scanned != self.scanned {
+            self.scanned = scanned;
+            self.render();
+        }
+    }
+
+    fn render(&mut self) {
+        let (palette, framebuffer) = self
+            .scanned
+            .split_at((DISPLAY_FRAMEBUFFER - DISPLAY_PALETTE) as usize);
+        let depth = self.depth as usize;
+        let mask = (1u8 << depth) - 1;
+        let row_bytes = self.row_bytes();
+
+        for y in 0..self.height as usize {
+            for x in 0..self.width as usize {
+                let bit = x * depth;
+                let byte = framebuffer[y * row_bytes + bit / 8];
+                let index = (byte >> (8 - depth - bit % 8)) & mask;
+                let color = &palette[index as usize * 3..index as usize * 3 + 3];
+                let pixel = (y * self.width as usize + x) * 4;
+                self.pixels[pixel..pixel + 3].copy_from_slice(color);
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+impl ShipDevice for Display {
+    fn name(&self) -> &str {
+        "display"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        DISPLAY_FRAMEBUFFER + self.framebuffer_size()
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("CONTROL", DISPLAY_CONTROL, 1, ReadWrite)
+                .with_fields(&[BitField("VSYNC_IRQ", DISPLAY_CONTROL_VSYNC_IRQ)]),
+            Register::new("STATUS", DISPLAY_STATUS, 1, ReadWrite)
+                .with_fields(&[BitField("VSYNC", DISPLAY_STATUS_VSYNC)]),
+            Register::new("WIDTH", DISPLAY_WIDTH, 1, ReadOnly),
+            Register::new("HEIGHT", DISPLAY_HEIGHT, 1, ReadOnly),
+            Register::new("DEPTH", DISPLAY_DEPTH, 1, ReadOnly),
+            Register::new("FRAME", DISPLAY_FRAME, 1, ReadOnly),
+            Register::new(
+                "PALETTE",
+                DISPLAY_PALETTE,
+                DISPLAY_COLORS as u16 * 3,
+                ReadWrite,
+            ),
+            Register::new(
+                "FRAMEBUFFER",
+                DISPLAY_FRAMEBUFFER,
+                self.framebuffer_size(),
+                ReadWrite,
+            ),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+        if !self.powered {
+            self.power_on(bus);
+            self.scan_out(bus);
+        }
+
+        self.cycles += cycles;
+        let period = (self.frequency / DISPLAY_REFRESH_HZ).max(1);
+        if self.cycles >= period {
+            self.cycles %= period;
+            self.scan_out(bus);
+        }
+
+        let control = bus.read(base + DISPLAY_CONTROL);
+        let status = bus.read(base + DISPLAY_STATUS);
+        if control & DISPLAY_CONTROL_VSYNC_IRQ != 0 && status & DISPLAY_STATUS_VSYNC != 0 {
+            bus.irq();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm6502::AssemblyOutput;
+    use crate::cpu_wrapper::CPUWrapper;
+    use crate::testing::device_cpu;
+
+    const BASE: u16 = 0x1000;
+
+    // 6 kHz, a frame every 100 cycles
+    fn cpu(source: &str) -> (CPUWrapper, AssemblyOutput) {
+        device_cpu(source, Box::new(Display::new(BASE, 4, 2, 2, 6_000)))
+    }
+
+    fn take_frame(cpu: &CPUWrapper) -> Option<Vec<u8>> {
+        cpu.with_device("display", |display: &mut Display| display.take_frame())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_renders_changed_frames() {
+        let (cpu, _) = cpu("
+.org $0600
+    LDA #$1B ; colors 0, 1, 2, 3
+    STA $1040
+    LDA #$C0 ; color 3 in the first pixel of the second row
+    STA $1041
+    LDA #$FF
+    STA $1016 ; color 2 becomes white
+loop:
+    JMP loop
+");
+        assert!(take_frame(&cpu).is_some(), "the first frame is black");
+        cpu.run_cycles(150);
+        let frame = take_frame(&cpu).unwrap();
+        let pixel = |x: usize, y: usize| frame[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4].to_vec();
+        assert_eq!(pixel(0, 0), [0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(1, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixel(2, 0), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(3, 0), [0xAA, 0xFF, 0xEE, 0xFF]);
+        assert_eq!(pixel(0, 1), [0xAA, 0xFF, 0xEE, 0xFF]);
+        assert_eq!(pixel(1, 1), [0x00, 0x00, 0x00, 0xFF]);
+
+        cpu.run_cycles(300);
+        assert_eq!(take_frame(&cpu), None, "nothing changed");
+        assert_eq!(cpu.read_byte(BASE + DISPLAY_WIDTH), 4);
+        assert_eq!(cpu.read_byte(BASE + DISPLAY_DEPTH), 2);
+        assert!(cpu.read_byte(BASE + DISPLAY_FRAME) >= 4);
+    }
+
+    #[test]
+    fn test_vsync_irq() {
+        let (cpu, output) = cpu("
+.org $0600
+    LDA #$01
+    STA $1000 ; vsync IRQ
+    CLI
+loop:
+    JMP loop
+irq:
+    LDA #$00
+    STA $1001 ; acknowledge
+    INC $10
+    RTI
+");
+        let irq = output.symbols["irq"];
+        cpu.write_byte(0xFFFE, irq as u8);
+        cpu.write_byte(0xFFFF, (irq >> 8) as u8);
+
+        cpu.run_cycles(1_050);
+        assert_eq!(
+            cpu.read_byte(0x10),
+            11,
+            "the frame at power on and one every 100 cycles"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+// DMA controller for bulk copies into RAM.
+//
+// Memory mode copies LENGTH bytes from SOURCE to DEST. Device mode copies bytes a sensor pushed
+// into the controller's stream (see `feed`) to DEST, waiting while the stream is empty.
+// While a transfer runs the controller moves DMA_BURST bytes after every CPU instruction and
+// steals DMA_CYCLES_PER_BYTE cycles for each, so the program keeps running but slower.
+// SOURCE, DEST and LENGTH count along with the transfer.
+
+use std::any::Any;
+use std::collections::VecDeque;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const DMA_CONTROL: u16 = 0x0;
+pub const DMA_STATUS: u16 = 0x1; // reading clears DONE
+pub const DMA_SOURCE: u16 = 0x2; // 16 bit
+pub const DMA_DEST: u16 = 0x4; // 16 bit
+pub const DMA_LENGTH: u16 = 0x6; // 16 bit
+pub const DMA_SIZE: u16 = 8;
+
+pub const DMA_CONTROL_START: u8 = 0b1000_0000; // strobe
+pub const DMA_CONTROL_IRQ: u8 = 0b0100_0000; // raise an IRQ while DONE
+pub const DMA_CONTROL_MODE: u8 = 0b0000_0011;
+
+pub const DMA_MODE_MEMORY: u8 = 0;
+pub const DMA_MODE_DEVICE: u8 = 1;
+
+pub const DMA_STATUS_BUSY: u8 = 0b1000_0000;
+pub const DMA_STATUS_DONE: u8 = 0b0100_0000;
+
+pub const DMA_BURST: u16 = 4;
+pub const DMA_CYCLES_PER_BYTE: u32 = 2; // one read and one write
+
+pub const DMA_STREAM_CAPACITY: usize = 0x10000;
+
+pub struct Dma {
+    base_address: u16,
+    busy: bool,
+    done: bool,
+    stream: VecDeque<u8>,
+}
+
+impl Dma {
+    pub fn new(base_address: u16) -> Self {
+        Self {
+            base_address,
+            busy: false,
+            done: false,
+            stream: VecDeque::new(),
+        }
+    }
+
+    // Bytes for device mode transfers, e.g. a camera frame
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.stream.extend(bytes);
+        while self.stream.len() > DMA_STREAM_CAPACITY {
+            self.stream.pop_front();
+        }
+    }
+}
+
+impl ShipDevice for Dma {
+    fn name(&self) -> &str {
+        "dma"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        DMA_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("CONTROL", DMA_CONTROL, 1, ReadWrite).with_fields(&[
+                BitField("START", DMA_CONTROL_START),
+                BitField("IRQ", DMA_CONTROL_IRQ),
+                BitField("MODE", DMA_CONTROL_MODE),
+            ]),
+            Register::new("STATUS", DMA_STATUS, 1, ReadOnly).with_fields(&[
+                BitField("BUSY", DMA_STATUS_BUSY),
+                BitField("DONE", DMA_STATUS_DONE),
+            ]),
+            Register::new("SOURCE", DMA_SOURCE, 2, ReadWrite),
+            Register::new("DEST", DMA_DEST, 2, ReadWrite),
+            Register::new("LENGTH", DMA_LENGTH, 2, ReadWrite),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, _cycles: u32) {
+        let base = self.base_address;
+        let control = bus.read(base + DMA_CONTROL);
+
+        if bus.was_read(base + DMA_STATUS) {
+            self.done = false;
+        }
+        if control & DMA_CONTROL_START != 0 {
+            bus.write(base + DMA_CONTROL, control & !DMA_CONTROL_START);
+            self.busy = true;
+            self.done = false;
+        }
+
+        if self.busy {
+            let mut source = bus.read_word(base + DMA_SOURCE);
+            let mut dest = bus.read_word(base + DMA_DEST);
+            let mut length = bus.read_word(base + DMA_LENGTH);
+            let mut moved = 0;
+
+            while length > 0 && moved < DMA_BURST {
+                let byte = match control & DMA_CONTROL_MODE {
+                    DMA_MODE_DEVICE => match self.stream.pop_front() {
+                        Some(byte) => byte,
+                        None => break,
+                    },
+                    _ => {
+                        let byte = bus.read(source);
+                        source = source.wrapping_add(1);
+                        byte
+                    }
+                };
+                bus.write(dest, byte);
+                dest = dest.wrapping_add(1);
+                length -= 1;
+                moved += 1;
+            }
+
+            bus.write_word(base + DMA_SOURCE, source);
+            bus.write_word(base + DMA_DEST, dest);
+            bus.write_word(base + DMA_LENGTH, length);
+            bus.steal(moved as u32 * DMA_CYCLES_PER_BYTE);
+
+            if length == 0 {
+                self.busy = false;
+                self.done = true;
+            }
+        }
+
+        let mut status = 0;
+        if self.busy {
+            status |= DMA_STATUS_BUSY;
+        }
+        if self.done {
+            status |= DMA_STATUS_DONE;
+        }
+        bus.write(base + DMA_STATUS, status);
+
+        if self.done && control & DMA_CONTROL_IRQ != 0 {
+            bus.irq();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_wrapper::CPUWrapper;
+    use std::collections::HashMap;
+
+    const BASE: u16 = 0x0270;
+
+    fn idle() -> CPUWrapper {
+        // loop: JMP loop
+        let cpu = CPUWrapper::new(0x0600, vec![0x4C, 0x00, 0x06], HashMap::new());
+        cpu.attach_device(Box::new(Dma::new(BASE))).unwrap();
+        cpu
+    }
+
+    fn start(cpu: &CPUWrapper, control: u8, source: u16, dest: u16, length: u16) {
+        for (offset, word) in [(DMA_SOURCE, source), (DMA_DEST, dest), (DMA_LENGTH, length)] {
+            cpu.write_byte(BASE + offset, word as u8);
+            cpu.write_byte(BASE + offset + 1, (word >> 8) as u8);
+        }
+        cpu.write_byte(BASE + DMA_CONTROL, control | DMA_CONTROL_START);
+    }
+
+    #[test]
+    fn test_memory_copy_steals_cycles() {
+        let cpu = idle();
+        for i in 0..64 {
+            cpu.write_byte(0x1000 + i, i as u8);
+        }
+        start(&cpu, DMA_MODE_MEMORY, 0x1000, 0x2000, 64);
+
+        let mut cycles = 0;
+        for _ in 0..16 {
+            cycles += cpu.run_step();
+        }
+        // 16 JMPs plus 64 bytes moved
+        assert_eq!(cycles, 16 * 3 + 64 * DMA_CYCLES_PER_BYTE);
+        assert_eq!(cpu.read_byte(BASE + DMA_STATUS), DMA_STATUS_DONE);
+        assert_eq!(cpu.read_byte(0x2000 + 63), 63);
+        assert_eq!(cpu.read_byte(BASE + DMA_LENGTH), 0);
+    }
+
+    #[test]
+    fn test_device_stream_irq() {
+        let cpu = idle();
+        cpu.write_byte(0xFFFE, 0x00);
+        cpu.write_byte(0xFFFF, 0x07);
+        for (i, byte) in [0x4C, 0x00, 0x07].iter().enumerate() {
+            cpu.write_byte(0x0700 + i as u16, *byte); // handler: JMP *
+        }
+        cpu.set_status(0); // interrupts enabled
+        start(&cpu, DMA_MODE_DEVICE | DMA_CONTROL_IRQ, 0, 0x3000, 6);
+
+        cpu.with_device("dma", |dma: &mut Dma| dma.feed(&[1, 2, 3]));
+        cpu.run_cycles(100);
+        assert_eq!(cpu.read_byte(BASE + DMA_STATUS), DMA_STATUS_BUSY);
+        assert_eq!(cpu.read_byte(BASE + DMA_LENGTH), 3);
+
+        cpu.with_device("dma", |dma: &mut Dma| dma.feed(&[4, 5, 6]));
+        cpu.run_step();
+        cpu.run_step();
+        assert_eq!(cpu.get_cpu().borrow().regs.pc, 0x0700);
+        assert_eq!(
+            (0..6)
+                .map(|i| cpu.read_byte(0x3000 + i))
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+    }
+}
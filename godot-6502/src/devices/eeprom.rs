@@ -0,0 +1,263 @@
+// Non-volatile storage: a page-addressed EEPROM that survives reboots and, through Godot, sessions.
+//
+// The program sees one page at a time through BUFFER. Reading copies the selected page into the
+// buffer; writing first needs the write-enable command, then stores the buffer into the page and
+// keeps the chip busy for EEPROM_WRITE_MS. The write-enable latch clears after every write.
+// Each page counts its writes; past EEPROM_ENDURANCE writes still land but set the WORN flag.
+//
+// Godot persists the whole chip with `image` and `load_image`: the data followed by the wear
+// counter of every page as 32-bit little endian values.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const EEPROM_COMMAND: u16 = 0x00; // strobe
+pub const EEPROM_STATUS: u16 = 0x01;
+pub const EEPROM_PAGE: u16 = 0x02; // 16 bit
+pub const EEPROM_WEAR: u16 = 0x04; // 16 bit, writes to the selected page, saturating
+pub const EEPROM_BUFFER: u16 = 0x08;
+pub const EEPROM_PAGE_SIZE: u16 = 32;
+pub const EEPROM_SIZE: u16 = EEPROM_BUFFER + EEPROM_PAGE_SIZE;
+
+pub const EEPROM_READ: u8 = 0x01;
+pub const EEPROM_WRITE: u8 = 0x02;
+pub const EEPROM_WRITE_DISABLE: u8 = 0x04;
+pub const EEPROM_WRITE_ENABLE: u8 = 0x06;
+
+pub const EEPROM_STATUS_BUSY: u8 = 0b1000_0000;
+pub const EEPROM_STATUS_WORN: u8 = 0b0000_0100;
+pub const EEPROM_STATUS_WRITE_ENABLED: u8 = 0b0000_0010;
+pub const EEPROM_STATUS_ERROR: u8 = 0b0000_0001; // bad page, busy, or write not enabled
+
+pub const EEPROM_DEFAULT_PAGES: u16 = 256; // 8 KB
+pub const EEPROM_WRITE_MS: u32 = 5;
+pub const EEPROM_ENDURANCE: u32 = 100_000;
+
+pub struct Eeprom {
+    base_address: u16,
+    frequency: u32, // CPU cycles per emulated second
+    data: Vec<u8>,
+    wear: Vec<u32>,
+    write_enabled: bool,
+    busy_cycles: u32,
+    error: bool,
+    worn: bool,
+}
+
+impl Eeprom {
+    pub fn new(base_address: u16, pages: u16, frequency: u32) -> Self {
+        Self {
+            base_address,
+            frequency,
+            // erased cells read as $FF
+            data: vec![0xFF; pages as usize * EEPROM_PAGE_SIZE as usize],
+            wear: vec![0; pages as usize],
+            write_enabled: false,
+            busy_cycles: 0,
+            error: false,
+            worn: false,
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.frequency = frequency;
+    }
+
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = self.data.clone();
+        for wear in &self.wear {
+            image.extend(wear.to_le_bytes());
+        }
+        image
+    }
+
+    // Restores an image taken from a chip with the same number of pages
+    pub fn load_image(&mut self, image: &[u8]) -> bool {
+        if image.len() != self.data.len() + self.wear.len() * 4 {
+            return false;
+        }
+        let (data, wear) = image.split_at(self.data.len());
+        self.data.copy_from_slice(data);
+        for (counter, bytes) in self.wear.iter_mut().zip(wear.chunks_exact(4)) {
+            *counter = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        true
+    }
+
+    fn page_range(&self, page: u16) -> Option<std::ops::Range<usize>> {
+        let start = page as usize * EEPROM_PAGE_SIZE as usize;
+        let end = start + EEPROM_PAGE_SIZE as usize;
+        (end <= self.data.len()).then_some(start..end)
+    }
+}
+
+impl ShipDevice for Eeprom {
+    fn name(&self) -> &str {
+        "eeprom"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        EEPROM_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("COMMAND", EEPROM_COMMAND, 1, WriteOnly),
+            Register::new("STATUS", EEPROM_STATUS, 1, ReadOnly).with_fields(&[
+                BitField("BUSY", EEPROM_STATUS_BUSY),
+                BitField("WORN", EEPROM_STATUS_WORN),
+                BitField("WRITE_ENABLED", EEPROM_STATUS_WRITE_ENABLED),
+                BitField("ERROR", EEPROM_STATUS_ERROR),
+            ]),
+            Register::new("PAGE", EEPROM_PAGE, 2, ReadWrite),
+            Register::new("WEAR", EEPROM_WEAR, 2, ReadOnly),
+            Register::new("BUFFER", EEPROM_BUFFER, EEPROM_PAGE_SIZE, ReadWrite),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+        let page = bus.read_word(base + EEPROM_PAGE);
+
+        self.busy_cycles = self.busy_cycles.saturating_sub(cycles);
+
+        let command = bus.read(base + EEPROM_COMMAND);
+        if command != 0 {
+            bus.write(base + EEPROM_COMMAND, 0);
+            self.error = false;
+
+            match (command, self.page_range(page)) {
+                (EEPROM_WRITE_ENABLE, _) => self.write_enabled = true,
+                (EEPROM_WRITE_DISABLE, _) => self.write_enabled = false,
+                _ if self.busy_cycles > 0 => self.error = true,
+                (EEPROM_READ, Some(range)) => {
+                    for (i, address) in range.enumerate() {
+                        bus.write(base + EEPROM_BUFFER + i as u16, self.data[address]);
+                    }
+                }
+                (EEPROM_WRITE, Some(range)) if self.write_enabled => {
+                    for (i, address) in range.enumerate() {
+                        self.data[address] = bus.read(base + EEPROM_BUFFER + i as u16);
+                    }
+                    let wear = &mut self.wear[page as usize];
+                    *wear = wear.saturating_add(1);
+                    self.worn = *wear > EEPROM_ENDURANCE;
+                    self.write_enabled = false;
+                    self.busy_cycles =
+                        (self.frequency as u64 * EEPROM_WRITE_MS as u64 / 1000).max(1) as u32;
+                }
+                _ => self.error = true,
+            }
+        }
+
+        let wear = self.wear.get(page as usize).copied().unwrap_or(0);
+        bus.write_word(base + EEPROM_WEAR, wear.min(0xFFFF) as u16);
+
+        let mut status = 0;
+        if self.busy_cycles > 0 {
+            status |= EEPROM_STATUS_BUSY;
+        }
+        if self.worn {
+            status |= EEPROM_STATUS_WORN;
+        }
+        if self.write_enabled {
+            status |= EEPROM_STATUS_WRITE_ENABLED;
+        }
+        if self.error {
+            status |= EEPROM_STATUS_ERROR;
+        }
+        bus.write(base + EEPROM_STATUS, status);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const BASE: u16 = 0x02A0;
+
+    // Store A in the first byte of page 3, then read the page back
+    const SOURCE: &str = "
+.org $0600
+save:
+    LDX #$03
+    STX $02A2
+    STA $02A8
+    LDX #$06
+    STX $02A0 ; write enable
+    LDX #$02
+    STX $02A0 ; write
+busy:
+    BIT $02A1
+    BMI busy
+    RTS
+
+load:
+    LDX #$03
+    STX $02A2
+    LDX #$01
+    STX $02A0 ; read
+    NOP
+    LDA $02A8
+    RTS
+";
+
+    fn test() -> RoutineTest {
+        let test = RoutineTest::new(SOURCE).unwrap();
+        test.cpu()
+            .attach_device(Box::new(Eeprom::new(BASE, 16, 100_000)))
+            .unwrap();
+        test
+    }
+
+    #[test]
+    fn test_write_survives_image() {
+        let mut first = test();
+        first.set_a(0x42);
+        let result = first.call("save").unwrap();
+        assert!(result.cycles >= 500, "a page write takes 5 ms");
+        first.assert_memory(BASE + EEPROM_WEAR, &[1, 0]);
+
+        let image = first
+            .cpu()
+            .with_device("eeprom", |eeprom: &mut Eeprom| eeprom.image())
+            .unwrap();
+
+        let mut second = test();
+        let loaded = second
+            .cpu()
+            .with_device("eeprom", |eeprom: &mut Eeprom| eeprom.load_image(&image));
+        assert_eq!(loaded, Some(true));
+        second.call("load").unwrap();
+        second.assert_a(0x42);
+        second.assert_memory(BASE + EEPROM_WEAR, &[1, 0]);
+    }
+
+    #[test]
+    fn test_write_needs_enable() {
+        let mut test = test();
+        test.write_memory(BASE + EEPROM_PAGE, &[1, 0]);
+        test.write_memory(BASE + EEPROM_COMMAND, &[EEPROM_WRITE]);
+        test.call("load").unwrap();
+        test.assert_a(0xFF);
+
+        let image = test
+            .cpu()
+            .with_device("eeprom", |eeprom: &mut Eeprom| eeprom.image())
+            .unwrap();
+        assert!(image[..16 * 32].iter().all(|byte| *byte == 0xFF));
+        assert!(image[16 * 32..].iter().all(|byte| *byte == 0));
+    }
+}
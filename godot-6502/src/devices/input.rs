@@ -0,0 +1,299 @@
+// Keyboard and gamepad: key events wait in a FIFO, the gamepad's sticks and buttons are always
+// current.
+//
+// The key at the head of the FIFO shows in ASCII, SCANCODE and KEY_FLAGS; writing NEXT drops it and
+// shows the next one. Scan codes follow Godot's key codes: printable keys keep their code, which is
+// the upper case ASCII character, and special keys like the arrows get $80 plus their index.
+// ASCII is the character the key typed, or 0 when it typed none. Releases are queued too, with
+// KEY_FLAGS_RELEASED set, so programs can tell how long a key is held.
+
+use std::any::Any;
+use std::collections::VecDeque;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const INPUT_STATUS: u16 = 0x00;
+pub const INPUT_CONTROL: u16 = 0x01;
+pub const INPUT_ASCII: u16 = 0x02;
+pub const INPUT_SCANCODE: u16 = 0x03;
+pub const INPUT_KEY_FLAGS: u16 = 0x04;
+pub const INPUT_NEXT: u16 = 0x05; // strobe: drop the key at the head of the FIFO
+pub const INPUT_AXES: u16 = 0x06; // signed, -127 to 127: left X, left Y, right X, right Y
+pub const INPUT_BUTTONS: u16 = 0x0A; // 16 bit, one bit per gamepad button
+pub const INPUT_SIZE: u16 = 0x0C;
+
+pub const INPUT_STATUS_KEY: u8 = 0b0000_0001; // a key is waiting
+pub const INPUT_STATUS_OVERFLOW: u8 = 0b0000_0010; // keys were dropped, clears once the FIFO empties
+
+pub const INPUT_CONTROL_KEY_IRQ: u8 = 0b0000_0001; // IRQ while a key is waiting
+
+pub const INPUT_KEY_FLAGS_SHIFT: u8 = 0b0000_0001;
+pub const INPUT_KEY_FLAGS_CTRL: u8 = 0b0000_0010;
+pub const INPUT_KEY_FLAGS_ALT: u8 = 0b0000_0100;
+pub const INPUT_KEY_FLAGS_RELEASED: u8 = 0b1000_0000;
+
+pub const INPUT_AXIS_COUNT: usize = 4;
+pub const INPUT_BUTTON_COUNT: usize = 16;
+
+// Keys waiting before new ones are dropped
+pub const INPUT_FIFO_CAPACITY: usize = 16;
+
+// Godot marks keys without a character with this bit
+const GODOT_KEY_SPECIAL: i64 = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub ascii: u8,
+    pub scancode: u8,
+    pub flags: u8,
+}
+
+impl KeyEvent {
+    // From a Godot key code and the character it typed, 0 for none
+    pub fn from_godot(keycode: i64, unicode: u32, flags: u8) -> Self {
+        let scancode = match keycode {
+            code if code & GODOT_KEY_SPECIAL != 0 => 0x80 | (code & 0x7F) as u8,
+            code => (code & 0x7F) as u8,
+        };
+        let ascii = match (unicode, keycode & !GODOT_KEY_SPECIAL) {
+            (0x20..=0x7E, _) => unicode as u8,
+            (0, 0x01) => 0x1B, // escape
+            (0, 0x02) => b'\t',
+            (0, 0x04) => 0x08,              // backspace
+            (0, 0x05) | (0, 0x06) => b'\r', // enter, keypad enter
+            _ => 0,
+        };
+        Self {
+            ascii,
+            scancode,
+            flags,
+        }
+    }
+}
+
+pub struct Input {
+    base_address: u16,
+    keys: VecDeque<KeyEvent>,
+    overflow: bool,
+    axes: [i8; INPUT_AXIS_COUNT],
+    buttons: u16,
+}
+
+impl Input {
+    pub fn new(base_address: u16) -> Self {
+        Self {
+            base_address,
+            keys: VecDeque::new(),
+            overflow: false,
+            axes: [0; INPUT_AXIS_COUNT],
+            buttons: 0,
+        }
+    }
+
+    pub fn push_key(&mut self, key: KeyEvent) {
+        if self.keys.len() == INPUT_FIFO_CAPACITY {
+            self.overflow = true;
+            return;
+        }
+        self.keys.push_back(key);
+    }
+
+    // `value` from -1.0 to 1.0, like Godot's joypad axes
+    pub fn set_axis(&mut self, axis: usize, value: f32) {
+        if let Some(register) = self.axes.get_mut(axis) {
+            *register = (value.clamp(-1.0, 1.0) * 127.0).round() as i8;
+        }
+    }
+
+    pub fn set_button(&mut self, button: usize, pressed: bool) {
+        if button < INPUT_BUTTON_COUNT {
+            match pressed {
+                true => self.buttons |= 1 << button,
+                false => self.buttons &= !(1 << button),
+            }
+        }
+    }
+}
+
+impl ShipDevice for Input {
+    fn name(&self) -> &str {
+        "input"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        INPUT_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("STATUS", INPUT_STATUS, 1, ReadOnly).with_fields(&[
+                BitField("KEY", INPUT_STATUS_KEY),
+                BitField("OVERFLOW", INPUT_STATUS_OVERFLOW),
+            ]),
+            Register::new("CONTROL", INPUT_CONTROL, 1, ReadWrite)
+                .with_fields(&[BitField("KEY_IRQ", INPUT_CONTROL_KEY_IRQ)]),
+            Register::new("ASCII", INPUT_ASCII, 1, ReadOnly),
+            Register::new("SCANCODE", INPUT_SCANCODE, 1, ReadOnly),
+            Register::new("KEY_FLAGS", INPUT_KEY_FLAGS, 1, ReadOnly).with_fields(&[
+                BitField("SHIFT", INPUT_KEY_FLAGS_SHIFT),
+                BitField("CTRL", INPUT_KEY_FLAGS_CTRL),
+                BitField("ALT", INPUT_KEY_FLAGS_ALT),
+                BitField("RELEASED", INPUT_KEY_FLAGS_RELEASED),
+            ]),
+            Register::new("NEXT", INPUT_NEXT, 1, WriteOnly),
+            Register::new("AXES", INPUT_AXES, INPUT_AXIS_COUNT as u16, ReadOnly),
+            Register::new("BUTTONS", INPUT_BUTTONS, 2, ReadOnly),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, _cycles: u32) {
+        let base = self.base_address;
+
+        if bus.was_written(base + INPUT_NEXT) {
+            self.keys.pop_front();
+        }
+        if self.keys.is_empty() {
+            self.overflow = false;
+        }
+
+        let key = self.keys.front().copied().unwrap_or(KeyEvent {
+            ascii: 0,
+            scancode: 0,
+            flags: 0,
+        });
+        bus.write(base + INPUT_ASCII, key.ascii);
+        bus.write(base + INPUT_SCANCODE, key.scancode);
+        bus.write(base + INPUT_KEY_FLAGS, key.flags);
+
+        let mut status = 0;
+        if !self.keys.is_empty() {
+            status |= INPUT_STATUS_KEY;
+        }
+        if self.overflow {
+            status |= INPUT_STATUS_OVERFLOW;
+        }
+        bus.write(base + INPUT_STATUS, status);
+
+        for (i, axis) in self.axes.iter().enumerate() {
+            bus.write(base + INPUT_AXES + i as u16, *axis as u8);
+        }
+        bus.write_word(base + INPUT_BUTTONS, self.buttons);
+
+        if bus.read(base + INPUT_CONTROL) & INPUT_CONTROL_KEY_IRQ != 0 && !self.keys.is_empty() {
+            bus.irq();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const BASE: u16 = 0x02C0;
+
+    // Copy the waiting keys to $10 on, with the count in Y
+    const SOURCE: &str = "
+.org $0600
+read:
+    LDY #$00
+next:
+    LDA $02C0
+    AND #$01
+    BEQ done
+    LDA $02C2
+    STA $0010,Y
+    STA $02C5
+    INY
+    JMP next
+done:
+    RTS
+
+skip:
+    LDA #$00
+    STA $02C5
+    LDA $02C2
+    RTS
+
+stick:
+    NOP
+    LDA $02C7
+    LDX $02CA
+    RTS
+";
+
+    fn test() -> RoutineTest {
+        let test = RoutineTest::new(SOURCE).unwrap();
+        test.cpu()
+            .attach_device(Box::new(Input::new(BASE)))
+            .unwrap();
+        test
+    }
+
+    fn push(test: &RoutineTest, keycode: i64, unicode: u32, flags: u8) {
+        test.cpu().with_device("input", |input: &mut Input| {
+            input.push_key(KeyEvent::from_godot(keycode, unicode, flags))
+        });
+    }
+
+    #[test]
+    fn test_key_codes() {
+        let a = KeyEvent::from_godot(65, 'a' as u32, 0);
+        assert_eq!((a.scancode, a.ascii), (0x41, b'a'));
+        let enter = KeyEvent::from_godot(GODOT_KEY_SPECIAL | 0x05, 0, 0);
+        assert_eq!((enter.scancode, enter.ascii), (0x85, b'\r'));
+        let up = KeyEvent::from_godot(GODOT_KEY_SPECIAL | 0x10, 0, 0);
+        assert_eq!((up.scancode, up.ascii), (0x90, 0));
+    }
+
+    #[test]
+    fn test_fifo() {
+        let mut test = test();
+        push(&test, 72, 'H' as u32, INPUT_KEY_FLAGS_SHIFT);
+        push(&test, 73, 'i' as u32, 0);
+        push(&test, 73, 'i' as u32, INPUT_KEY_FLAGS_RELEASED);
+        test.call("read").unwrap();
+        test.assert_y(3);
+        test.assert_memory(0x10, b"Hii");
+        test.assert_memory(BASE + INPUT_STATUS, &[0]);
+
+        for _ in 0..INPUT_FIFO_CAPACITY + 1 {
+            push(&test, 88, 'x' as u32, 0);
+        }
+        test.call("read").unwrap();
+        test.assert_y(INPUT_FIFO_CAPACITY as u8);
+    }
+
+    #[test]
+    fn test_next_with_zero() {
+        let mut test = test();
+        push(&test, 65, 'a' as u32, 0);
+        push(&test, 66, 'b' as u32, 0);
+        test.call("skip").unwrap();
+        test.assert_a(b'b');
+    }
+
+    #[test]
+    fn test_gamepad() {
+        let mut test = test();
+        test.cpu().with_device("input", |input: &mut Input| {
+            input.set_axis(1, -1.0);
+            input.set_button(0, true);
+            input.set_button(3, true);
+            input.set_button(0, false);
+        });
+        test.call("stick").unwrap();
+        test.assert_a(0x81);
+        test.assert_x(0b1000);
+    }
+}
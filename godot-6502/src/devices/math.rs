@@ -0,0 +1,237 @@
+// Math coprocessor: multiply, divide, fixed-point and trig operations the 6502 is slow at.
+//
+// Load the operands, write an opcode, then poll STATUS until BUSY clears. Results appear when the
+// operation completes, after the latency listed below; until then the result registers keep
+// their previous contents. Writing an opcode while busy abandons the running operation.
+//
+//   op   operation                 operands                result                     cycles
+//   $01  unsigned 16x16 multiply   A (16 bit), B           RESULT (32 bit)            8
+//   $02  signed 16x16 multiply     A (16 bit), B           RESULT (32 bit)            10
+//   $03  unsigned 32/16 divide     A (32 bit), B           RESULT (32), REMAINDER     24
+//   $04  signed 8.8 multiply       A (16 bit), B           RESULT (16 bit, saturated) 10
+//   $05  sine                      A (8.8 radians)         RESULT (8.8)               32
+//   $06  cosine                    A (8.8 radians)         RESULT (8.8)               32
+//   $07  atan2(y, x)               A = y, B = x (8.8)      RESULT (8.8 radians)       40
+//   $08  integer square root       A (32 bit)              RESULT (16 bit)            20
+//
+// Dividing by zero or writing an unknown opcode sets the ERROR bit and leaves the results alone.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const MATH_OPCODE: u16 = 0x00; // strobe
+pub const MATH_STATUS: u16 = 0x01;
+pub const MATH_A: u16 = 0x02; // 32 bit
+pub const MATH_B: u16 = 0x06; // 16 bit
+pub const MATH_RESULT: u16 = 0x08; // 32 bit
+pub const MATH_REMAINDER: u16 = 0x0C; // 16 bit
+pub const MATH_SIZE: u16 = 0x0E;
+
+pub const MATH_STATUS_BUSY: u8 = 0b1000_0000;
+pub const MATH_STATUS_ERROR: u8 = 0b0000_0001;
+
+pub const MATH_MUL: u8 = 0x01;
+pub const MATH_SMUL: u8 = 0x02;
+pub const MATH_DIV: u8 = 0x03;
+pub const MATH_FMUL: u8 = 0x04;
+pub const MATH_SIN: u8 = 0x05;
+pub const MATH_COS: u8 = 0x06;
+pub const MATH_ATAN2: u8 = 0x07;
+pub const MATH_SQRT: u8 = 0x08;
+
+pub fn latency(opcode: u8) -> Option<u32> {
+    match opcode {
+        MATH_MUL => Some(8),
+        MATH_SMUL => Some(10),
+        MATH_DIV => Some(24),
+        MATH_FMUL => Some(10),
+        MATH_SIN | MATH_COS => Some(32),
+        MATH_ATAN2 => Some(40),
+        MATH_SQRT => Some(20),
+        _ => None,
+    }
+}
+
+// (result, remainder), None on error
+pub fn compute(opcode: u8, a: u32, b: u16) -> Option<(u32, u16)> {
+    let a16 = a as u16;
+    let fixed = |value: f64| (value * 256.0).round() as i16 as u16 as u32;
+    let radians = |value: u16| value as i16 as f64 / 256.0;
+
+    match opcode {
+        MATH_MUL => Some((a16 as u32 * b as u32, 0)),
+        MATH_SMUL => Some(((a16 as i16 as i32 * b as i16 as i32) as u32, 0)),
+        MATH_DIV if b == 0 => None,
+        MATH_DIV => Some((a / b as u32, (a % b as u32) as u16)),
+        MATH_FMUL => {
+            let product = (a16 as i16 as i32 * b as i16 as i32) >> 8;
+            let saturated = product.clamp(i16::MIN as i32, i16::MAX as i32);
+            Some((saturated as i16 as u16 as u32, 0))
+        }
+        MATH_SIN => Some((fixed(radians(a16).sin()), 0)),
+        MATH_COS => Some((fixed(radians(a16).cos()), 0)),
+        MATH_ATAN2 => Some((fixed(radians(a16).atan2(radians(b))), 0)),
+        MATH_SQRT => Some((isqrt(a), 0)),
+        _ => None,
+    }
+}
+
+fn isqrt(value: u32) -> u32 {
+    let mut root = (value as f64).sqrt() as u32;
+    // correct any rounding from the float estimate
+    while root as u64 * root as u64 > value as u64 {
+        root -= 1;
+    }
+    while (root as u64 + 1) * (root as u64 + 1) <= value as u64 {
+        root += 1;
+    }
+    root
+}
+
+pub struct MathUnit {
+    base_address: u16,
+    pending: Option<(u32, Option<(u32, u16)>)>, // cycles left, outcome
+    error: bool,
+}
+
+impl MathUnit {
+    pub fn new(base_address: u16) -> Self {
+        Self {
+            base_address,
+            pending: None,
+            error: false,
+        }
+    }
+}
+
+impl ShipDevice for MathUnit {
+    fn name(&self) -> &str {
+        "math"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        MATH_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("OPCODE", MATH_OPCODE, 1, WriteOnly),
+            Register::new("STATUS", MATH_STATUS, 1, ReadOnly).with_fields(&[
+                BitField("BUSY", MATH_STATUS_BUSY),
+                BitField("ERROR", MATH_STATUS_ERROR),
+            ]),
+            Register::new("A", MATH_A, 4, ReadWrite),
+            Register::new("B", MATH_B, 2, ReadWrite),
+            Register::new("RESULT", MATH_RESULT, 4, ReadOnly),
+            Register::new("REMAINDER", MATH_REMAINDER, 2, ReadOnly),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+
+        match bus.read(base + MATH_OPCODE) {
+            0 => {
+                if let Some((left, outcome)) = self.pending {
+                    match left.checked_sub(cycles) {
+                        Some(left) if left > 0 => self.pending = Some((left, outcome)),
+                        _ => {
+                            match outcome {
+                                Some((result, remainder)) => {
+                                    bus.write_word(base + MATH_RESULT, result as u16);
+                                    bus.write_word(base + MATH_RESULT + 2, (result >> 16) as u16);
+                                    bus.write_word(base + MATH_REMAINDER, remainder);
+                                }
+                                None => self.error = true,
+                            }
+                            self.pending = None;
+                        }
+                    }
+                }
+            }
+            opcode => {
+                // Operands are sampled when the operation starts
+                let a = bus.read_word(base + MATH_A) as u32
+                    | (bus.read_word(base + MATH_A + 2) as u32) << 16;
+                let b = bus.read_word(base + MATH_B);
+                self.error = false;
+                self.pending = Some((latency(opcode).unwrap_or(1), compute(opcode, a, b)));
+                bus.write(base + MATH_OPCODE, 0);
+            }
+        }
+
+        let mut status = 0;
+        if self.pending.is_some() {
+            status |= MATH_STATUS_BUSY;
+        }
+        if self.error {
+            status |= MATH_STATUS_ERROR;
+        }
+        bus.write(base + MATH_STATUS, status);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    #[test]
+    fn test_compute() {
+        assert_eq!(compute(MATH_MUL, 300, 400), Some((120_000, 0)));
+        assert_eq!(compute(MATH_SMUL, 0xFFFE, 3), Some((-6i32 as u32, 0)));
+        assert_eq!(compute(MATH_DIV, 100_000, 7), Some((14_285, 5)));
+        assert_eq!(compute(MATH_DIV, 1, 0), None);
+        assert_eq!(compute(MATH_FMUL, 0x0180, 0xFE00), Some((0xFD00, 0))); // 1.5 * -2
+        assert_eq!(compute(MATH_FMUL, 0x7F00, 0x7F00), Some((0x7FFF, 0)));
+        assert_eq!(compute(MATH_SIN, 0x0192, 0), Some((0x0100, 0))); // sin(pi / 2)
+        assert_eq!(compute(MATH_COS, 0, 0), Some((0x0100, 0)));
+        assert_eq!(compute(MATH_ATAN2, 0x0100, 0x0100), Some((0x00C9, 0))); // pi / 4
+        assert_eq!(compute(MATH_SQRT, 0xFFFF_FFFF, 0), Some((0xFFFF, 0)));
+        assert_eq!(compute(MATH_SQRT, 99, 0), Some((9, 0)));
+        assert_eq!(compute(0x42, 0, 0), None);
+    }
+
+    #[test]
+    fn test_busy_until_latency() {
+        let mut test = RoutineTest::new(
+            "
+.org $0600
+multiply:
+    LDA #$2C
+    STA $0262
+    LDA #$01
+    STA $0263 ; A = 300
+    LDA #$90
+    STA $0266
+    LDA #$01
+    STA $0267 ; B = 400
+    LDA #$01
+    STA $0260 ; MUL
+wait:
+    BIT $0261
+    BMI wait
+    RTS
+",
+        )
+        .unwrap();
+        test.cpu()
+            .attach_device(Box::new(MathUnit::new(0x0260)))
+            .unwrap();
+
+        let result = test.call("multiply").unwrap();
+        test.assert_memory(0x0268, &[0xC0, 0xD4, 0x01, 0x00]);
+        assert!(result.cycles >= 8 + 26);
+    }
+}
@@ -0,0 +1,390 @@
+// Memory-mapped devices attached to a CPU.
+//
+// Device registers live in the CPU's own memory. After every instruction the wrapper ticks each
+// device with the cycles that instruction took; the device reads what the program wrote, updates
+// its state and writes its outputs back, much like ShipComponent does from GDScript every frame.
+// Registers that trigger an action (send, kick, ...) are strobes: the program writes a non-zero
+// value and the device clears it once handled.
+//
+// Each CPU keeps a registry of its devices: attaching one whose address range overlaps another's
+// fails with a DeviceError instead of letting the two fight over the same bytes.
+
+use rv6502emu::cpu::{Cpu, CpuFlags};
+use std::any::Any;
+use thiserror::Error;
+
+use access::Access;
+
+pub mod access;
+pub mod acia;
+pub mod clock;
+pub mod display;
+pub mod dma;
+pub mod eeprom;
+pub mod font;
+pub mod input;
+pub mod math;
+pub mod region;
+pub mod rng;
+pub mod sensor;
+pub mod shared_memory;
+pub mod sound;
+pub mod terminal;
+pub mod via;
+pub mod watchdog;
+
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+// Cycles taken by the CPU to enter an interrupt handler
+pub const INTERRUPT_CYCLES: u32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterAccess {
+    ReadOnly,
+    WriteOnly, // strobes and latches, reading gives nothing meaningful
+    ReadWrite,
+}
+
+impl RegisterAccess {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegisterAccess::ReadOnly => "read",
+            RegisterAccess::WriteOnly => "write",
+            RegisterAccess::ReadWrite => "read_write",
+        }
+    }
+}
+
+// Named bits of a register for the memory map: name, mask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitField(pub &'static str, pub u8);
+
+// A register as the program sees it, `offset` is relative to the device's base address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register {
+    pub name: &'static str,
+    pub offset: u16,
+    pub size: u16, // bytes, multi-byte registers are little endian
+    pub access: RegisterAccess,
+    pub fields: &'static [BitField],
+}
+
+impl Register {
+    pub const fn new(name: &'static str, offset: u16, size: u16, access: RegisterAccess) -> Self {
+        Self {
+            name,
+            offset,
+            size,
+            access,
+            fields: &[],
+        }
+    }
+
+    pub const fn with_fields(mut self, fields: &'static [BitField]) -> Self {
+        self.fields = fields;
+        self
+    }
+}
+
+pub trait ShipDevice {
+    fn name(&self) -> &str;
+    fn base_address(&self) -> u16;
+    fn size(&self) -> u16;
+    fn registers(&self) -> Vec<Register>;
+
+    // Advance the device by `cycles` emulated cycles
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32);
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DeviceError {
+    #[error("{name} at ${base_address:04X}-${end:04X} overlaps {other} at ${other_base_address:04X}-${other_end:04X}")]
+    Overlap {
+        name: String,
+        base_address: u16,
+        end: u16,
+        other: String,
+        other_base_address: u16,
+        other_end: u16,
+    },
+    #[error("{name} at ${base_address:04X} needs {size} bytes and runs past the end of memory")]
+    OutOfMemory {
+        name: String,
+        base_address: u16,
+        size: u16,
+    },
+}
+
+// Last address a device occupies, None for a device without registers
+fn last_address(device: &dyn ShipDevice) -> Option<u32> {
+    (device.size() > 0).then(|| device.base_address() as u32 + device.size() as u32 - 1)
+}
+
+// Checks that `device` fits in memory without overlapping any of `attached`
+pub fn check_placement(
+    device: &dyn ShipDevice,
+    attached: &[Box<dyn ShipDevice>],
+) -> Result<(), DeviceError> {
+    let Some(end) = last_address(device) else {
+        return Ok(());
+    };
+    if end > 0xFFFF {
+        return Err(DeviceError::OutOfMemory {
+            name: device.name().to_string(),
+            base_address: device.base_address(),
+            size: device.size(),
+        });
+    }
+
+    for other in attached {
+        let Some(other_end) = last_address(other.as_ref()) else {
+            continue;
+        };
+        if device.base_address() as u32 <= other_end && other.base_address() as u32 <= end {
+            return Err(DeviceError::Overlap {
+                name: device.name().to_string(),
+                base_address: device.base_address(),
+                end: end as u16,
+                other: other.name().to_string(),
+                other_base_address: other.base_address(),
+                other_end: other_end as u16,
+            });
+        }
+    }
+    Ok(())
+}
+
+// What the registry knows about an attached device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub base_address: u16,
+    pub size: u16,
+    pub registers: Vec<Register>,
+}
+
+impl DeviceInfo {
+    pub fn of(device: &dyn ShipDevice) -> Self {
+        Self {
+            name: device.name().to_string(),
+            base_address: device.base_address(),
+            size: device.size(),
+            registers: device.registers(),
+        }
+    }
+}
+
+// A device's view of the CPU during a tick
+pub struct DeviceBus<'a> {
+    cpu: &'a mut Cpu,
+    access: Option<Access>,
+    irq: bool,
+    nmi: bool,
+    reset: bool,
+    stolen: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Signals {
+    pub irq: bool,
+    pub nmi: bool,
+    pub reset: bool,
+    pub stolen_cycles: u32,
+}
+
+impl<'a> DeviceBus<'a> {
+    pub fn new(cpu: &'a mut Cpu) -> Self {
+        Self {
+            cpu,
+            access: None,
+            irq: false,
+            nmi: false,
+            reset: false,
+            stolen: 0,
+        }
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.cpu
+            .bus
+            .get_memory()
+            .read_byte(address as usize)
+            .unwrap_or(0)
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let _ = self
+            .cpu
+            .bus
+            .get_memory()
+            .write_byte(address as usize, value);
+    }
+
+    // Memory access made by the instruction that just ran
+    pub fn with_access(mut self, access: Option<Access>) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn was_read(&self, address: u16) -> bool {
+        matches!(self.access, Some(access) if access.read && access.address == address)
+    }
+
+    pub fn written_address(&self) -> Option<u16> {
+        self.access
+            .filter(|access| access.write)
+            .map(|access| access.address)
+    }
+
+    pub fn was_written(&self, address: u16) -> bool {
+        matches!(self.access, Some(access) if access.write && access.address == address)
+    }
+
+    pub fn read_word(&mut self, address: u16) -> u16 {
+        self.read(address) as u16 | (self.read(address.wrapping_add(1)) as u16) << 8
+    }
+
+    pub fn write_word(&mut self, address: u16, value: u16) {
+        self.write(address, value as u8);
+        self.write(address.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    // Hold the IRQ line low for this tick. The line is level triggered, so devices keep
+    // asserting it until the program acknowledges the cause.
+    pub fn irq(&mut self) {
+        self.irq = true;
+    }
+
+    // Request a non-maskable interrupt. NMI is edge triggered, call it once per event.
+    pub fn nmi(&mut self) {
+        self.nmi = true;
+    }
+
+    pub fn reset(&mut self) {
+        self.reset = true;
+    }
+
+    // Keep the CPU off the bus for `cycles` extra cycles, like a DMA transfer does
+    pub fn steal(&mut self, cycles: u32) {
+        self.stolen += cycles;
+    }
+
+    pub fn signals(&self) -> Signals {
+        Signals {
+            irq: self.irq,
+            nmi: self.nmi,
+            reset: self.reset,
+            stolen_cycles: self.stolen,
+        }
+    }
+}
+
+fn push(cpu: &mut Cpu, value: u8) {
+    let sp = cpu.regs.s;
+    let _ = cpu.bus.get_memory().write_byte(0x0100 | sp as usize, value);
+    cpu.regs.s = sp.wrapping_sub(1);
+}
+
+// Push PC and P then jump through `vector`, like the hardware does for IRQ and NMI
+pub fn interrupt(cpu: &mut Cpu, vector: u16) {
+    let pc = cpu.regs.pc;
+    // B is only set in the copy pushed by BRK/PHP, bit 5 always reads as 1
+    let status = (cpu.regs.p.bits() | 0x20) & !0x10;
+
+    push(cpu, (pc >> 8) as u8);
+    push(cpu, pc as u8);
+    push(cpu, status);
+
+    cpu.regs.p.insert(CpuFlags::I);
+    cpu.regs.pc = DeviceBus::new(cpu).read_word(vector);
+}
+
+pub fn irq_disabled(cpu: &Cpu) -> bool {
+    cpu.regs.p.contains(CpuFlags::I)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_wrapper::CPUWrapper;
+    use region::Region;
+    use std::collections::HashMap;
+
+    fn built_in() -> Vec<Box<dyn ShipDevice>> {
+        let (mailbox, _) = shared_memory::Mailbox::pair(0, 0);
+        vec![
+            Box::new(acia::Acia::new(0, 1)),
+            Box::new(clock::Clock::new(0, 1)),
+            Box::new(display::Display::new(0, 3, 2, 4, 1)),
+            Box::new(dma::Dma::new(0)),
+            Box::new(eeprom::Eeprom::new(0, 1, 1)),
+            Box::new(input::Input::new(0)),
+            Box::new(math::MathUnit::new(0)),
+            Box::new(rng::Rng::new(0, 0)),
+            Box::new(sensor::Sensor::new(
+                "sensor",
+                0,
+                3,
+                sensor::Encoding::Fixed16_16,
+                1.0,
+            )),
+            Box::new(mailbox),
+            Box::new(shared_memory::SharedWindow::new(
+                0,
+                shared_memory::shared_buffer(8),
+            )),
+            Box::new(sound::Sound::new(0, 1, 1)),
+            Box::new(terminal::Terminal::new(0, 1)),
+            Box::new(via::Via::new(0)),
+            Box::new(watchdog::Watchdog::new(0)),
+        ]
+    }
+
+    #[test]
+    fn test_registers_fit_their_device() {
+        for device in built_in() {
+            let mut used = vec![false; device.size() as usize];
+            for register in device.registers() {
+                assert!(
+                    register.offset + register.size <= device.size(),
+                    "{}: {} is outside the device",
+                    device.name(),
+                    register.name
+                );
+                for offset in register.offset..register.offset + register.size {
+                    let byte = &mut used[offset as usize];
+                    assert!(!*byte, "{}: {} overlaps", device.name(), register.name);
+                    *byte = true;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_registry_rejects_overlaps() {
+        let cpu = CPUWrapper::new(0x0600, Vec::new(), HashMap::new());
+        cpu.attach_device(Box::new(via::Via::new(0x0200))).unwrap();
+
+        let error = cpu
+            .attach_device(Box::new(Region::new("Thrusters", 0x020C, 4)))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Thrusters at $020C-$020F overlaps via at $0200-$020F"
+        );
+        cpu.attach_device(Box::new(Region::new("Thrusters", 0x0210, 4)))
+            .unwrap();
+
+        let error = cpu
+            .attach_device(Box::new(Region::new("Camera", 0xFFF0, 0x20)))
+            .unwrap_err();
+        assert!(matches!(error, DeviceError::OutOfMemory { .. }));
+
+        let names: Vec<String> = cpu.device_info().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, vec!["via", "Thrusters"]);
+    }
+}
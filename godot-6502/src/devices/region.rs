@@ -0,0 +1,49 @@
+// Memory claimed by a GDScript ship component.
+//
+// Components read and write their bytes themselves every frame; the region only enters them in
+// the CPU's device registry, so a part placed on top of another is caught when it is attached.
+
+use std::any::Any;
+
+use super::RegisterAccess::ReadWrite;
+use super::{DeviceBus, Register, ShipDevice};
+
+pub struct Region {
+    name: String,
+    base_address: u16,
+    size: u16,
+}
+
+impl Region {
+    pub fn new(name: &str, base_address: u16, size: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            base_address,
+            size,
+        }
+    }
+}
+
+impl ShipDevice for Region {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        self.size
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![Register::new("DATA", 0, self.size, ReadWrite)]
+    }
+
+    fn tick(&mut self, _bus: &mut DeviceBus, _cycles: u32) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,177 @@
+// Seeded random numbers, for the program through MMIO and for sensor noise through the emulator.
+//
+// Everything random about a flight derives from the CPU's seed, so replaying with the same seed
+// gives the same run bit for bit. The program and sensor noise draw from separate streams, so a
+// sensor sampling more often does not change what the program reads.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const RNG_DATA: u16 = 0x0; // a new byte after every read
+pub const RNG_CONTROL: u16 = 0x1; // strobe: $01 reseed from SEED
+pub const RNG_SEED: u16 = 0x2; // 32 bit
+pub const RNG_SIZE: u16 = 6;
+
+pub const RNG_COMMAND_RESEED: u8 = 0x01;
+
+pub const DEFAULT_SEED: u64 = 0x5EED_6502;
+
+// Stream identifiers mixed into the seed
+pub const STREAM_DEVICE: u64 = 1;
+pub const STREAM_NOISE: u64 = 2;
+
+// SplitMix64: small, fast and identical on every platform
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn stream(seed: u64, stream: u64) -> Self {
+        Self::new(seed ^ stream.wrapping_mul(0xD1B5_4A32_D192_ED03))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Normally distributed, Box-Muller
+    pub fn next_normal(&mut self, mean: f64, deviation: f64) -> f64 {
+        let u1 = 1.0 - self.next_f64(); // (0, 1], keeps ln finite
+        let u2 = self.next_f64();
+        mean + deviation * (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+pub struct Rng {
+    base_address: u16,
+    rng: SeededRng,
+    current: Option<u8>,
+}
+
+impl Rng {
+    pub fn new(base_address: u16, seed: u64) -> Self {
+        Self {
+            base_address,
+            rng: SeededRng::stream(seed, STREAM_DEVICE),
+            current: None,
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = SeededRng::stream(seed, STREAM_DEVICE);
+        self.current = None;
+    }
+}
+
+impl ShipDevice for Rng {
+    fn name(&self) -> &str {
+        "rng"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        RNG_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("DATA", RNG_DATA, 1, ReadOnly),
+            Register::new("CONTROL", RNG_CONTROL, 1, WriteOnly),
+            Register::new("SEED", RNG_SEED, 4, ReadWrite),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, _cycles: u32) {
+        let base = self.base_address;
+
+        if bus.read(base + RNG_CONTROL) == RNG_COMMAND_RESEED {
+            let seed = bus.read_word(base + RNG_SEED) as u64
+                | (bus.read_word(base + RNG_SEED + 2) as u64) << 16;
+            self.reseed(seed);
+        }
+        bus.write(base + RNG_CONTROL, 0);
+
+        if bus.was_read(base + RNG_DATA) {
+            self.current = None;
+        }
+        let byte = *self.current.get_or_insert_with(|| self.rng.next_u8());
+        bus.write(base + RNG_DATA, byte);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const SOURCE: &str = "
+.org $0600
+; fill $10-$17 with random bytes
+fill:
+    LDX #$00
+loop:
+    LDA $0280
+    STA $10,X
+    INX
+    CPX #$08
+    BNE loop
+    RTS
+";
+
+    fn run(seed: u64) -> Vec<u8> {
+        let mut test = RoutineTest::new(SOURCE).unwrap();
+        test.cpu()
+            .attach_device(Box::new(Rng::new(0x0280, seed)))
+            .unwrap();
+        test.call("fill").unwrap();
+        test.read_memory(0x10, 8)
+    }
+
+    #[test]
+    fn test_replays_from_seed() {
+        let bytes = run(42);
+        assert_eq!(bytes, run(42));
+        assert_ne!(bytes, run(43));
+        // every read draws a new byte
+        assert!(bytes.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_normal_distribution() {
+        let mut rng = SeededRng::new(7);
+        let samples: Vec<f64> = (0..10_000).map(|_| rng.next_normal(5.0, 2.0)).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance =
+            samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+        assert!((mean - 5.0).abs() < 0.1);
+        assert!((variance.sqrt() - 2.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,290 @@
+// Sensor readings with more than 8 bits: signed 16 bit, 8.8 or 16.16 fixed point.
+//
+// Godot sets each channel's reading as a float. Writing LATCH copies every channel into VALUES at
+// once, so neither a multi-byte value nor a set of related channels can change while the program
+// reads them. Values are little endian and saturate at the ends of the range. The descriptor
+// registers say how to read them: ENCODING, CHANNELS, RANGE, the largest magnitude in whole units,
+// and RESOLUTION, the value of one step in 16.16 fixed point. The fixed point encodings reach 127
+// and 32767 at most, a sensor given a larger range reports and saturates at that instead.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, WriteOnly};
+use super::{DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const SENSOR_LATCH: u16 = 0x00; // write anything to latch every channel
+pub const SENSOR_ENCODING: u16 = 0x01;
+pub const SENSOR_CHANNELS: u16 = 0x02;
+pub const SENSOR_RANGE: u16 = 0x03; // 16 bit, whole units
+pub const SENSOR_RESOLUTION: u16 = 0x05; // 32 bit, 16.16 fixed point
+pub const SENSOR_VALUES: u16 = 0x09;
+
+pub const SENSOR_MAX_CHANNELS: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Unsigned8 = 0,  // -range to range as 0 to 255, like the original GDScript sensors
+    Signed16 = 1,   // -range to range as -32767 to 32767
+    Fixed8_8 = 2,   // signed, 8 integer and 8 fraction bits
+    Fixed16_16 = 3, // signed, 16 integer and 16 fraction bits
+}
+
+impl Encoding {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Unsigned8),
+            1 => Some(Self::Signed16),
+            2 => Some(Self::Fixed8_8),
+            3 => Some(Self::Fixed16_16),
+            _ => None,
+        }
+    }
+
+    // Bytes per value
+    pub fn width(self) -> u16 {
+        match self {
+            Self::Unsigned8 => 1,
+            Self::Signed16 | Self::Fixed8_8 => 2,
+            Self::Fixed16_16 => 4,
+        }
+    }
+
+    // Largest range, in whole units, that readings can reach. RANGE itself is 16 bits.
+    pub fn max_range(self) -> f64 {
+        match self {
+            Self::Unsigned8 | Self::Signed16 => 65535.0,
+            Self::Fixed8_8 => 127.0,
+            Self::Fixed16_16 => 32767.0,
+        }
+    }
+
+    // Value of one step for a sensor reading from -range to range
+    pub fn resolution(self, range: f64) -> f64 {
+        match self {
+            Self::Unsigned8 => 2.0 * range / 255.0,
+            Self::Signed16 => range / 32767.0,
+            Self::Fixed8_8 => 1.0 / 256.0,
+            Self::Fixed16_16 => 1.0 / 65536.0,
+        }
+    }
+
+    // `value` as little endian bytes, clamped to -range to range and to what the encoding holds
+    pub fn encode(self, value: f64, range: f64) -> Vec<u8> {
+        let value = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(-range, range)
+        };
+        match self {
+            Self::Unsigned8 => {
+                let scaled = (value + range) / (2.0 * range) * 255.0;
+                vec![scaled.round() as u8]
+            }
+            Self::Signed16 => {
+                let scaled = (value / range * 32767.0).round() as i16;
+                scaled.to_le_bytes().to_vec()
+            }
+            // `as` saturates, so out of range values stick at the ends
+            Self::Fixed8_8 => ((value * 256.0).round() as i16).to_le_bytes().to_vec(),
+            Self::Fixed16_16 => ((value * 65536.0).round() as i32).to_le_bytes().to_vec(),
+        }
+    }
+}
+
+pub struct Sensor {
+    name: String,
+    base_address: u16,
+    encoding: Encoding,
+    range: f64,
+    readings: Vec<f64>,
+}
+
+impl Sensor {
+    // `channels` from 1 to SENSOR_MAX_CHANNELS, `range` above 0. A range the encoding cannot reach
+    // is reduced to Encoding::max_range.
+    pub fn new(
+        name: &str,
+        base_address: u16,
+        channels: u8,
+        encoding: Encoding,
+        range: f64,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            base_address,
+            encoding,
+            range: range.min(encoding.max_range()),
+            readings: vec![0.0; channels as usize],
+        }
+    }
+
+    // The program sees the reading the next time it latches
+    pub fn set_reading(&mut self, channel: usize, value: f64) -> bool {
+        match self.readings.get_mut(channel) {
+            Some(reading) => {
+                *reading = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn write_descriptor(&self, bus: &mut DeviceBus) {
+        let base = self.base_address;
+        bus.write(base + SENSOR_ENCODING, self.encoding as u8);
+        bus.write(base + SENSOR_CHANNELS, self.readings.len() as u8);
+        bus.write_word(base + SENSOR_RANGE, self.range.ceil() as u16);
+        let resolution = (self.encoding.resolution(self.range) * 65536.0).round() as u32;
+        bus.write_word(base + SENSOR_RESOLUTION, resolution as u16);
+        bus.write_word(base + SENSOR_RESOLUTION + 2, (resolution >> 16) as u16);
+    }
+
+    fn latch(&self, bus: &mut DeviceBus) {
+        let mut address = self.base_address + SENSOR_VALUES;
+        for reading in &self.readings {
+            for byte in self.encoding.encode(*reading, self.range) {
+                bus.write(address, byte);
+                address += 1;
+            }
+        }
+    }
+}
+
+impl ShipDevice for Sensor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        SENSOR_VALUES + self.readings.len() as u16 * self.encoding.width()
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        let values = self.readings.len() as u16 * self.encoding.width();
+        vec![
+            Register::new("LATCH", SENSOR_LATCH, 1, WriteOnly),
+            Register::new("ENCODING", SENSOR_ENCODING, 1, ReadOnly),
+            Register::new("CHANNELS", SENSOR_CHANNELS, 1, ReadOnly),
+            Register::new("RANGE", SENSOR_RANGE, 2, ReadOnly),
+            Register::new("RESOLUTION", SENSOR_RESOLUTION, 4, ReadOnly),
+            Register::new("VALUES", SENSOR_VALUES, values, ReadOnly),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, _cycles: u32) {
+        self.write_descriptor(bus);
+        if bus.was_written(self.base_address + SENSOR_LATCH) {
+            self.latch(bus);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const BASE: u16 = 0x0240;
+
+    // Latch, then copy the descriptor and the values to $10 on. `peek` copies without latching.
+    const SOURCE: &str = "
+.org $0600
+read:
+    LDA #$01
+    STA $0240
+    NOP
+peek:
+    LDX #$00
+copy:
+    LDA $0241,X
+    STA $10,X
+    INX
+    CPX #$10
+    BNE copy
+    RTS
+zero:
+    LDA #$00
+    STA $0240
+    JMP peek
+";
+
+    fn test(encoding: Encoding, range: f64) -> RoutineTest {
+        let test = RoutineTest::new(SOURCE).unwrap();
+        let sensor = Sensor::new("IMU", BASE, 3, encoding, range);
+        test.cpu().attach_device(Box::new(sensor)).unwrap();
+        test
+    }
+
+    fn set(test: &RoutineTest, readings: &[f64]) {
+        test.cpu().with_device("IMU", |sensor: &mut Sensor| {
+            for (channel, value) in readings.iter().enumerate() {
+                sensor.set_reading(channel, *value);
+            }
+        });
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(Encoding::Unsigned8.encode(0.0, 25.0), [128]);
+        assert_eq!(Encoding::Unsigned8.encode(-30.0, 25.0), [0]);
+        assert_eq!(
+            Encoding::Signed16.encode(-25.0, 25.0),
+            (-32767i16).to_le_bytes()
+        );
+        assert_eq!(Encoding::Fixed8_8.encode(-1.5, 100.0), [0x80, 0xFE]);
+        assert_eq!(Encoding::Fixed8_8.encode(1000.0, 127.0), [0x00, 0x7F]);
+        assert_eq!(
+            Encoding::Fixed16_16.encode(3.25, 100.0),
+            [0x00, 0x40, 0x03, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_latched_values() {
+        let mut test = test(Encoding::Fixed8_8, 25.0);
+        set(&test, &[1.5, -2.0, 40.0]);
+        test.call("read").unwrap();
+        test.assert_memory(0x10, &[2, 3, 25, 0, 0x00, 0x01, 0x00, 0x00]);
+        test.assert_memory(0x18, &[0x80, 0x01, 0x00, 0xFE, 0x00, 0x19]);
+
+        // Nothing changes until the program latches again
+        set(&test, &[0.0, 0.0, 0.0]);
+        test.call("peek").unwrap();
+        test.assert_memory(0x18, &[0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_latch_with_zero() {
+        let mut test = test(Encoding::Fixed8_8, 25.0);
+        set(&test, &[1.5, -2.0, 40.0]);
+        test.call("zero").unwrap();
+        test.assert_memory(0x18, &[0x80, 0x01, 0x00, 0xFE, 0x00, 0x19]);
+    }
+
+    #[test]
+    fn test_descriptor() {
+        let mut test = test(Encoding::Signed16, 10.0);
+        test.call("read").unwrap();
+        // 10 / 32767 is 20 in 16.16
+        test.assert_memory(0x10, &[1, 3, 10, 0, 20, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_range_beyond_encoding() {
+        let mut test = test(Encoding::Fixed8_8, 1000.0);
+        set(&test, &[1000.0, -1000.0, 100.0]);
+        test.call("read").unwrap();
+        // RANGE says 127, which is where the readings stop
+        test.assert_memory(0x10, &[2, 3, 127, 0]);
+        test.assert_memory(0x18, &[0x00, 0x7F, 0x00, 0x81, 0x00, 0x64]);
+    }
+}
@@ -0,0 +1,372 @@
+// Memory shared between CPUs: dual-port RAM windows and mailboxes with doorbell interrupts.
+//
+// Every CPU keeps its own 64k, so sharing works by synchronising after each instruction: a byte the
+// instruction stored in the window is published to the shared buffer and queued for the other
+// windows, which copy it in after their own next instruction. When two CPUs write the same byte
+// between syncs the last one to run wins. Only stores made by the CPU are seen, bytes written into
+// the window by DMA or from GDScript stay local. A window leaves the buffer when it is dropped, so
+// one that was refused or detached no longer collects bytes.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+pub struct SharedMemory {
+    data: Vec<u8>,
+    pending: BTreeMap<usize, BTreeSet<u16>>, // offsets each window has yet to copy in
+    next_window: usize,
+}
+
+impl SharedMemory {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn add_window(&mut self) -> usize {
+        let window = self.next_window;
+        self.next_window += 1;
+        self.pending.insert(window, BTreeSet::new());
+        window
+    }
+
+    fn publish(&mut self, window: usize, offset: u16, value: u8) {
+        self.data[offset as usize] = value;
+        for (i, pending) in self.pending.iter_mut() {
+            if *i != window {
+                pending.insert(offset);
+            }
+        }
+    }
+}
+
+pub type SharedBuffer = Rc<RefCell<SharedMemory>>;
+
+pub fn shared_buffer(size: u16) -> SharedBuffer {
+    Rc::new(RefCell::new(SharedMemory {
+        data: vec![0; size as usize],
+        pending: BTreeMap::new(),
+        next_window: 0,
+    }))
+}
+
+// One CPU's view of a shared buffer, mapped at `base_address`
+pub struct SharedWindow {
+    base_address: u16,
+    buffer: SharedBuffer,
+    window: usize,
+    synced: bool,
+}
+
+impl SharedWindow {
+    pub fn new(base_address: u16, buffer: SharedBuffer) -> Self {
+        let window = buffer.borrow_mut().add_window();
+        Self {
+            base_address,
+            buffer,
+            window,
+            synced: false,
+        }
+    }
+}
+
+impl ShipDevice for SharedWindow {
+    fn name(&self) -> &str {
+        "shared_memory"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        self.buffer.borrow().data.len() as u16
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![Register::new("DATA", 0, self.size(), ReadWrite)]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, _cycles: u32) {
+        let mut shared = self.buffer.borrow_mut();
+        if !self.synced {
+            // First sync: the window takes the shared contents
+            for (i, byte) in shared.data.iter().enumerate() {
+                bus.write(self.base_address.wrapping_add(i as u16), *byte);
+            }
+            if let Some(pending) = shared.pending.get_mut(&self.window) {
+                pending.clear();
+            }
+            self.synced = true;
+        }
+
+        if let Some(address) = bus.written_address() {
+            let offset = address.wrapping_sub(self.base_address);
+            if (offset as usize) < shared.data.len() {
+                let value = bus.read(address);
+                shared.publish(self.window, offset, value);
+            }
+        }
+
+        let pending = shared.pending.get_mut(&self.window).map(std::mem::take);
+        for offset in pending.unwrap_or_default() {
+            let value = shared.data[offset as usize];
+            bus.write(self.base_address.wrapping_add(offset), value);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Drop for SharedWindow {
+    fn drop(&mut self) {
+        self.buffer.borrow_mut().pending.remove(&self.window);
+    }
+}
+
+// Mailbox registers, relative to the base address
+pub const MAILBOX_STATUS: u16 = 0x00; // read: bit 7 message received, bit 6 outgoing message not taken yet,
+                                      // bit 5 the last send was refused as the outgoing one was not taken yet
+pub const MAILBOX_CONTROL: u16 = 0x01; // write: bit 0 raise an IRQ while a message is waiting
+pub const MAILBOX_COMMAND: u16 = 0x02; // strobe: $01 send TX buffer, $02 release RX buffer
+pub const MAILBOX_TX: u16 = 0x04;
+pub const MAILBOX_RX: u16 = MAILBOX_TX + MAILBOX_MESSAGE_SIZE;
+pub const MAILBOX_MESSAGE_SIZE: u16 = 16;
+pub const MAILBOX_SIZE: u16 = MAILBOX_RX + MAILBOX_MESSAGE_SIZE;
+
+pub const MAILBOX_STATUS_RX_READY: u8 = 0b1000_0000;
+pub const MAILBOX_STATUS_TX_FULL: u8 = 0b0100_0000;
+pub const MAILBOX_STATUS_TX_REFUSED: u8 = 0b0010_0000;
+pub const MAILBOX_CONTROL_IRQ: u8 = 0b0000_0001;
+pub const MAILBOX_COMMAND_SEND: u8 = 0x01;
+pub const MAILBOX_COMMAND_ACK: u8 = 0x02;
+
+// One pending message in each direction
+#[derive(Default)]
+struct MailboxLink {
+    slots: [Option<Vec<u8>>; 2],
+}
+
+// One end of a mailbox. A message sent on one end waits in the other's RX buffer until released.
+pub struct Mailbox {
+    base_address: u16,
+    link: Rc<RefCell<MailboxLink>>,
+    side: usize,
+    delivered: bool,
+    refused: bool, // until the next send goes through
+}
+
+impl Mailbox {
+    pub fn pair(base_a: u16, base_b: u16) -> (Mailbox, Mailbox) {
+        let link = Rc::new(RefCell::new(MailboxLink::default()));
+        let end = |base_address, side| Mailbox {
+            base_address,
+            link: link.clone(),
+            side,
+            delivered: false,
+            refused: false,
+        };
+        (end(base_a, 0), end(base_b, 1))
+    }
+}
+
+impl ShipDevice for Mailbox {
+    fn name(&self) -> &str {
+        "mailbox"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        MAILBOX_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("STATUS", MAILBOX_STATUS, 1, ReadOnly).with_fields(&[
+                BitField("RX_READY", MAILBOX_STATUS_RX_READY),
+                BitField("TX_FULL", MAILBOX_STATUS_TX_FULL),
+                BitField("TX_REFUSED", MAILBOX_STATUS_TX_REFUSED),
+            ]),
+            Register::new("CONTROL", MAILBOX_CONTROL, 1, ReadWrite)
+                .with_fields(&[BitField("IRQ", MAILBOX_CONTROL_IRQ)]),
+            Register::new("COMMAND", MAILBOX_COMMAND, 1, WriteOnly),
+            Register::new("TX", MAILBOX_TX, MAILBOX_MESSAGE_SIZE, ReadWrite),
+            Register::new("RX", MAILBOX_RX, MAILBOX_MESSAGE_SIZE, ReadOnly),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, _cycles: u32) {
+        let base = self.base_address;
+        let mut link = self.link.borrow_mut();
+        let (inbox, outbox) = (self.side, 1 - self.side);
+
+        match bus.read(base + MAILBOX_COMMAND) {
+            0 => (),
+            command => {
+                if command == MAILBOX_COMMAND_SEND && link.slots[outbox].is_some() {
+                    // Left to the program to send again once TX_FULL clears
+                    self.refused = true;
+                } else if command == MAILBOX_COMMAND_SEND {
+                    let message = (0..MAILBOX_MESSAGE_SIZE)
+                        .map(|i| bus.read(base + MAILBOX_TX + i))
+                        .collect();
+                    link.slots[outbox] = Some(message);
+                    self.refused = false;
+                } else if command == MAILBOX_COMMAND_ACK {
+                    link.slots[inbox] = None;
+                    self.delivered = false;
+                }
+                bus.write(base + MAILBOX_COMMAND, 0);
+            }
+        }
+
+        if let (Some(message), false) = (&link.slots[inbox], self.delivered) {
+            for (i, byte) in message.iter().enumerate() {
+                bus.write(base + MAILBOX_RX + i as u16, *byte);
+            }
+            self.delivered = true;
+        }
+
+        let mut status = 0;
+        if self.delivered {
+            status |= MAILBOX_STATUS_RX_READY;
+        }
+        if link.slots[outbox].is_some() {
+            status |= MAILBOX_STATUS_TX_FULL;
+        }
+        if self.refused {
+            status |= MAILBOX_STATUS_TX_REFUSED;
+        }
+        bus.write(base + MAILBOX_STATUS, status);
+
+        // Doorbell
+        if self.delivered && bus.read(base + MAILBOX_CONTROL) & MAILBOX_CONTROL_IRQ != 0 {
+            bus.irq();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_wrapper::CPUWrapper;
+    use std::collections::HashMap;
+
+    // LDA #value; STA address
+    fn store(value: u8, address: u16) -> Vec<u8> {
+        vec![0xA9, value, 0x8D, address as u8, (address >> 8) as u8]
+    }
+
+    #[test]
+    fn test_shared_window() {
+        let a = CPUWrapper::new(0x0600, store(0x42, 0x0300), HashMap::new());
+        let b = CPUWrapper::new(0x0600, store(0x17, 0x0401), HashMap::new());
+        let buffer = shared_buffer(4);
+        a.attach_device(Box::new(SharedWindow::new(0x0300, buffer.clone())))
+            .unwrap();
+        b.attach_device(Box::new(SharedWindow::new(0x0400, buffer.clone())))
+            .unwrap();
+
+        a.run_cycles(6);
+        b.run_cycles(6);
+        a.run_step();
+
+        assert_eq!(b.read_byte(0x0400), 0x42);
+        assert_eq!(a.read_byte(0x0301), 0x17);
+        assert_eq!(buffer.borrow().data(), [0x42, 0x17, 0, 0]);
+    }
+
+    #[test]
+    fn test_refused_and_detached_windows() {
+        let a = CPUWrapper::new(0x0600, store(0x42, 0x0300), HashMap::new());
+        let b = CPUWrapper::new(0x0600, vec![0xEA], HashMap::new());
+        let buffer = shared_buffer(4);
+        a.attach_device(Box::new(SharedWindow::new(0x0300, buffer.clone())))
+            .unwrap();
+        b.attach_device(Box::new(SharedWindow::new(0x0400, shared_buffer(4))))
+            .unwrap();
+
+        // $0400 is taken, so the window is refused and drops out of the buffer
+        assert!(b
+            .attach_device(Box::new(SharedWindow::new(0x0400, buffer.clone())))
+            .is_err());
+        b.attach_device(Box::new(SharedWindow::new(0x0500, buffer.clone())))
+            .unwrap();
+        assert!(b.detach_device(0x0500));
+
+        a.run_cycles(6);
+        assert_eq!(buffer.borrow().data(), [0x42, 0, 0, 0]);
+        assert_eq!(buffer.borrow().pending.len(), 1);
+    }
+
+    #[test]
+    fn test_mailbox_doorbell() {
+        let base = 0x0300;
+        let mut program = store(0x99, base + MAILBOX_TX);
+        program.extend(store(MAILBOX_COMMAND_SEND, base + MAILBOX_COMMAND));
+        let a = CPUWrapper::new(0x0600, program, HashMap::new());
+        // CLI; JMP *
+        let b = CPUWrapper::new(0x0600, vec![0x58, 0x4C, 0x01, 0x06], HashMap::new());
+        b.write_byte(0xFFFE, 0x00);
+        b.write_byte(0xFFFF, 0x07);
+        b.write_byte(0x0700, 0x40); // RTI
+
+        let (end_a, end_b) = Mailbox::pair(base, base);
+        a.attach_device(Box::new(end_a)).unwrap();
+        b.attach_device(Box::new(end_b)).unwrap();
+        b.write_byte(base + MAILBOX_CONTROL, MAILBOX_CONTROL_IRQ);
+
+        a.run_cycles(12);
+        assert_eq!(a.read_byte(base + MAILBOX_STATUS), MAILBOX_STATUS_TX_FULL);
+
+        b.run_cycles(2);
+        b.run_step();
+        assert_eq!(b.read_byte(base + MAILBOX_STATUS), MAILBOX_STATUS_RX_READY);
+        assert_eq!(b.read_byte(base + MAILBOX_RX), 0x99);
+        assert_eq!(b.get_cpu().borrow().regs.pc, 0x0700);
+
+        b.write_byte(base + MAILBOX_COMMAND, MAILBOX_COMMAND_ACK);
+        b.run_step();
+        a.run_step();
+        assert_eq!(b.read_byte(base + MAILBOX_STATUS), 0);
+        assert_eq!(a.read_byte(base + MAILBOX_STATUS), 0);
+    }
+
+    #[test]
+    fn test_mailbox_refused_send() {
+        let base = 0x0300;
+        let mut program = store(MAILBOX_COMMAND_SEND, base + MAILBOX_COMMAND);
+        program.extend(store(MAILBOX_COMMAND_SEND, base + MAILBOX_COMMAND));
+        let a = CPUWrapper::new(0x0600, program, HashMap::new());
+        let b = CPUWrapper::new(0x0600, vec![0xEA], HashMap::new());
+
+        let (end_a, end_b) = Mailbox::pair(base, base);
+        a.attach_device(Box::new(end_a)).unwrap();
+        b.attach_device(Box::new(end_b)).unwrap();
+
+        a.run_cycles(12);
+        assert_eq!(
+            a.read_byte(base + MAILBOX_STATUS),
+            MAILBOX_STATUS_TX_FULL | MAILBOX_STATUS_TX_REFUSED
+        );
+
+        b.run_step();
+        b.write_byte(base + MAILBOX_COMMAND, MAILBOX_COMMAND_ACK);
+        b.run_step();
+        a.write_byte(base + MAILBOX_COMMAND, MAILBOX_COMMAND_SEND);
+        a.run_step();
+        assert_eq!(a.read_byte(base + MAILBOX_STATUS), MAILBOX_STATUS_TX_FULL);
+    }
+}
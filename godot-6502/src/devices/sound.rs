@@ -0,0 +1,348 @@
+// Programmable sound generator: three tone channels, a noise channel and one ADSR envelope.
+//
+// Each channel has a frequency, a volume from 0 to 15 and, for the tone channels, a waveform.
+// Channels with SOUND_WAVE_ENVELOPE set in WAVE are also scaled by the envelope, which GATE starts
+// (attack, decay, then sustain) and releases. The noise channel shifts a 15-bit LFSR at its
+// frequency. The device renders mono samples as the CPU runs, sample_rate of them per emulated
+// second, so the sound follows emulated time and the same program always makes the same samples.
+
+use std::any::Any;
+use std::collections::VecDeque;
+
+use super::RegisterAccess::ReadWrite;
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address. Channel n uses SOUND_CHANNEL_SIZE bytes from
+// n * SOUND_CHANNEL_SIZE, channel 3 is the noise channel.
+pub const SOUND_FREQUENCY: u16 = 0x00; // 16 bit, Hz
+pub const SOUND_VOLUME: u16 = 0x02; // 0 to 15
+pub const SOUND_WAVE: u16 = 0x03;
+pub const SOUND_CHANNEL_SIZE: u16 = 4;
+pub const SOUND_ATTACK: u16 = 0x10; // in 10 ms steps, 0 is immediate
+pub const SOUND_DECAY: u16 = 0x11; // in 10 ms steps
+pub const SOUND_SUSTAIN: u16 = 0x12; // 0 to 15
+pub const SOUND_RELEASE: u16 = 0x13; // in 10 ms steps
+pub const SOUND_GATE: u16 = 0x14; // 1 starts the envelope, 0 releases it
+pub const SOUND_SIZE: u16 = 0x15;
+
+pub const SOUND_CHANNELS: usize = 4;
+pub const SOUND_NOISE_CHANNEL: usize = 3;
+
+pub const SOUND_WAVE_SQUARE: u8 = 0x00;
+pub const SOUND_WAVE_TRIANGLE: u8 = 0x01;
+pub const SOUND_WAVE_SAWTOOTH: u8 = 0x02;
+pub const SOUND_WAVE_SHAPE: u8 = 0b0000_0011;
+pub const SOUND_WAVE_ENVELOPE: u8 = 0b1000_0000;
+
+pub const SOUND_DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+// Samples kept for Godot before the oldest are dropped, a second at the default rate
+pub const SOUND_BUFFER_CAPACITY: usize = 44_100;
+
+const CHANNEL_REGISTERS: [[&str; 3]; SOUND_CHANNELS] = [
+    ["TONE_A_FREQUENCY", "TONE_A_VOLUME", "TONE_A_WAVE"],
+    ["TONE_B_FREQUENCY", "TONE_B_VOLUME", "TONE_B_WAVE"],
+    ["TONE_C_FREQUENCY", "TONE_C_VOLUME", "TONE_C_WAVE"],
+    ["NOISE_FREQUENCY", "NOISE_VOLUME", "NOISE_WAVE"],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+pub struct Sound {
+    base_address: u16,
+    frequency: u32, // CPU cycles per emulated second
+    sample_rate: u32,
+    remainder: u64, // cycles * sample_rate not yet turned into a sample
+    phases: [u32; SOUND_CHANNELS],
+    lfsr: u16,
+    gate: bool,
+    stage: Stage,
+    level: f32, // envelope, 0 to 1
+    samples: VecDeque<f32>,
+}
+
+impl Sound {
+    pub fn new(base_address: u16, sample_rate: u32, frequency: u32) -> Self {
+        Self {
+            base_address,
+            frequency,
+            sample_rate,
+            remainder: 0,
+            phases: [0; SOUND_CHANNELS],
+            lfsr: 0x4000,
+            gate: false,
+            stage: Stage::Release,
+            level: 0.0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.frequency = frequency;
+    }
+
+    // Samples rendered since the last call, at most `max`, from -1.0 to 1.0
+    pub fn pop_samples(&mut self, max: usize) -> Vec<f32> {
+        let count = max.min(self.samples.len());
+        self.samples.drain(..count).collect()
+    }
+
+    // Per-sample change of the envelope for a time in 10 ms steps
+    fn envelope_step(&self, steps: u8) -> f32 {
+        match steps {
+            0 => 1.0,
+            steps => 100.0 / (steps as f32 * self.sample_rate as f32),
+        }
+    }
+
+    fn advance_envelope(&mut self, registers: &[u8]) {
+        let sustain = (registers[SOUND_SUSTAIN as usize] & 0x0F) as f32 / 15.0;
+        match self.stage {
+            Stage::Attack => {
+                self.level += self.envelope_step(registers[SOUND_ATTACK as usize]);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= self.envelope_step(registers[SOUND_DECAY as usize]);
+                if self.level <= sustain {
+                    self.level = sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = sustain,
+            Stage::Release => {
+                self.level -= self.envelope_step(registers[SOUND_RELEASE as usize]);
+                self.level = self.level.max(0.0);
+            }
+        }
+    }
+
+    fn render_sample(&mut self, registers: &[u8]) -> f32 {
+        self.advance_envelope(registers);
+
+        let mut mix = 0.0;
+        for channel in 0..SOUND_CHANNELS {
+            let offset = channel * SOUND_CHANNEL_SIZE as usize;
+            let frequency = u16::from_le_bytes([registers[offset], registers[offset + 1]]) as u32;
+            let frequency = frequency.min(self.sample_rate / 2);
+            let volume = (registers[offset + SOUND_VOLUME as usize] & 0x0F) as f32 / 15.0;
+            let wave = registers[offset + SOUND_WAVE as usize];
+
+            // Phase as a fraction of a turn, 2^32 is a whole period
+            let increment = (frequency as u64 * (1 << 32) / self.sample_rate as u64) as u32;
+            let (phase, wrapped) = self.phases[channel].overflowing_add(increment);
+            self.phases[channel] = phase;
+            if frequency == 0 || volume == 0.0 {
+                continue;
+            }
+
+            let value = if channel == SOUND_NOISE_CHANNEL {
+                if wrapped {
+                    let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                    self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+                }
+                match self.lfsr & 1 {
+                    0 => -1.0,
+                    _ => 1.0,
+                }
+            } else {
+                let turn = phase as f32 / 4_294_967_296.0;
+                match wave & SOUND_WAVE_SHAPE {
+                    SOUND_WAVE_TRIANGLE if turn < 0.5 => 4.0 * turn - 1.0,
+                    SOUND_WAVE_TRIANGLE => 3.0 - 4.0 * turn,
+                    SOUND_WAVE_SAWTOOTH => 2.0 * turn - 1.0,
+                    _ if phase < 1 << 31 => 1.0,
+                    _ => -1.0,
+                }
+            };
+            let envelope = match wave & SOUND_WAVE_ENVELOPE {
+                0 => 1.0,
+                _ => self.level,
+            };
+            mix += value * volume * envelope;
+        }
+        mix / SOUND_CHANNELS as f32
+    }
+}
+
+impl ShipDevice for Sound {
+    fn name(&self) -> &str {
+        "sound"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        SOUND_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        let mut registers = Vec::new();
+        for (channel, [frequency, volume, wave]) in CHANNEL_REGISTERS.into_iter().enumerate() {
+            let offset = channel as u16 * SOUND_CHANNEL_SIZE;
+            registers.push(Register::new(
+                frequency,
+                offset + SOUND_FREQUENCY,
+                2,
+                ReadWrite,
+            ));
+            registers.push(Register::new(volume, offset + SOUND_VOLUME, 1, ReadWrite));
+            registers.push(
+                Register::new(wave, offset + SOUND_WAVE, 1, ReadWrite).with_fields(&[
+                    BitField("SHAPE", SOUND_WAVE_SHAPE),
+                    BitField("ENVELOPE", SOUND_WAVE_ENVELOPE),
+                ]),
+            );
+        }
+        registers.extend([
+            Register::new("ATTACK", SOUND_ATTACK, 1, ReadWrite),
+            Register::new("DECAY", SOUND_DECAY, 1, ReadWrite),
+            Register::new("SUSTAIN", SOUND_SUSTAIN, 1, ReadWrite),
+            Register::new("RELEASE", SOUND_RELEASE, 1, ReadWrite),
+            Register::new("GATE", SOUND_GATE, 1, ReadWrite),
+        ]);
+        registers
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+        let registers: Vec<u8> = (0..SOUND_SIZE).map(|i| bus.read(base + i)).collect();
+
+        let gate = registers[SOUND_GATE as usize] & 1 != 0;
+        if gate != self.gate {
+            self.gate = gate;
+            self.stage = match gate {
+                true => Stage::Attack,
+                false => Stage::Release,
+            };
+        }
+
+        self.remainder += cycles as u64 * self.sample_rate as u64;
+        let frequency = self.frequency.max(1) as u64;
+        while self.remainder >= frequency {
+            self.remainder -= frequency;
+            let sample = self.render_sample(&registers);
+            if self.samples.len() == SOUND_BUFFER_CAPACITY {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const BASE: u16 = 0x02E0;
+
+    const SOURCE: &str = "
+.org $0600
+; 1 kHz square wave at full volume on tone A
+beep:
+    LDA #$E8
+    STA $02E0
+    LDA #$03
+    STA $02E1
+    LDA #$0F
+    STA $02E2
+    RTS
+
+; noise at full volume, shaped by an envelope with a 10 ms attack
+noise:
+    LDA #$FF
+    STA $02EC
+    STA $02ED
+    LDA #$0F
+    STA $02EE
+    LDA #$80
+    STA $02EF
+    LDA #$01
+    STA $02F0 ; attack
+    LDA #$08
+    STA $02F2 ; sustain
+    LDA #$01
+    STA $02F4 ; gate
+    RTS
+
+; about 800 cycles
+wait:
+    LDX #$A0
+delay:
+    DEX
+    BNE delay
+    RTS
+";
+
+    // One sample per cycle
+    fn test() -> RoutineTest {
+        let test = RoutineTest::new(SOURCE).unwrap();
+        test.cpu()
+            .attach_device(Box::new(Sound::new(BASE, 8_000, 8_000)))
+            .unwrap();
+        test
+    }
+
+    fn samples(test: &RoutineTest) -> Vec<f32> {
+        test.cpu()
+            .with_device("sound", |sound: &mut Sound| sound.pop_samples(usize::MAX))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_square_wave() {
+        let mut test = test();
+        test.call("beep").unwrap();
+        samples(&test);
+        let result = test.call("wait").unwrap();
+
+        let samples = samples(&test);
+        assert_eq!(samples.len() as u64, result.cycles);
+        // 8 samples per period, half of them high
+        for (i, sample) in samples.iter().enumerate().skip(8).take(32) {
+            assert_eq!(sample.abs(), 0.25, "sample {i}");
+        }
+        let high = samples.iter().filter(|sample| **sample > 0.0).count();
+        assert!(high.abs_diff(samples.len() / 2) <= 4);
+    }
+
+    #[test]
+    fn test_noise_envelope_is_deterministic() {
+        let run = || {
+            let mut test = test();
+            test.call("noise").unwrap();
+            samples(&test);
+            test.call("wait").unwrap();
+            test.call("wait").unwrap();
+            samples(&test)
+        };
+        let samples = run();
+        assert_eq!(samples, run());
+
+        // 80 samples of attack, then down to the sustain level at once
+        let peak = |range: std::ops::Range<usize>| {
+            samples[range]
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+        assert!(peak(0..20) < 0.1);
+        assert!(peak(60..80) > 0.2);
+        assert!((peak(200..400) - 0.25 * 8.0 / 15.0).abs() < 0.001);
+    }
+}
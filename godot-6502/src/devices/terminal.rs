@@ -0,0 +1,366 @@
+// Text terminal: 40 columns by 25 rows of characters from a built-in 8x8 font.
+//
+// Character RAM holds one ASCII code per cell and color RAM its colors, the foreground in the low
+// nibble and the background in the high nibble, both from the display palette. Programs can write
+// the cells directly, or write a character to PUTC to print it at the cursor with COLOR: the cursor
+// moves on, CR returns, LF starts a new line, backspace erases, and the screen scrolls up when the
+// cursor runs off the last row. Like the bitmap display the screen is only rendered again at a
+// refresh that finds it changed.
+
+use std::any::Any;
+
+use super::display::{DEFAULT_PALETTE, DISPLAY_REFRESH_HZ};
+use super::font::{FONT, FONT_FIRST};
+use super::RegisterAccess::{ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const TERMINAL_CONTROL: u16 = 0x00;
+pub const TERMINAL_CURSOR_X: u16 = 0x01;
+pub const TERMINAL_CURSOR_Y: u16 = 0x02;
+pub const TERMINAL_COLOR: u16 = 0x03; // colors of printed characters
+pub const TERMINAL_PUTC: u16 = 0x04; // strobe: print a character
+pub const TERMINAL_COMMAND: u16 = 0x05; // strobe
+pub const TERMINAL_CHARS: u16 = 0x10;
+pub const TERMINAL_COLORS: u16 = TERMINAL_CHARS + TERMINAL_CELLS;
+pub const TERMINAL_SIZE: u16 = TERMINAL_COLORS + TERMINAL_CELLS;
+
+pub const TERMINAL_COLUMNS: u16 = 40;
+pub const TERMINAL_ROWS: u16 = 25;
+pub const TERMINAL_CELLS: u16 = TERMINAL_COLUMNS * TERMINAL_ROWS;
+
+pub const TERMINAL_CONTROL_CURSOR: u8 = 0b0000_0001; // show the cursor
+
+pub const TERMINAL_CLEAR: u8 = 0x01; // blank every cell with COLOR, cursor home
+
+pub const TERMINAL_DEFAULT_COLOR: u8 = 0x01; // white on black
+
+// Size of the rendered screen in pixels
+pub const TERMINAL_WIDTH: usize = TERMINAL_COLUMNS as usize * 8;
+pub const TERMINAL_HEIGHT: usize = TERMINAL_ROWS as usize * 8;
+
+pub struct Terminal {
+    base_address: u16,
+    frequency: u32, // CPU cycles per emulated second
+    cycles: u32,    // since the last refresh
+    powered: bool,
+    scanned: Vec<u8>, // registers, characters and colors as of the last rendered frame
+    pixels: Vec<u8>,  // RGBA8
+    dirty: bool,
+}
+
+impl Terminal {
+    pub fn new(base_address: u16, frequency: u32) -> Self {
+        Self {
+            base_address,
+            frequency,
+            cycles: 0,
+            powered: false,
+            scanned: Vec::new(),
+            pixels: [0, 0, 0, 0xFF].repeat(TERMINAL_WIDTH * TERMINAL_HEIGHT),
+            dirty: true,
+        }
+    }
+
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.frequency = frequency;
+    }
+
+    // The screen as RGBA8 pixels if it changed since the last call
+    pub fn take_frame(&mut self) -> Option<Vec<u8>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(self.pixels.clone())
+    }
+
+    fn clear(&self, bus: &mut DeviceBus) {
+        let base = self.base_address;
+        let color = bus.read(base + TERMINAL_COLOR);
+        for cell in 0..TERMINAL_CELLS {
+            bus.write(base + TERMINAL_CHARS + cell, b' ');
+            bus.write(base + TERMINAL_COLORS + cell, color);
+        }
+        bus.write(base + TERMINAL_CURSOR_X, 0);
+        bus.write(base + TERMINAL_CURSOR_Y, 0);
+    }
+
+    fn scroll(&self, bus: &mut DeviceBus) {
+        let base = self.base_address;
+        for ram in [TERMINAL_CHARS, TERMINAL_COLORS] {
+            for cell in 0..TERMINAL_CELLS - TERMINAL_COLUMNS {
+                let below = bus.read(base + ram + cell + TERMINAL_COLUMNS);
+                bus.write(base + ram + cell, below);
+            }
+        }
+        let color = bus.read(base + TERMINAL_COLOR);
+        for cell in TERMINAL_CELLS - TERMINAL_COLUMNS..TERMINAL_CELLS {
+            bus.write(base + TERMINAL_CHARS + cell, b' ');
+            bus.write(base + TERMINAL_COLORS + cell, color);
+        }
+    }
+
+    fn put_char(&self, bus: &mut DeviceBus, c: u8) {
+        let base = self.base_address;
+        let mut x = (bus.read(base + TERMINAL_CURSOR_X) as u16).min(TERMINAL_COLUMNS - 1);
+        let mut y = (bus.read(base + TERMINAL_CURSOR_Y) as u16).min(TERMINAL_ROWS - 1);
+
+        match c {
+            b'\r' => x = 0,
+            b'\n' => {
+                x = 0;
+                y += 1;
+            }
+            0x08 => {
+                x = x.saturating_sub(1);
+                bus.write(base + TERMINAL_CHARS + y * TERMINAL_COLUMNS + x, b' ');
+            }
+            _ => {
+                let color = bus.read(base + TERMINAL_COLOR);
+                bus.write(base + TERMINAL_CHARS + y * TERMINAL_COLUMNS + x, c);
+                bus.write(base + TERMINAL_COLORS + y * TERMINAL_COLUMNS + x, color);
+                x += 1;
+                if x == TERMINAL_COLUMNS {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+        if y == TERMINAL_ROWS {
+            self.scroll(bus);
+            y -= 1;
+        }
+
+        bus.write(base + TERMINAL_CURSOR_X, x as u8);
+        bus.write(base + TERMINAL_CURSOR_Y, y as u8);
+    }
+
+    fn refresh(&mut self, bus: &mut DeviceBus) {
+        let base = self.base_address;
+        let scanned: Vec<u8> = (0..TERMINAL_SIZE).map(|i| bus.read(base + i)).collect();
+        if scanned != self.scanned {
+            self.scanned = scanned;
+            self.render();
+        }
+    }
+
+    fn render(&mut self) {
+        let registers = &self.scanned;
+        let chars = &registers[TERMINAL_CHARS as usize..TERMINAL_COLORS as usize];
+        let colors = &registers[TERMINAL_COLORS as usize..];
+        let cursor = match registers[TERMINAL_CONTROL as usize] & TERMINAL_CONTROL_CURSOR {
+            0 => None,
+            _ => Some(
+                registers[TERMINAL_CURSOR_Y as usize] as usize * TERMINAL_COLUMNS as usize
+                    + registers[TERMINAL_CURSOR_X as usize] as usize,
+            ),
+        };
+
+        for cell in 0..TERMINAL_CELLS as usize {
+            let glyph = chars[cell]
+                .checked_sub(FONT_FIRST)
+                .and_then(|index| FONT.get(index as usize))
+                .unwrap_or(&FONT[0]);
+            let mut foreground = DEFAULT_PALETTE[(colors[cell] & 0x0F) as usize];
+            let mut background = DEFAULT_PALETTE[(colors[cell] >> 4) as usize];
+            if cursor == Some(cell) {
+                std::mem::swap(&mut foreground, &mut background);
+            }
+
+            let left = cell % TERMINAL_COLUMNS as usize * 8;
+            let top = cell / TERMINAL_COLUMNS as usize * 8;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..8 {
+                    let color = match bits >> column & 1 {
+                        0 => background,
+                        _ => foreground,
+                    };
+                    let pixel = ((top + row) * TERMINAL_WIDTH + left + column) * 4;
+                    self.pixels[pixel..pixel + 3].copy_from_slice(&color);
+                }
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+// Character RAM as text, one line per row without trailing blanks, and without trailing empty
+// rows. Anything outside printable ASCII reads as a blank.
+pub fn screen_text(chars: &[u8]) -> String {
+    let lines: Vec<String> = chars
+        .chunks(TERMINAL_COLUMNS as usize)
+        .map(|row| {
+            let line: String = row
+                .iter()
+                .map(|c| match c {
+                    0x20..=0x7E => *c as char,
+                    _ => ' ',
+                })
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect();
+    lines.join("\n").trim_end().to_string()
+}
+
+impl ShipDevice for Terminal {
+    fn name(&self) -> &str {
+        "terminal"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        TERMINAL_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("CONTROL", TERMINAL_CONTROL, 1, ReadWrite)
+                .with_fields(&[BitField("CURSOR", TERMINAL_CONTROL_CURSOR)]),
+            Register::new("CURSOR_X", TERMINAL_CURSOR_X, 1, ReadWrite),
+            Register::new("CURSOR_Y", TERMINAL_CURSOR_Y, 1, ReadWrite),
+            Register::new("COLOR", TERMINAL_COLOR, 1, ReadWrite),
+            Register::new("PUTC", TERMINAL_PUTC, 1, WriteOnly),
+            Register::new("COMMAND", TERMINAL_COMMAND, 1, WriteOnly),
+            Register::new("CHARS", TERMINAL_CHARS, TERMINAL_CELLS, ReadWrite),
+            Register::new("COLORS", TERMINAL_COLORS, TERMINAL_CELLS, ReadWrite),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+        if !self.powered {
+            bus.write(base + TERMINAL_COLOR, TERMINAL_DEFAULT_COLOR);
+            self.clear(bus);
+            self.powered = true;
+        }
+
+        let c = bus.read(base + TERMINAL_PUTC);
+        if c != 0 {
+            bus.write(base + TERMINAL_PUTC, 0);
+            self.put_char(bus, c);
+        }
+
+        let command = bus.read(base + TERMINAL_COMMAND);
+        if command != 0 {
+            bus.write(base + TERMINAL_COMMAND, 0);
+            if command == TERMINAL_CLEAR {
+                self.clear(bus);
+            }
+        }
+
+        self.cycles += cycles;
+        let period = (self.frequency / DISPLAY_REFRESH_HZ).max(1);
+        if self.cycles >= period {
+            self.cycles %= period;
+            self.refresh(bus);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const BASE: u16 = 0x1000;
+
+    // Print the zero-terminated string at message, or wait for a few refreshes
+    const SOURCE: &str = "
+.org $0600
+print:
+    LDX #$00
+next:
+    LDA message,X
+    BEQ done
+    STA $1004
+    INX
+    JMP next
+done:
+    RTS
+
+clear:
+    LDA #$01
+    STA $1005
+    RTS
+
+wait:
+    LDX #$00
+delay:
+    DEX
+    BNE delay
+    RTS
+
+message:
+    .byte $48, $49, $0A, $0A, $4F, $4B, $08, $3F, $00
+";
+
+    fn test() -> RoutineTest {
+        let test = RoutineTest::new(SOURCE).unwrap();
+        test.cpu()
+            .attach_device(Box::new(Terminal::new(BASE, 6_000)))
+            .unwrap();
+        test
+    }
+
+    fn text(test: &RoutineTest) -> String {
+        screen_text(&test.read_memory(BASE + TERMINAL_CHARS, TERMINAL_CELLS as usize))
+    }
+
+    #[test]
+    fn test_print() {
+        let mut test = test();
+        test.call("print").unwrap();
+        assert_eq!(text(&test), "HI\n\nO?");
+        test.assert_memory(BASE + TERMINAL_CURSOR_X, &[2, 2]);
+        test.assert_memory(BASE + TERMINAL_COLORS + 2 * 40, &[TERMINAL_DEFAULT_COLOR]);
+
+        test.call("clear").unwrap();
+        test.call("print").unwrap();
+        assert_eq!(text(&test), "HI\n\nO?");
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut test = test();
+        for _ in 0..13 {
+            test.call("print").unwrap();
+        }
+        let text = text(&test);
+        assert_eq!(text.lines().count(), TERMINAL_ROWS as usize);
+        assert!(text.starts_with("O?HI\n"), "the top rows scrolled away");
+        assert!(text.ends_with("\nO?HI\n\nO?"));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut test = test();
+        test.call("wait").unwrap(); // powers on with white on black
+        test.write_memory(BASE + TERMINAL_COLOR, &[0x25]); // green on red
+        test.call("print").unwrap();
+        test.call("wait").unwrap();
+        let frame = test
+            .cpu()
+            .with_device("terminal", |terminal: &mut Terminal| terminal.take_frame())
+            .unwrap()
+            .unwrap();
+
+        // The top row of "H" is ##..##.., its first pixel foreground and its third background
+        let pixel = |x: usize, y: usize| &frame[(y * TERMINAL_WIDTH + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [0x00, 0xCC, 0x55, 0xFF]);
+        assert_eq!(pixel(2, 0), [0x88, 0x00, 0x00, 0xFF]);
+        assert_eq!(
+            pixel(0, 8),
+            [0x00, 0x00, 0x00, 0xFF],
+            "blank cells stay black"
+        );
+    }
+}
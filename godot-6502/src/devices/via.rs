@@ -0,0 +1,360 @@
+// MOS 6522 Versatile Interface Adapter: two 16-bit timers and two 8-bit I/O ports.
+//
+// Registers follow the real chip. Timer 1 runs one-shot or free-running (ACR bit 6) and can
+// drive PB7 (ACR bit 7), timer 2 is one-shot only. Handshake lines CA1/CA2/CB1/CB2, the shift
+// register and T2 pulse counting are not emulated; SR, ACR and PCR are plain storage.
+// Ship parts bind to the ports from Godot through `set_port_input` and `port_output`.
+
+use std::any::Any;
+
+use super::RegisterAccess::ReadWrite;
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const VIA_ORB: u16 = 0x0;
+pub const VIA_ORA: u16 = 0x1;
+pub const VIA_DDRB: u16 = 0x2;
+pub const VIA_DDRA: u16 = 0x3;
+pub const VIA_T1CL: u16 = 0x4;
+pub const VIA_T1CH: u16 = 0x5;
+pub const VIA_T1LL: u16 = 0x6;
+pub const VIA_T1LH: u16 = 0x7;
+pub const VIA_T2CL: u16 = 0x8;
+pub const VIA_T2CH: u16 = 0x9;
+pub const VIA_SR: u16 = 0xA;
+pub const VIA_ACR: u16 = 0xB;
+pub const VIA_PCR: u16 = 0xC;
+pub const VIA_IFR: u16 = 0xD;
+pub const VIA_IER: u16 = 0xE;
+pub const VIA_ORA_NO_HANDSHAKE: u16 = 0xF;
+pub const VIA_SIZE: u16 = 16;
+
+pub const VIA_IRQ_ANY: u8 = 0b1000_0000;
+pub const VIA_IRQ_T1: u8 = 0b0100_0000;
+pub const VIA_IRQ_T2: u8 = 0b0010_0000;
+
+pub const VIA_ACR_T1_FREE_RUN: u8 = 0b0100_0000;
+pub const VIA_ACR_T1_PB7: u8 = 0b1000_0000;
+
+pub const PORT_A: u8 = 0;
+pub const PORT_B: u8 = 1;
+
+#[derive(Default)]
+struct Timer {
+    counter: i64,
+    latch: u16,
+    armed: bool, // raise the interrupt on the next underflow
+}
+
+pub struct Via {
+    base_address: u16,
+    ora: u8,
+    orb: u8,
+    input_a: u8, // levels driven on the pins by ship parts
+    input_b: u8,
+    ddra: u8,
+    ddrb: u8,
+    t1: Timer,
+    t2: Timer,
+    pb7: bool,
+    ifr: u8,
+    ier: u8,
+}
+
+impl Via {
+    pub fn new(base_address: u16) -> Self {
+        Self {
+            base_address,
+            ora: 0,
+            orb: 0,
+            input_a: 0xFF, // inputs float high
+            input_b: 0xFF,
+            ddra: 0,
+            ddrb: 0,
+            t1: Timer::default(),
+            t2: Timer::default(),
+            pb7: true,
+            ifr: 0,
+            ier: 0,
+        }
+    }
+
+    pub fn set_port_input(&mut self, port: u8, value: u8) {
+        match port {
+            PORT_A => self.input_a = value,
+            _ => self.input_b = value,
+        }
+    }
+
+    // Pin levels as seen from outside: outputs where DDR is set, inputs elsewhere
+    pub fn port_output(&self, port: u8) -> u8 {
+        match port {
+            PORT_A => pins(self.ora, self.input_a, self.ddra),
+            _ => pins(self.orb, self.input_b, self.ddrb),
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.ifr & self.ier & 0x7F != 0
+    }
+}
+
+fn pins(output: u8, input: u8, ddr: u8) -> u8 {
+    (output & ddr) | (input & !ddr)
+}
+
+impl ShipDevice for Via {
+    fn name(&self) -> &str {
+        "via"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        VIA_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("ORB", VIA_ORB, 1, ReadWrite),
+            Register::new("ORA", VIA_ORA, 1, ReadWrite),
+            Register::new("DDRB", VIA_DDRB, 1, ReadWrite),
+            Register::new("DDRA", VIA_DDRA, 1, ReadWrite),
+            Register::new("T1CL", VIA_T1CL, 1, ReadWrite),
+            Register::new("T1CH", VIA_T1CH, 1, ReadWrite),
+            Register::new("T1LL", VIA_T1LL, 1, ReadWrite),
+            Register::new("T1LH", VIA_T1LH, 1, ReadWrite),
+            Register::new("T2CL", VIA_T2CL, 1, ReadWrite),
+            Register::new("T2CH", VIA_T2CH, 1, ReadWrite),
+            Register::new("SR", VIA_SR, 1, ReadWrite),
+            Register::new("ACR", VIA_ACR, 1, ReadWrite).with_fields(&[
+                BitField("T1_FREE_RUN", VIA_ACR_T1_FREE_RUN),
+                BitField("T1_PB7", VIA_ACR_T1_PB7),
+            ]),
+            Register::new("PCR", VIA_PCR, 1, ReadWrite),
+            Register::new("IFR", VIA_IFR, 1, ReadWrite).with_fields(&[
+                BitField("ANY", VIA_IRQ_ANY),
+                BitField("T1", VIA_IRQ_T1),
+                BitField("T2", VIA_IRQ_T2),
+            ]),
+            Register::new("IER", VIA_IER, 1, ReadWrite).with_fields(&[
+                BitField("SET", VIA_IRQ_ANY),
+                BitField("T1", VIA_IRQ_T1),
+                BitField("T2", VIA_IRQ_T2),
+            ]),
+            Register::new("ORA_NO_HANDSHAKE", VIA_ORA_NO_HANDSHAKE, 1, ReadWrite),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+        let acr = bus.read(base + VIA_ACR);
+        let written = |bus: &DeviceBus, register| bus.was_written(base + register);
+        let mut t1_loaded = false;
+        let mut t2_loaded = false;
+
+        // Writes
+        if written(bus, VIA_ORB) {
+            self.orb = bus.read(base + VIA_ORB);
+        }
+        if written(bus, VIA_ORA) || written(bus, VIA_ORA_NO_HANDSHAKE) {
+            let register = match written(bus, VIA_ORA) {
+                true => VIA_ORA,
+                false => VIA_ORA_NO_HANDSHAKE,
+            };
+            self.ora = bus.read(base + register);
+        }
+        if written(bus, VIA_T1CL) || written(bus, VIA_T1LL) {
+            let register = match written(bus, VIA_T1CL) {
+                true => VIA_T1CL,
+                false => VIA_T1LL,
+            };
+            self.t1.latch = (self.t1.latch & 0xFF00) | bus.read(base + register) as u16;
+        }
+        if written(bus, VIA_T1CH) {
+            self.t1.latch = (self.t1.latch & 0x00FF) | (bus.read(base + VIA_T1CH) as u16) << 8;
+            self.t1.counter = self.t1.latch as i64;
+            self.t1.armed = true;
+            self.ifr &= !VIA_IRQ_T1;
+            if acr & VIA_ACR_T1_PB7 != 0 {
+                self.pb7 = false;
+            }
+            t1_loaded = true;
+        }
+        if written(bus, VIA_T1LH) {
+            self.t1.latch = (self.t1.latch & 0x00FF) | (bus.read(base + VIA_T1LH) as u16) << 8;
+            self.ifr &= !VIA_IRQ_T1;
+        }
+        if written(bus, VIA_T2CL) {
+            self.t2.latch = bus.read(base + VIA_T2CL) as u16;
+        }
+        if written(bus, VIA_T2CH) {
+            self.t2.counter = ((bus.read(base + VIA_T2CH) as u16) << 8 | self.t2.latch) as i64;
+            self.t2.armed = true;
+            self.ifr &= !VIA_IRQ_T2;
+            t2_loaded = true;
+        }
+        if written(bus, VIA_IFR) {
+            self.ifr &= !(bus.read(base + VIA_IFR) & 0x7F);
+        }
+        if written(bus, VIA_IER) {
+            let value = bus.read(base + VIA_IER);
+            match value & 0x80 {
+                0 => self.ier &= !value,
+                _ => self.ier |= value & 0x7F,
+            }
+        }
+
+        // Reads
+        if bus.was_read(base + VIA_T1CL) {
+            self.ifr &= !VIA_IRQ_T1;
+        }
+        if bus.was_read(base + VIA_T2CL) {
+            self.ifr &= !VIA_IRQ_T2;
+        }
+
+        // Timers start counting on the cycle after they are loaded
+        if !t1_loaded {
+            self.t1.counter -= cycles as i64;
+            while self.t1.counter < 0 {
+                let free_running = acr & VIA_ACR_T1_FREE_RUN != 0;
+                if self.t1.armed {
+                    self.ifr |= VIA_IRQ_T1;
+                    // PB7 goes high at the end of a one-shot and toggles when free-running
+                    self.pb7 = !free_running || !self.pb7;
+                }
+                if free_running {
+                    self.t1.counter += self.t1.latch as i64 + 2;
+                } else {
+                    self.t1.armed = false;
+                    self.t1.counter += 0x10000;
+                }
+            }
+        }
+        if !t2_loaded {
+            self.t2.counter -= cycles as i64;
+            if self.t2.counter < 0 {
+                if self.t2.armed {
+                    self.ifr |= VIA_IRQ_T2;
+                    self.t2.armed = false;
+                }
+                self.t2.counter = self.t2.counter.rem_euclid(0x10000);
+            }
+        }
+
+        // Reflect the state back into the registers
+        self.ddra = bus.read(base + VIA_DDRA);
+        self.ddrb = bus.read(base + VIA_DDRB);
+        let mut port_b = self.port_output(PORT_B);
+        if acr & VIA_ACR_T1_PB7 != 0 {
+            port_b = (port_b & 0x7F) | (self.pb7 as u8) << 7;
+        }
+        bus.write(base + VIA_ORB, port_b);
+        bus.write(base + VIA_ORA, self.port_output(PORT_A));
+        bus.write(base + VIA_ORA_NO_HANDSHAKE, self.port_output(PORT_A));
+        bus.write_word(base + VIA_T1CL, self.t1.counter as u16);
+        bus.write_word(base + VIA_T1LL, self.t1.latch);
+        bus.write_word(base + VIA_T2CL, self.t2.counter as u16);
+        bus.write(
+            base + VIA_IFR,
+            self.ifr | if self.irq_pending() { VIA_IRQ_ANY } else { 0 },
+        );
+        bus.write(base + VIA_IER, self.ier | 0x80);
+
+        if self.irq_pending() {
+            bus.irq();
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm6502::AssemblyOutput;
+    use crate::cpu_wrapper::CPUWrapper;
+    use crate::testing::device_cpu;
+
+    const BASE: u16 = 0x0240;
+
+    fn cpu(source: &str) -> (CPUWrapper, AssemblyOutput) {
+        device_cpu(source, Box::new(Via::new(BASE)))
+    }
+
+    #[test]
+    fn test_one_shot_timer() {
+        let (cpu, _) = cpu("
+COUNT = $10
+.org $0600
+    LDA #$64
+    STA $0244
+    LDA #$00
+    STA $0245 ; T1 = 100 cycles
+wait:
+    BIT $024D ; IFR bit 6 -> V
+    BVC wait
+    LDA $0244 ; acknowledge
+    INC COUNT
+done:
+    JMP done
+");
+        cpu.run_cycles(60);
+        assert_eq!(cpu.read_byte(0x10), 0);
+
+        cpu.run_cycles(1_000);
+        assert_eq!(cpu.read_byte(0x10), 1);
+        assert_eq!(cpu.read_byte(BASE + VIA_IFR), 0);
+    }
+
+    #[test]
+    fn test_free_running_irq() {
+        let (cpu, output) = cpu("
+COUNT = $10
+.org $0600
+    LDA #$40
+    STA $024B ; ACR: T1 free-running
+    LDA #$C0
+    STA $024E ; IER: enable T1
+    LDA #$F4
+    STA $0244
+    LDA #$01
+    STA $0245 ; T1 = 500 cycles
+    CLI
+loop:
+    JMP loop
+irq:
+    INC COUNT
+    BIT $0244 ; reading T1C-L acknowledges
+    RTI
+");
+        let irq = output.symbols["irq"];
+        cpu.write_byte(0xFFFE, irq as u8);
+        cpu.write_byte(0xFFFF, (irq >> 8) as u8);
+
+        cpu.run_cycles(5_100);
+        assert_eq!(cpu.read_byte(0x10), 10);
+    }
+
+    #[test]
+    fn test_ports() {
+        let (cpu, _) = cpu("
+.org $0600
+    LDA #$0F
+    STA $0242 ; DDRB: low nibble output
+    LDA #$A5
+    STA $0240
+    LDA $0240
+    STA $10
+");
+        cpu.with_device_at(BASE, |via: &mut Via| via.set_port_input(PORT_B, 0x30));
+        cpu.run_cycles(20);
+        assert_eq!(cpu.read_byte(0x10), 0x35);
+        let output = cpu.with_device_at(BASE, |via: &mut Via| via.port_output(PORT_B));
+        assert_eq!(output, Some(0x35));
+    }
+}
@@ -0,0 +1,185 @@
+// Watchdog timer: once enabled the program has to kick it before the timeout runs out, otherwise
+// it raises an NMI or resets the CPU. The reason for the last reset stays readable afterwards.
+
+use std::any::Any;
+
+use super::RegisterAccess::{ReadOnly, ReadWrite, WriteOnly};
+use super::{BitField, DeviceBus, Register, ShipDevice};
+
+// Registers, relative to the base address
+pub const WATCHDOG_CONTROL: u16 = 0x00; // bit 0 enable, bit 1 reset instead of NMI
+pub const WATCHDOG_TIMEOUT: u16 = 0x01; // 16-bit, in units of WATCHDOG_TICK cycles, 0 = 65536
+pub const WATCHDOG_KICK: u16 = 0x03; // strobe: restart the countdown
+pub const WATCHDOG_CAUSE: u16 = 0x04; // read: why the CPU was last reset or interrupted
+pub const WATCHDOG_SIZE: u16 = 5;
+
+pub const WATCHDOG_CONTROL_ENABLE: u8 = 0b0000_0001;
+pub const WATCHDOG_CONTROL_RESET: u8 = 0b0000_0010;
+
+pub const WATCHDOG_CAUSE_POWER_ON: u8 = 0x00;
+pub const WATCHDOG_CAUSE_RESET: u8 = 0x01;
+pub const WATCHDOG_CAUSE_NMI: u8 = 0x02;
+
+// About a millisecond at 1 MHz
+pub const WATCHDOG_TICK: u32 = 1024;
+
+pub struct Watchdog {
+    base_address: u16,
+    remaining: Option<u32>, // cycles left, None while disabled
+    cause: u8,
+}
+
+impl Watchdog {
+    pub fn new(base_address: u16) -> Self {
+        Self {
+            base_address,
+            remaining: None,
+            cause: WATCHDOG_CAUSE_POWER_ON,
+        }
+    }
+
+    pub fn cause(&self) -> u8 {
+        self.cause
+    }
+
+    fn timeout(bus: &mut DeviceBus, base: u16) -> u32 {
+        match bus.read_word(base + WATCHDOG_TIMEOUT) {
+            0 => 0x10000 * WATCHDOG_TICK,
+            timeout => timeout as u32 * WATCHDOG_TICK,
+        }
+    }
+}
+
+impl ShipDevice for Watchdog {
+    fn name(&self) -> &str {
+        "watchdog"
+    }
+
+    fn base_address(&self) -> u16 {
+        self.base_address
+    }
+
+    fn size(&self) -> u16 {
+        WATCHDOG_SIZE
+    }
+
+    fn registers(&self) -> Vec<Register> {
+        vec![
+            Register::new("CONTROL", WATCHDOG_CONTROL, 1, ReadWrite).with_fields(&[
+                BitField("ENABLE", WATCHDOG_CONTROL_ENABLE),
+                BitField("RESET", WATCHDOG_CONTROL_RESET),
+            ]),
+            Register::new("TIMEOUT", WATCHDOG_TIMEOUT, 2, ReadWrite),
+            Register::new("KICK", WATCHDOG_KICK, 1, WriteOnly),
+            Register::new("CAUSE", WATCHDOG_CAUSE, 1, ReadOnly),
+        ]
+    }
+
+    fn tick(&mut self, bus: &mut DeviceBus, cycles: u32) {
+        let base = self.base_address;
+        let control = bus.read(base + WATCHDOG_CONTROL);
+
+        if control & WATCHDOG_CONTROL_ENABLE == 0 {
+            self.remaining = None;
+        } else if bus.was_written(base + WATCHDOG_KICK) || self.remaining.is_none() {
+            self.remaining = Some(Self::timeout(bus, base));
+        } else if let Some(remaining) = self.remaining {
+            match remaining.checked_sub(cycles) {
+                Some(remaining) if remaining > 0 => self.remaining = Some(remaining),
+                _ => {
+                    if control & WATCHDOG_CONTROL_RESET != 0 {
+                        self.cause = WATCHDOG_CAUSE_RESET;
+                        bus.reset();
+                    } else {
+                        self.cause = WATCHDOG_CAUSE_NMI;
+                        bus.nmi();
+                    }
+                    self.remaining = Some(Self::timeout(bus, base));
+                }
+            }
+        }
+
+        bus.write(base + WATCHDOG_KICK, 0);
+        bus.write(base + WATCHDOG_CAUSE, self.cause);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_wrapper::CPUWrapper;
+    use crate::testing::device_cpu;
+    use std::collections::HashMap;
+
+    const BASE: u16 = 0x0220;
+
+    // INC $10; loop: JMP loop
+    fn hang() -> CPUWrapper {
+        let cpu = CPUWrapper::new(0x0600, vec![0xE6, 0x10, 0x4C, 0x02, 0x06], HashMap::new());
+        cpu.attach_device(Box::new(Watchdog::new(BASE))).unwrap();
+        cpu.write_byte(BASE + WATCHDOG_TIMEOUT, 1);
+        cpu
+    }
+
+    #[test]
+    fn test_watchdog_reset() {
+        let cpu = hang();
+        cpu.write_byte(
+            BASE + WATCHDOG_CONTROL,
+            WATCHDOG_CONTROL_ENABLE | WATCHDOG_CONTROL_RESET,
+        );
+
+        cpu.run_cycles(WATCHDOG_TICK - 16);
+        assert_eq!(
+            cpu.read_byte(BASE + WATCHDOG_CAUSE),
+            WATCHDOG_CAUSE_POWER_ON
+        );
+
+        cpu.run_cycles(32);
+        assert_eq!(cpu.read_byte(BASE + WATCHDOG_CAUSE), WATCHDOG_CAUSE_RESET);
+        assert_eq!(cpu.read_byte(0x10), 2);
+    }
+
+    // Kick with A=0 until $11 is set, then hang
+    const KICK: &str = "
+.org $0600
+    INC $10
+    LDA #$00
+kick:
+    STA $0223
+    LDX $11
+    BEQ kick
+hang:
+    JMP hang
+";
+
+    #[test]
+    fn test_watchdog_kick_and_nmi() {
+        let (cpu, _) = device_cpu(KICK, Box::new(Watchdog::new(BASE)));
+        cpu.write_byte(BASE + WATCHDOG_TIMEOUT, 1);
+        cpu.write_byte(0xFFFA, 0x00);
+        cpu.write_byte(0xFFFB, 0x07);
+        cpu.write_byte(0x0700, 0x40); // RTI
+        cpu.write_byte(BASE + WATCHDOG_CONTROL, WATCHDOG_CONTROL_ENABLE);
+
+        cpu.run_cycles(WATCHDOG_TICK * 2);
+        assert_eq!(
+            cpu.read_byte(BASE + WATCHDOG_CAUSE),
+            WATCHDOG_CAUSE_POWER_ON
+        );
+
+        cpu.write_byte(0x11, 1);
+        let mut fired = false;
+        for _ in 0..WATCHDOG_TICK {
+            cpu.run_step();
+            fired |= cpu.get_cpu().borrow().regs.pc == 0x0700;
+        }
+        assert!(fired);
+        assert_eq!(cpu.read_byte(BASE + WATCHDOG_CAUSE), WATCHDOG_CAUSE_NMI);
+        assert_eq!(cpu.read_byte(0x10), 1);
+    }
+}
@@ -0,0 +1,195 @@
+// Swapping a running program's code for a new build without resetting the CPU.
+//
+// Only the code region is rewritten; RAM, registers and devices are kept. The PC moves to the
+// same offset from the same label in the new program, the closest label at or before it in the
+// old one. That only works if the code from the label up to the PC assembled to the same bytes,
+// otherwise the PC could land in the middle of an instruction. Return addresses already on the
+// stack are not moved.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::asm6502::AssemblyOutput;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ReloadError {
+    #[error("No program was loaded from source")]
+    NothingLoaded,
+    #[error("The new program does not assemble: {0}")]
+    Assembly(String),
+    #[error("The program moved from ${old:04X} to ${new:04X}")]
+    StartMoved { old: u16, new: u16 },
+    #[error("The PC ${0:04X} is outside the program")]
+    PcOutsideProgram(u16),
+    #[error("No label before the PC ${0:04X}")]
+    NoLabel(u16),
+    #[error("The label {0} is gone from the new program")]
+    LabelRemoved(String),
+    #[error("The code from {0} up to the PC changed")]
+    CodeChanged(String),
+}
+
+// A program as assembled and loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedProgram {
+    pub start_address: u16, // where the bytes were written
+    pub origin: u16,        // where the assembler placed them, the .org
+    pub bytes: Vec<u8>,
+    pub labels: HashMap<String, u16>, // in memory, so from `start_address`
+}
+
+impl LoadedProgram {
+    // `output` written to memory at `load_address`, which may differ from its .org
+    pub fn new(output: &AssemblyOutput, load_address: u16) -> Self {
+        let labels = output
+            .labels
+            .iter()
+            .map(|(name, address)| {
+                let offset = address.wrapping_sub(output.start_address);
+                (name.clone(), load_address.wrapping_add(offset))
+            })
+            .collect();
+        Self {
+            start_address: load_address,
+            origin: output.start_address,
+            bytes: output.bytes.clone(),
+            labels,
+        }
+    }
+
+    fn contains(&self, address: u16) -> bool {
+        let offset = address.wrapping_sub(self.start_address) as usize;
+        address >= self.start_address && offset < self.bytes.len()
+    }
+
+    fn code(&self, from: u16, to: u16) -> &[u8] {
+        let start = (from - self.start_address) as usize;
+        let end = (to - self.start_address) as usize;
+        &self.bytes[start..end]
+    }
+}
+
+// Where the PC of `old` belongs in `new`
+pub fn remap_pc(pc: u16, old: &LoadedProgram, new: &LoadedProgram) -> Result<u16, ReloadError> {
+    if old.origin != new.origin || old.start_address != new.start_address {
+        return Err(ReloadError::StartMoved {
+            old: old.origin,
+            new: new.origin,
+        });
+    }
+    if !old.contains(pc) {
+        return Err(ReloadError::PcOutsideProgram(pc));
+    }
+
+    // Of several labels on one address, prefer one the new program still has
+    let (label, address) = old
+        .labels
+        .iter()
+        .filter(|(_, address)| old.contains(**address) && **address <= pc)
+        .max_by_key(|(name, address)| (**address, new.labels.contains_key(*name)))
+        .ok_or(ReloadError::NoLabel(pc))?;
+    let new_address = *new
+        .labels
+        .get(label)
+        .ok_or_else(|| ReloadError::LabelRemoved(label.clone()))?;
+
+    let new_pc = new_address.wrapping_add(pc - address);
+    let same_code = new.contains(new_pc)
+        && new_address >= new.start_address
+        && old.code(*address, pc) == new.code(new_address, new_pc);
+    match same_code {
+        true => Ok(new_pc),
+        false => Err(ReloadError::CodeChanged(label.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm6502;
+    use crate::cpu_wrapper::CPUWrapper;
+
+    const OLD: &str = "
+.org $0600
+start:
+    LDX #$00
+loop:
+    INX
+    STX $10
+    JMP loop
+";
+
+    fn remap(pc: u16, old: &AssemblyOutput, new_source: &str) -> Result<u16, ReloadError> {
+        let new = asm6502::assemble_string(new_source).unwrap();
+        let load = |output| LoadedProgram::new(output, 0x0600);
+        remap_pc(pc, &load(old), &load(&new))
+    }
+
+    #[test]
+    fn test_pc_follows_its_label() {
+        let old = asm6502::assemble_string(OLD).unwrap();
+        // STX $10 in the loop, one byte after the label
+        let new = ".org $0600\nstart:\n    LDX #$00\n    LDY #$00\nloop:\n    INX\n    STX $11\n";
+        assert_eq!(remap(0x0603, &old, new), Ok(0x0605));
+    }
+
+    #[test]
+    fn test_refusals() {
+        let old = asm6502::assemble_string(OLD).unwrap();
+        assert_eq!(
+            remap(0x0603, &old, ".org $0600\nstart:\n    LDX #$00\n"),
+            Err(ReloadError::LabelRemoved("loop".to_string()))
+        );
+        assert_eq!(
+            remap(0x0603, &old, ".org $0600\nloop:\n    DEX\n    STX $10\n"),
+            Err(ReloadError::CodeChanged("loop".to_string()))
+        );
+        assert_eq!(
+            remap(0x0603, &old, ".org $0700\nloop:\n    INX\n"),
+            Err(ReloadError::StartMoved {
+                old: 0x0600,
+                new: 0x0700
+            })
+        );
+        assert_eq!(
+            remap(0xFE00, &old, OLD),
+            Err(ReloadError::PcOutsideProgram(0xFE00))
+        );
+    }
+
+    #[test]
+    fn test_reload_keeps_ram_and_registers() {
+        let old = asm6502::assemble_string(OLD).unwrap();
+        let cpu = CPUWrapper::new(0x0600, old.bytes.clone(), old.offset_to_line.clone());
+        cpu.set_program(&old);
+        cpu.run_steps_async(100);
+        let count = cpu.read_byte(0x10);
+        assert!(count > 0);
+
+        let source = OLD.replace("STX $10", "STX $10\n    STX $11");
+        let new = asm6502::assemble_string(&source).unwrap();
+        cpu.hot_reload(&new, &source).unwrap();
+        cpu.run_steps_async(100);
+        assert!(cpu.read_byte(0x10) > count, "X kept counting");
+        assert!(cpu.read_byte(0x11) > count);
+    }
+
+    #[test]
+    fn test_reload_without_org() {
+        // Assembled from $0000 but loaded at $0600, like ships do
+        let source = "start:\n    LDX #$00\nloop:\n    INX\n    STX $10\n    BNE loop\n";
+        let old = asm6502::assemble_string(source).unwrap();
+        let cpu = CPUWrapper::new(0x0600, old.bytes.clone(), old.offset_to_line.clone());
+        cpu.set_program(&old);
+        cpu.run_steps_async(100);
+        let count = cpu.read_byte(0x10);
+
+        let source = source.replace("STX $10", "STX $10\n    STX $11");
+        let new = asm6502::assemble_string(&source).unwrap();
+        assert_eq!(cpu.hot_reload(&new, &source), Ok(()));
+        cpu.run_steps_async(100);
+        assert!(cpu.read_byte(0x10) > count);
+        assert!(cpu.read_byte(0x11) > 0);
+    }
+}
@@ -1,5 +1,9 @@
+use godot::classes::image::Format;
+use godot::classes::{
+    Image, ImageTexture, InputEvent, InputEventJoypadButton, InputEventJoypadMotion, InputEventKey,
+};
+use godot::obj::EngineEnum;
 use godot::prelude::*;
-use rv6502emu::cpu::Cpu;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -8,49 +12,38 @@ use uuid::Uuid;
 use std::collections::HashMap;
 
 pub mod asm6502;
+mod cpu_wrapper;
+mod cycles;
+pub mod dap;
+pub mod devices;
+mod hot_reload;
+mod memory_diff;
+mod memory_map;
+mod monitor;
+mod syscall;
+pub mod testing;
 
-#[derive(Clone)]
-struct CPUWrapper {
-    cpu: Rc<RefCell<Cpu>>,
-    start_address: Rc<RefCell<u16>>, // program load address
-    offset_to_line: Rc<RefCell<HashMap<u16, u32>>>, // offset in program -> line
-}
-
-impl CPUWrapper {
-    pub fn get_cpu(&self) -> Rc<RefCell<Cpu>> {
-        self.cpu.clone()
-    }
-
-    pub fn run_step(&self) {
-        // Execute a single instruction using run with a limit of 1
-        let _ = self.cpu.borrow_mut().run(None, 1);
-    }
-
-    pub fn run_steps_async(&self, steps: u32) {
-        let _ = self.cpu.borrow_mut().run(None, steps as usize);
-    }
-
-    pub fn wait_until_done(&self) {
-        // No-op (synchronous run)
-    }
-
-    pub fn set_mapping(&self, start_address: u16, mapping: HashMap<u16, u32>) {
-        *self.start_address.borrow_mut() = start_address;
-        let mut map = self.offset_to_line.borrow_mut();
-        map.clear();
-        map.extend(mapping.into_iter());
-    }
-
-    pub fn get_line_number(&self, pc: u16) -> Option<u32> {
-        let start: u16 = *self.start_address.borrow();
-        if pc < start {
-            return None;
-        }
-        let offset = pc.wrapping_sub(start);
-        let map = self.offset_to_line.borrow();
-        map.get(&offset).copied()
-    }
-}
+use asm6502::{AssemblyOutput, Diagnostic};
+use cpu_wrapper::CPUWrapper;
+use devices::acia::Acia;
+use devices::clock::Clock;
+use devices::display::{self, Display};
+use devices::dma::Dma;
+use devices::eeprom::{self, Eeprom};
+use devices::input::{self, Input, KeyEvent};
+use devices::math::MathUnit;
+use devices::region::Region;
+use devices::rng::Rng;
+use devices::sensor::{self, Encoding, Sensor};
+use devices::shared_memory::{self, Mailbox, SharedWindow};
+use devices::sound::{self, Sound};
+use devices::terminal::{self, Terminal};
+use devices::via::Via;
+use devices::watchdog::Watchdog;
+use devices::{DeviceError, DeviceInfo, ShipDevice};
+use hot_reload::ReloadError;
+use memory_diff::{ChangedRange, Snapshots};
+use syscall::SyscallRegisters;
 
 struct Orchestrator {
     cpus: HashMap<Uuid, CPUWrapper>,
@@ -81,35 +74,84 @@ impl Orchestrator {
         mapping: HashMap<u16, u32>,
     ) -> Uuid {
         let key = uuid::Uuid::new_v4();
-
-        // create a MOS6502 (default) CPU with default bus and 64k memory
-        let mut cpu_obj = Cpu::new_default(None);
-        // load program bytes into memory
-        let mem = cpu_obj.bus.get_memory();
-        for (i, b) in program.iter().enumerate() {
-            let _ = mem.write_byte(start_address as usize + i, *b);
-        }
-        // reset CPU so SP/flags/PC are correctly initialized
-        let _ = cpu_obj.reset(Some(start_address));
-
-        let cpu = Rc::new(RefCell::new(cpu_obj));
-
-        let wrapper = CPUWrapper {
-            cpu,
-            start_address: Rc::new(RefCell::new(start_address)),
-            offset_to_line: Rc::new(RefCell::new(mapping)),
-        };
+        let wrapper = CPUWrapper::new(start_address, program, mapping);
 
         self.cpus.insert(key, wrapper);
 
         return key;
     }
+
+    // Map one buffer of `size` bytes into every member at its own base address.
+    // Nothing is attached unless every member has room for the window.
+    pub fn share_memory(&self, members: &[(Uuid, u16)], size: u16) -> Result<(), DeviceError> {
+        let buffer = shared_memory::shared_buffer(size);
+        for (i, (key, base_address)) in members.iter().enumerate() {
+            let window = SharedWindow::new(*base_address, buffer.clone());
+            if let Err(error) = self.get_cpu(*key).attach_device(Box::new(window)) {
+                for (key, base_address) in &members[..i] {
+                    self.get_cpu(*key).detach_device(*base_address);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn connect_mailbox(&self, a: (Uuid, u16), b: (Uuid, u16)) -> Result<(), DeviceError> {
+        let (end_a, end_b) = Mailbox::pair(a.1, b.1);
+        self.get_cpu(a.0).attach_device(Box::new(end_a))?;
+        if let Err(error) = self.get_cpu(b.0).attach_device(Box::new(end_b)) {
+            self.get_cpu(a.0).detach_device(a.1);
+            return Err(error);
+        }
+        Ok(())
+    }
 }
 
 thread_local! {
     static ORCHESTRATOR: RefCell<Orchestrator> = RefCell::new(Orchestrator::new());
 }
 
+// Godot callers get a bool, the reason a device was refused goes to the error log
+fn report(result: Result<(), DeviceError>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(error) => {
+            godot_error!("{}", error);
+            false
+        }
+    }
+}
+
+// Changed memory ranges as Dictionaries of {start, old, new} for GDScript and the web UI
+fn changed_ranges(changes: &[ChangedRange]) -> Array<Dictionary> {
+    let mut result = Array::new();
+    for change in changes {
+        let mut range = Dictionary::new();
+        let _ = range.insert("start", change.start);
+        let _ = range.insert("old", change.old.iter().copied().collect::<Array<u8>>());
+        let _ = range.insert("new", change.new.iter().copied().collect::<Array<u8>>());
+        result.push(&range);
+    }
+    result
+}
+
+fn diagnostic_dictionaries(diagnostics: &[Diagnostic]) -> Array<Dictionary> {
+    let mut result = Array::new();
+    for diagnostic in diagnostics {
+        let mut entry = Dictionary::new();
+        let _ = entry.insert("severity", diagnostic.severity.as_str());
+        let _ = entry.insert("file", diagnostic.file.as_str());
+        let _ = entry.insert("line", diagnostic.line as i64);
+        let _ = entry.insert("column", diagnostic.column as i64);
+        let _ = entry.insert("end", diagnostic.end as i64);
+        let _ = entry.insert("message", diagnostic.message.as_str());
+        let _ = entry.insert("code", diagnostic.code);
+        result.push(&entry);
+    }
+    result
+}
+
 struct MyExtension;
 
 #[gdextension]
@@ -121,6 +163,11 @@ struct Emulator6502 {
     key: String,
     frequency: i32,
     partial_step: f32,
+    dap: Option<dap::DapListener>,
+    inject_memory_map: bool, // predefine the device constants when assembling
+    textures: HashMap<u16, Gd<ImageTexture>>, // screen devices by base address
+    snapshots: Snapshots,
+    diagnostics: Vec<Diagnostic>, // of the last assembly, errors or warnings
 }
 
 #[godot_api]
@@ -135,6 +182,11 @@ impl Emulator6502 {
             key: key.to_string(),
             frequency,
             partial_step: 0.0,
+            dap: None,
+            inject_memory_map: false,
+            textures: HashMap::new(),
+            snapshots: Snapshots::default(),
+            diagnostics: Vec::new(),
         });
     }
 
@@ -163,16 +215,21 @@ impl Emulator6502 {
     }
 
     #[func]
-    pub fn load_program_from_string(&self, assembly_code: String, start_address: u16) {
+    pub fn load_program_from_string(&mut self, assembly_code: String, start_address: u16) {
         // most likely we'll want to add a mapping between PC <-> code line number here
         // even though we only access the CPU in load_program so this might be an issue.
-        let output = match asm6502::assemble_string(&assembly_code) {
+        let output = match self.assemble(&assembly_code) {
             Ok(out) => {
                 godot_print!("Successfully compiled assembly from string");
+                self.diagnostics = out.warnings.clone();
                 out
             }
-            _ => {
-                godot_error!("Failed to compile assembly from string");
+            Err(diagnostics) => {
+                godot_error!(
+                    "Failed to compile assembly from string\n{}",
+                    asm6502::describe(&diagnostics)
+                );
+                self.diagnostics = diagnostics;
                 return;
             }
         };
@@ -188,7 +245,31 @@ impl Emulator6502 {
         // Store mapping in the CPU wrapper for later lookup
         let key = Uuid::parse_str(&self.key).unwrap();
         let cpuw = ORCHESTRATOR.with(|o| o.borrow().get_cpu(key).clone());
+        cpuw.set_program(&output);
         cpuw.set_mapping(start_address, output.offset_to_line);
+        cpuw.set_source(&assembly_code);
+    }
+
+    // Reassemble `assembly_code` and swap it in for the running program without touching RAM,
+    // registers or devices; the PC moves to the same label and offset in the new code. Returns
+    // an empty string when reloaded, otherwise why not, and nothing changes.
+    #[func]
+    pub fn hot_reload(&mut self, assembly_code: String) -> GString {
+        let result = match self.assemble(&assembly_code) {
+            Ok(output) => {
+                self.diagnostics = output.warnings.clone();
+                self.cpu().hot_reload(&output, &assembly_code)
+            }
+            Err(diagnostics) => {
+                let error = ReloadError::Assembly(asm6502::describe(&diagnostics));
+                self.diagnostics = diagnostics;
+                Err(error)
+            }
+        };
+        match result {
+            Ok(()) => GString::new(),
+            Err(error) => error.to_string().into(),
+        }
     }
 
     #[func]
@@ -198,8 +279,11 @@ impl Emulator6502 {
                 godot_print!("Successfully compiled assembly from string");
                 out
             }
-            _ => {
-                godot_error!("Failed to compile assembly from string");
+            Err(diagnostics) => {
+                godot_error!(
+                    "Failed to compile assembly from string\n{}",
+                    asm6502::describe(&diagnostics)
+                );
                 // create empty CPU if failed
                 let key = ORCHESTRATOR.with(|o| {
                     o.borrow_mut()
@@ -209,26 +293,88 @@ impl Emulator6502 {
                     key: key.to_string(),
                     frequency,
                     partial_step: 0.0,
+                    dap: None,
+                    inject_memory_map: false,
+                    textures: HashMap::new(),
+                    snapshots: Snapshots::default(),
+                    diagnostics,
                 });
             }
         };
 
         let key = ORCHESTRATOR.with(|o| {
             o.borrow_mut()
-                .create_cpu(0x0600, output.bytes, output.offset_to_line)
+                .create_cpu(0x0600, output.bytes.clone(), output.offset_to_line.clone())
+        });
+        ORCHESTRATOR.with(|o| {
+            let cpu = o.borrow().get_cpu(key).clone();
+            cpu.set_program(&output);
+            cpu.set_source(&assembly_code);
         });
         return Gd::from_object(Emulator6502 {
             key: key.to_string(),
             frequency,
             partial_step: 0.0,
+            dap: None,
+            inject_memory_map: false,
+            textures: HashMap::new(),
+            snapshots: Snapshots::default(),
+            diagnostics: output.warnings,
         });
     }
 
+    // Errors, or warnings when it built, of the last program assembled from a string, as
+    // Dictionaries of severity, file, line, column, end, message and code. Lines and columns
+    // count from 0 and end is the column after the token.
+    #[func]
+    pub fn get_diagnostics(&self) -> Array<Dictionary> {
+        diagnostic_dictionaries(&self.diagnostics)
+    }
+
+    // Assemble without loading, for editors to check the code as it is typed. Returns the
+    // diagnostics like get_diagnostics.
+    #[func]
+    pub fn check_program(&self, assembly_code: String) -> Array<Dictionary> {
+        match self.assemble(&assembly_code) {
+            Ok(output) => diagnostic_dictionaries(&output.warnings),
+            Err(diagnostics) => diagnostic_dictionaries(&diagnostics),
+        }
+    }
+
+    fn assemble(&self, assembly_code: &str) -> Result<AssemblyOutput, Vec<Diagnostic>> {
+        let predefined = match self.inject_memory_map {
+            true => memory_map::constants(&self.cpu().device_info()),
+            false => Vec::new(),
+        };
+        asm6502::assemble_string_with(assembly_code, &predefined)
+    }
+
     fn cpu(&self) -> CPUWrapper {
         let key = Uuid::parse_str(&self.key).unwrap();
         ORCHESTRATOR.with(|o| o.borrow().get_cpu(key).clone())
     }
 
+    fn attach(&self, device: Box<dyn ShipDevice>) -> bool {
+        report(self.cpu().attach_device(device))
+    }
+
+    // Upload RGBA8 pixels to the texture of the screen device at `base_address`
+    fn update_texture(&mut self, base_address: u16, width: i32, height: i32, pixels: &[u8]) {
+        let data = PackedByteArray::from(pixels);
+        let Some(image) = Image::create_from_data(width, height, false, Format::RGBA8, &data)
+        else {
+            return;
+        };
+        match self.textures.get_mut(&base_address) {
+            Some(texture) => texture.update(&image),
+            None => {
+                if let Some(texture) = ImageTexture::create_from_image(&image) {
+                    self.textures.insert(base_address, texture);
+                }
+            }
+        }
+    }
+
     #[func]
     pub fn execute_cycles_for_duration(&mut self, delta: f32) {
         // Calculate how many CPU cycles to execute based on time delta and target frequency
@@ -237,7 +383,7 @@ impl Emulator6502 {
             self.partial_step += steps;
             if (self.partial_step >= 1.0) {
                 self.partial_step -= 1.0;
-                self.cpu().run_step();
+                self.cpu().run_steps_async(1);
             }
         } else {
             self.partial_step = 0.0;
@@ -302,6 +448,39 @@ impl Emulator6502 {
         mem.read_byte(address as usize).unwrap_or(0)
     }
 
+    // Copy all of memory and return the snapshot's id for diff_memory. Only the newest 16
+    // snapshots are kept.
+    #[func]
+    pub fn snapshot_memory(&mut self) -> i64 {
+        let memory = self.cpu().read_all();
+        self.snapshots.take(memory)
+    }
+
+    // Bytes that differ between two snapshots, as ranges of {start, old, new}. Empty, with an
+    // error, if either snapshot is unknown.
+    #[func]
+    pub fn diff_memory(&self, id_a: i64, id_b: i64) -> Array<Dictionary> {
+        match self.snapshots.diff(id_a, id_b) {
+            Some(changes) => changed_ranges(&changes),
+            None => {
+                godot_error!("No memory snapshot {} or {}", id_a, id_b);
+                Array::new()
+            }
+        }
+    }
+
+    #[func]
+    pub fn release_snapshot(&mut self, id: i64) -> bool {
+        self.snapshots.release(id)
+    }
+
+    // Bytes written since the previous call, like diff_memory. The first call returns nothing.
+    #[func]
+    pub fn get_changed_memory(&mut self) -> Array<Dictionary> {
+        let memory = self.cpu().read_all();
+        changed_ranges(&self.snapshots.changed_since_last(memory))
+    }
+
     #[func]
     pub fn set_memory(&self, address: u16, value: u8) {
         let cpu = self.cpu().get_cpu();
@@ -324,7 +503,23 @@ impl Emulator6502 {
 
     #[func]
     pub fn set_frequency(&mut self, frequency: i32) {
-        self.frequency = frequency
+        self.frequency = frequency;
+        // device timings follow the CPU clock
+        let cpu = self.cpu();
+        cpu.with_device("acia", |acia: &mut Acia| acia.set_clock(frequency as u32));
+        cpu.with_device("clock", |clock: &mut Clock| {
+            clock.set_frequency(frequency as u32)
+        });
+        cpu.with_device("eeprom", |eeprom: &mut Eeprom| {
+            eeprom.set_frequency(frequency as u32)
+        });
+        cpu.with_device("display", |display: &mut Display| {
+            display.set_frequency(frequency as u32)
+        });
+        cpu.with_device("terminal", |terminal: &mut Terminal| {
+            terminal.set_frequency(frequency as u32)
+        });
+        cpu.with_device("sound", |sound: &mut Sound| sound.set_frequency(frequency as u32));
     }
 
     #[func]
@@ -340,4 +535,693 @@ impl Emulator6502 {
             None => -1,
         }
     }
+
+    // Start a Debug Adapter Protocol server for this CPU on localhost:port.
+    // Requests are only processed from dap_poll, which should be called every frame.
+    #[func]
+    pub fn dap_listen(&mut self, port: u16) -> bool {
+        match dap::DapListener::bind(port, self.cpu()) {
+            Ok(listener) => {
+                godot_print!("Debug adapter listening on port {}", port);
+                self.dap = Some(listener);
+                true
+            }
+            Err(error) => {
+                godot_warn!("Could not start debug adapter on port {}: {}", port, error);
+                false
+            }
+        }
+    }
+
+    // Stop the debug adapter, dropping its client, so another CPU can listen on the port
+    #[func]
+    pub fn dap_stop(&mut self) {
+        self.dap = None;
+    }
+
+    #[func]
+    pub fn dap_poll(&mut self) {
+        if let Some(listener) = &mut self.dap {
+            listener.poll();
+        }
+    }
+
+    #[func]
+    pub fn is_halted(&self) -> bool {
+        self.cpu().is_halted()
+    }
+
+    // Dual-port RAM: `size` bytes at `base_address` here and at `other_base_address` on `other`
+    // hold the same contents
+    #[func]
+    pub fn share_memory_with(
+        &self,
+        other: Gd<Emulator6502>,
+        base_address: u16,
+        other_base_address: u16,
+        size: u16,
+    ) -> bool {
+        let members = [
+            (Uuid::parse_str(&self.key).unwrap(), base_address),
+            (Uuid::parse_str(&other.bind().key).unwrap(), other_base_address),
+        ];
+        report(ORCHESTRATOR.with(|o| o.borrow().share_memory(&members, size)))
+    }
+
+    // Mailbox between this CPU and `other`, see devices/shared_memory.rs for the registers
+    #[func]
+    pub fn connect_mailbox(
+        &self,
+        other: Gd<Emulator6502>,
+        base_address: u16,
+        other_base_address: u16,
+    ) -> bool {
+        let a = (Uuid::parse_str(&self.key).unwrap(), base_address);
+        let b = (Uuid::parse_str(&other.bind().key).unwrap(), other_base_address);
+        report(ORCHESTRATOR.with(|o| o.borrow().connect_mailbox(a, b)))
+    }
+
+    // Watchdog timer, see devices/watchdog.rs for the registers
+    #[func]
+    pub fn attach_watchdog(&self, base_address: u16) -> bool {
+        self.attach(Box::new(Watchdog::new(base_address)))
+    }
+
+    // Why the watchdog last fired, -1 without a watchdog
+    #[func]
+    pub fn get_watchdog_cause(&self) -> i32 {
+        self.cpu()
+            .with_device("watchdog", |watchdog: &mut Watchdog| watchdog.cause() as i32)
+            .unwrap_or(-1)
+    }
+
+    // 6522 VIA, see devices/via.rs for the registers
+    #[func]
+    pub fn attach_via(&self, base_address: u16) -> bool {
+        self.attach(Box::new(Via::new(base_address)))
+    }
+
+    // Drive the input pins of port 0 (A) or 1 (B) of the VIA at `base_address`
+    #[func]
+    pub fn via_set_port_input(&self, base_address: u16, port: u8, value: u8) {
+        self.cpu()
+            .with_device_at(base_address, |via: &mut Via| via.set_port_input(port, value));
+    }
+
+    // Pin levels of port 0 (A) or 1 (B), -1 without a VIA at `base_address`
+    #[func]
+    pub fn via_get_port_output(&self, base_address: u16, port: u8) -> i32 {
+        self.cpu()
+            .with_device_at(base_address, |via: &mut Via| via.port_output(port) as i32)
+            .unwrap_or(-1)
+    }
+
+    // 6551 ACIA clocked by this CPU's frequency, see devices/acia.rs for the registers
+    #[func]
+    pub fn attach_acia(&self, base_address: u16) -> bool {
+        let acia = Acia::new(base_address, self.frequency as u32);
+        self.attach(Box::new(acia))
+    }
+
+    // Queue bytes for the ship to receive
+    #[func]
+    pub fn acia_push_rx(&self, base_address: u16, bytes: Array<u8>) {
+        let bytes: Vec<u8> = bytes.iter_shared().collect();
+        self.cpu()
+            .with_device_at(base_address, |acia: &mut Acia| acia.push_received(&bytes));
+    }
+
+    // Bytes the ship transmitted since the last call
+    #[func]
+    pub fn acia_pop_tx(&self, base_address: u16) -> Array<u8> {
+        let bytes = self
+            .cpu()
+            .with_device_at(base_address, |acia: &mut Acia| acia.pop_transmitted())
+            .unwrap_or_default();
+        let mut result = Array::new();
+        for byte in bytes {
+            result.push(byte);
+        }
+        result
+    }
+
+    // Math coprocessor, see devices/math.rs for the operations and their latency
+    #[func]
+    pub fn attach_math_unit(&self, base_address: u16) -> bool {
+        self.attach(Box::new(MathUnit::new(base_address)))
+    }
+
+    // DMA controller, see devices/dma.rs for the registers
+    #[func]
+    pub fn attach_dma(&self, base_address: u16) -> bool {
+        self.attach(Box::new(Dma::new(base_address)))
+    }
+
+    // Queue sensor data for device-to-memory transfers of the DMA at `base_address`
+    #[func]
+    pub fn dma_feed(&self, base_address: u16, bytes: Array<u8>) {
+        let bytes: Vec<u8> = bytes.iter_shared().collect();
+        self.cpu()
+            .with_device_at(base_address, |dma: &mut Dma| dma.feed(&bytes));
+    }
+
+    // Random number generator seeded from this CPU's seed, see devices/rng.rs for the registers
+    #[func]
+    pub fn attach_rng(&self, base_address: u16) -> bool {
+        let cpu = self.cpu();
+        self.attach(Box::new(Rng::new(base_address, cpu.get_seed())))
+    }
+
+    // Seed for everything random in this CPU: the RNG device and sensor noise.
+    // The same seed replays a flight exactly.
+    #[func]
+    pub fn set_seed(&self, seed: i64) {
+        self.cpu().set_seed(seed as u64);
+    }
+
+    #[func]
+    pub fn get_seed(&self) -> i64 {
+        self.cpu().get_seed() as i64
+    }
+
+    // Uniform in [0, 1), from the sensor noise stream
+    #[func]
+    pub fn rand_float(&self) -> f64 {
+        self.cpu().random_f64()
+    }
+
+    // Normally distributed, from the sensor noise stream
+    #[func]
+    pub fn rand_normal(&self, mean: f64, deviation: f64) -> f64 {
+        self.cpu().random_normal(mean, deviation)
+    }
+
+    // Mission clock counting this CPU's emulated time, see devices/clock.rs for the registers
+    #[func]
+    pub fn attach_clock(&self, base_address: u16) -> bool {
+        let clock = Clock::new(base_address, self.frequency as u32);
+        self.attach(Box::new(clock))
+    }
+
+    // Mission elapsed time in milliseconds, -1 without a clock
+    #[func]
+    pub fn get_mission_time(&self) -> i64 {
+        self.cpu()
+            .with_device("clock", |clock: &mut Clock| clock.milliseconds() as i64)
+            .unwrap_or(-1)
+    }
+
+    // EEPROM with `pages` pages of 32 bytes (0 for the default 8 KB), see devices/eeprom.rs
+    #[func]
+    pub fn attach_eeprom(&self, base_address: u16, pages: u16) -> bool {
+        let pages = match pages {
+            0 => eeprom::EEPROM_DEFAULT_PAGES,
+            pages => pages,
+        };
+        let eeprom = Eeprom::new(base_address, pages, self.frequency as u32);
+        self.attach(Box::new(eeprom))
+    }
+
+    // Contents and wear counters of the EEPROM at `base_address`, to persist between sessions
+    #[func]
+    pub fn eeprom_get_image(&self, base_address: u16) -> Array<u8> {
+        let image = self
+            .cpu()
+            .with_device_at(base_address, |eeprom: &mut Eeprom| eeprom.image())
+            .unwrap_or_default();
+        let mut result = Array::new();
+        for byte in image {
+            result.push(byte);
+        }
+        result
+    }
+
+    // Restore an image from eeprom_get_image, false if it does not fit the EEPROM
+    #[func]
+    pub fn eeprom_set_image(&self, base_address: u16, image: Array<u8>) -> bool {
+        let image: Vec<u8> = image.iter_shared().collect();
+        self.cpu()
+            .with_device_at(base_address, |eeprom: &mut Eeprom| eeprom.load_image(&image))
+            .unwrap_or(false)
+    }
+
+    // Bitmap display of `width` x `height` pixels with 1, 2 or 4 bits per pixel, see
+    // devices/display.rs for the registers and the framebuffer layout
+    #[func]
+    pub fn attach_display(&self, base_address: u16, width: u8, height: u8, depth: u8) -> bool {
+        if !display::valid_depth(depth) {
+            godot_error!("A display has 1, 2 or 4 bits per pixel, not {}", depth);
+            return false;
+        }
+        let display = Display::new(base_address, width, height, depth, self.frequency as u32);
+        self.attach(Box::new(display))
+    }
+
+    // Texture showing the display at `base_address`. It is the same texture on every call and
+    // only gets new pixels when the program changed the picture.
+    #[func]
+    pub fn display_get_texture(&mut self, base_address: u16) -> Option<Gd<ImageTexture>> {
+        let (width, height, frame) = self
+            .cpu()
+            .with_device_at(base_address, |display: &mut Display| {
+                (display.width(), display.height(), display.take_frame())
+            })?;
+        if let Some(pixels) = frame {
+            self.update_texture(base_address, width as i32, height as i32, &pixels);
+        }
+        self.textures.get(&base_address).cloned()
+    }
+
+    // 40x25 text terminal, see devices/terminal.rs for the registers
+    #[func]
+    pub fn attach_terminal(&self, base_address: u16) -> bool {
+        let terminal = Terminal::new(base_address, self.frequency as u32);
+        self.attach(Box::new(terminal))
+    }
+
+    // Texture showing the terminal at `base_address`, updated like display_get_texture
+    #[func]
+    pub fn terminal_get_texture(&mut self, base_address: u16) -> Option<Gd<ImageTexture>> {
+        let frame = self
+            .cpu()
+            .with_device_at(base_address, |terminal: &mut Terminal| terminal.take_frame())?;
+        if let Some(pixels) = frame {
+            let (width, height) = (terminal::TERMINAL_WIDTH, terminal::TERMINAL_HEIGHT);
+            self.update_texture(base_address, width as i32, height as i32, &pixels);
+        }
+        self.textures.get(&base_address).cloned()
+    }
+
+    // What the terminal at `base_address` shows, as lines of text
+    #[func]
+    pub fn terminal_get_text(&self, base_address: u16) -> GString {
+        let cpu = self.cpu();
+        if cpu
+            .with_device_at(base_address, |_: &mut Terminal| ())
+            .is_none()
+        {
+            return GString::new();
+        }
+        let chars: Vec<u8> = (0..terminal::TERMINAL_CELLS)
+            .map(|cell| cpu.read_byte(base_address + terminal::TERMINAL_CHARS + cell))
+            .collect();
+        terminal::screen_text(&chars).into()
+    }
+
+    // Keyboard FIFO and gamepad, see devices/input.rs for the registers
+    #[func]
+    pub fn attach_input(&self, base_address: u16) -> bool {
+        self.attach(Box::new(Input::new(base_address)))
+    }
+
+    // Pass a key, joypad button or joypad motion event on to the input device at `base_address`.
+    // False for any other event, or without an input device there.
+    #[func]
+    pub fn input_event(&self, base_address: u16, event: Gd<InputEvent>) -> bool {
+        let cpu = self.cpu();
+        let event = match event.try_cast::<InputEventKey>() {
+            Ok(key) => {
+                let mut flags = 0;
+                if key.is_shift_pressed() {
+                    flags |= input::INPUT_KEY_FLAGS_SHIFT;
+                }
+                if key.is_ctrl_pressed() {
+                    flags |= input::INPUT_KEY_FLAGS_CTRL;
+                }
+                if key.is_alt_pressed() {
+                    flags |= input::INPUT_KEY_FLAGS_ALT;
+                }
+                if !key.is_pressed() {
+                    flags |= input::INPUT_KEY_FLAGS_RELEASED;
+                }
+                let keycode = key.get_keycode().ord() as i64;
+                let key = KeyEvent::from_godot(keycode, key.get_unicode() as u32, flags);
+                return cpu
+                    .with_device_at(base_address, |input: &mut Input| input.push_key(key))
+                    .is_some();
+            }
+            Err(event) => event,
+        };
+        let event = match event.try_cast::<InputEventJoypadButton>() {
+            Ok(button) => {
+                let index = button.get_button_index().ord() as usize;
+                let pressed = button.is_pressed();
+                return cpu
+                    .with_device_at(base_address, |input: &mut Input| {
+                        input.set_button(index, pressed)
+                    })
+                    .is_some();
+            }
+            Err(event) => event,
+        };
+        match event.try_cast::<InputEventJoypadMotion>() {
+            Ok(motion) => {
+                let axis = motion.get_axis().ord() as usize;
+                let value = motion.get_axis_value() as f32;
+                cpu.with_device_at(base_address, |input: &mut Input| input.set_axis(axis, value))
+                    .is_some()
+            }
+            Err(_) => false,
+        }
+    }
+
+    // Sound generator rendering `sample_rate` samples per emulated second (0 for 44.1 kHz), see
+    // devices/sound.rs for the registers
+    #[func]
+    pub fn attach_sound(&self, base_address: u16, sample_rate: u32) -> bool {
+        let sample_rate = match sample_rate {
+            0 => sound::SOUND_DEFAULT_SAMPLE_RATE,
+            sample_rate => sample_rate,
+        };
+        let sound = Sound::new(base_address, sample_rate, self.frequency as u32);
+        self.attach(Box::new(sound))
+    }
+
+    // Up to `max_frames` samples rendered since the last call, as stereo frames for
+    // AudioStreamGeneratorPlayback.push_buffer
+    #[func]
+    pub fn sound_pop_frames(&self, base_address: u16, max_frames: u32) -> PackedVector2Array {
+        let samples = self
+            .cpu()
+            .with_device_at(base_address, |sound: &mut Sound| {
+                sound.pop_samples(max_frames as usize)
+            })
+            .unwrap_or_default();
+        let mut frames = PackedVector2Array::new();
+        for sample in samples {
+            frames.push(Vector2::splat(sample as real));
+        }
+        frames
+    }
+
+    // Sensor with `channels` readings from -range to range in the given encoding: 0 unsigned
+    // 8 bit, 1 signed 16 bit, 2 8.8 or 3 16.16 fixed point. See devices/sensor.rs for the registers.
+    #[func]
+    pub fn attach_sensor(
+        &self,
+        name: GString,
+        base_address: u16,
+        channels: u8,
+        encoding: u8,
+        range: f64,
+    ) -> bool {
+        let Some(encoding) = Encoding::from_u8(encoding) else {
+            godot_error!("Unknown sensor encoding {}", encoding);
+            return false;
+        };
+        if channels == 0 || channels > sensor::SENSOR_MAX_CHANNELS {
+            godot_error!(
+                "A sensor has 1 to {} channels, not {}",
+                sensor::SENSOR_MAX_CHANNELS,
+                channels
+            );
+            return false;
+        }
+        if range.is_nan() || range <= 0.0 {
+            godot_error!("A sensor's range has to be above 0, not {}", range);
+            return false;
+        }
+        let sensor = Sensor::new(&name.to_string(), base_address, channels, encoding, range);
+        self.attach(Box::new(sensor))
+    }
+
+    // Reading of a sensor channel, the program sees it once it latches
+    #[func]
+    pub fn sensor_set(&self, base_address: u16, channel: u8, value: f64) -> bool {
+        self.cpu()
+            .with_device_at(base_address, |sensor: &mut Sensor| {
+                sensor.set_reading(channel as usize, value)
+            })
+            .unwrap_or(false)
+    }
+
+    // Call `handler` when the program makes syscall `number`, with `BRK #number` or with a JSR to
+    // the trap address followed by the byte `number`. The handler gets a SyscallContext to read
+    // and change A, X, Y, the flags and memory; the program carries on after the syscall once it
+    // returns. See syscall.rs.
+    #[func]
+    pub fn register_syscall(&self, number: u8, handler: Callable) {
+        let key = self.key.clone();
+        let handler = Rc::new(move |number: u8, registers: &mut SyscallRegisters| {
+            let context = SyscallContext::new(&key, number, *registers);
+            handler.call(&[context.to_variant()]);
+            *registers = context.bind().registers;
+        });
+        self.cpu().register_syscall(number, handler);
+    }
+
+    #[func]
+    pub fn unregister_syscall(&self, number: u8) -> bool {
+        self.cpu().unregister_syscall(number)
+    }
+
+    // JSR target that makes a syscall, -1 to only make them with BRK. Defaults to $FDF0.
+    #[func]
+    pub fn set_syscall_trap(&self, address: i32) {
+        let address = (0..=0xFFFF).contains(&address).then_some(address as u16);
+        self.cpu().set_syscall_trap(address);
+    }
+
+    // Copy the monitor ROM to the top of memory, see rom/monitor.asm for the commands. With
+    // `take_reset` the reset vector points at it too, otherwise a reset still restarts the
+    // program. With `enter` the CPU jumps to the monitor right away.
+    #[func]
+    pub fn install_monitor(&self, enter: bool, take_reset: bool) {
+        let cpu = self.cpu();
+        monitor::install(&cpu, take_reset);
+        if enter {
+            cpu.get_cpu().borrow_mut().regs.pc = monitor::MONITOR_ENTRY;
+        }
+    }
+
+    // Claim `size` bytes at `base_address` for a GDScript component. False, with the conflict in
+    // the error log, if another device already uses any of them.
+    #[func]
+    pub fn register_region(&self, name: GString, base_address: u16, size: u16) -> bool {
+        self.attach(Box::new(Region::new(&name.to_string(), base_address, size)))
+    }
+
+    // Every attached device, by base address
+    #[func]
+    pub fn get_devices(&self) -> Array<Gd<EmulatorDevice>> {
+        let mut result = Array::new();
+        for info in self.cpu().device_info() {
+            result.push(&EmulatorDevice::new(&self.key, info));
+        }
+        result
+    }
+
+    // When enabled, programs assembled by load_program_from_string can use the memory map
+    // constants of the devices attached at that time without defining them
+    #[func]
+    pub fn set_memory_map_injection(&mut self, enabled: bool) {
+        self.inject_memory_map = enabled;
+    }
+
+    #[func]
+    pub fn get_memory_map_json(&self) -> GString {
+        memory_map::to_json(&self.cpu().device_info()).into()
+    }
+
+    // The memory map as NAME = $addr lines, to paste into a program
+    #[func]
+    pub fn get_memory_map_asm(&self) -> GString {
+        memory_map::to_asm(&self.cpu().device_info()).into()
+    }
+
+    #[func]
+    pub fn get_device(&self, base_address: u16) -> Option<Gd<EmulatorDevice>> {
+        self.cpu()
+            .device_info()
+            .into_iter()
+            .find(|info| info.base_address == base_address)
+            .map(|info| EmulatorDevice::new(&self.key, info))
+    }
+
+    #[func]
+    pub fn detach_device(&self, base_address: u16) -> bool {
+        self.cpu().detach_device(base_address)
+    }
+}
+
+// A device attached to an Emulator6502: its registers and their access modes, read and written
+// by name. Built-in sensors are ShipDevices in Rust and show up here like any other device.
+#[derive(GodotClass)]
+#[class(no_init, base=RefCounted)]
+struct EmulatorDevice {
+    key: String,
+    info: DeviceInfo,
+}
+
+impl EmulatorDevice {
+    fn new(key: &str, info: DeviceInfo) -> Gd<Self> {
+        Gd::from_object(Self {
+            key: key.to_string(),
+            info,
+        })
+    }
+
+    fn cpu(&self) -> CPUWrapper {
+        let key = Uuid::parse_str(&self.key).unwrap();
+        ORCHESTRATOR.with(|o| o.borrow().get_cpu(key).clone())
+    }
+
+    fn register(&self, name: &GString) -> Option<devices::Register> {
+        let name = name.to_string();
+        self.info
+            .registers
+            .iter()
+            .find(|register| register.name == name)
+            .copied()
+    }
+}
+
+#[godot_api]
+impl EmulatorDevice {
+    #[func]
+    pub fn get_name(&self) -> GString {
+        self.info.name.as_str().into()
+    }
+
+    #[func]
+    pub fn get_base_address(&self) -> u16 {
+        self.info.base_address
+    }
+
+    #[func]
+    pub fn get_size(&self) -> u16 {
+        self.info.size
+    }
+
+    // One Dictionary per register: name, address, offset, size and access
+    // ("read", "write" or "read_write")
+    #[func]
+    pub fn get_registers(&self) -> Array<Dictionary> {
+        let mut result = Array::new();
+        for register in &self.info.registers {
+            let mut entry = Dictionary::new();
+            let _ = entry.insert("name", register.name);
+            let _ = entry.insert("address", self.info.base_address + register.offset);
+            let _ = entry.insert("offset", register.offset);
+            let _ = entry.insert("size", register.size);
+            let _ = entry.insert("access", register.access.as_str());
+            result.push(&entry);
+        }
+        result
+    }
+
+    // Little endian value of a register up to 8 bytes, -1 for an unknown register
+    #[func]
+    pub fn read_register(&self, name: GString) -> i64 {
+        let Some(register) = self.register(&name) else {
+            return -1;
+        };
+        let cpu = self.cpu();
+        let address = self.info.base_address + register.offset;
+        (0..register.size.min(8))
+            .map(|i| (cpu.read_byte(address + i) as i64) << (8 * i))
+            .sum()
+    }
+
+    // False for an unknown or read-only register
+    #[func]
+    pub fn write_register(&self, name: GString, value: i64) -> bool {
+        let Some(register) = self.register(&name) else {
+            return false;
+        };
+        if register.access == devices::RegisterAccess::ReadOnly {
+            return false;
+        }
+        let cpu = self.cpu();
+        let address = self.info.base_address + register.offset;
+        for i in 0..register.size.min(8) {
+            cpu.write_byte(address + i, (value >> (8 * i)) as u8);
+        }
+        true
+    }
+}
+
+// What a syscall handler gets: the syscall's number, the registers it may change and the CPU's
+// memory. Changes to the registers take effect when the handler returns. Use the context rather
+// than the Emulator6502 while handling a syscall, the emulator is busy running the program.
+#[derive(GodotClass)]
+#[class(no_init, base=RefCounted)]
+struct SyscallContext {
+    key: String,
+    number: u8,
+    registers: SyscallRegisters,
+}
+
+impl SyscallContext {
+    fn new(key: &str, number: u8, registers: SyscallRegisters) -> Gd<Self> {
+        Gd::from_object(Self {
+            key: key.to_string(),
+            number,
+            registers,
+        })
+    }
+
+    fn cpu(&self) -> CPUWrapper {
+        let key = Uuid::parse_str(&self.key).unwrap();
+        ORCHESTRATOR.with(|o| o.borrow().get_cpu(key).clone())
+    }
+}
+
+#[godot_api]
+impl SyscallContext {
+    #[func]
+    pub fn get_number(&self) -> u8 {
+        self.number
+    }
+
+    #[func]
+    pub fn get_a(&self) -> u8 {
+        self.registers.a
+    }
+
+    #[func]
+    pub fn set_a(&mut self, value: u8) {
+        self.registers.a = value;
+    }
+
+    #[func]
+    pub fn get_x(&self) -> u8 {
+        self.registers.x
+    }
+
+    #[func]
+    pub fn set_x(&mut self, value: u8) {
+        self.registers.x = value;
+    }
+
+    #[func]
+    pub fn get_y(&self) -> u8 {
+        self.registers.y
+    }
+
+    #[func]
+    pub fn set_y(&mut self, value: u8) {
+        self.registers.y = value;
+    }
+
+    // The status register, carry in bit 0 up to negative in bit 7
+    #[func]
+    pub fn get_flags(&self) -> u8 {
+        self.registers.p
+    }
+
+    #[func]
+    pub fn set_flags(&mut self, value: u8) {
+        self.registers.p = value;
+    }
+
+    #[func]
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.cpu().read_byte(address)
+    }
+
+    #[func]
+    pub fn write_memory(&self, address: u16, value: u8) {
+        self.cpu().write_byte(address, value);
+    }
 }
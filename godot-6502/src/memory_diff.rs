@@ -0,0 +1,155 @@
+// Memory snapshots and the bytes that changed between them, for the memory viewer.
+//
+// A snapshot is a copy of all 64 KB. Only the newest MAX_SNAPSHOTS are kept, older ids stop
+// resolving. Besides comparing two snapshots by id, `changed_since_last` compares memory with
+// what the previous call saw, so a viewer polling it sees every write exactly once.
+
+use std::collections::BTreeMap;
+
+pub const MAX_SNAPSHOTS: usize = 16;
+
+// A run of consecutive changed bytes starting at `start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedRange {
+    pub start: u16,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+pub fn diff(old: &[u8], new: &[u8]) -> Vec<ChangedRange> {
+    let mut ranges: Vec<ChangedRange> = Vec::new();
+    for (address, (old_byte, new_byte)) in old.iter().zip(new).enumerate() {
+        if old_byte == new_byte {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.start as usize + range.new.len() == address => {
+                range.old.push(*old_byte);
+                range.new.push(*new_byte);
+            }
+            _ => ranges.push(ChangedRange {
+                start: address as u16,
+                old: vec![*old_byte],
+                new: vec![*new_byte],
+            }),
+        }
+    }
+    ranges
+}
+
+#[derive(Default)]
+pub struct Snapshots {
+    snapshots: BTreeMap<i64, Vec<u8>>,
+    next_id: i64,
+    last_seen: Option<Vec<u8>>,
+}
+
+impl Snapshots {
+    pub fn take(&mut self, memory: Vec<u8>) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.snapshots.insert(id, memory);
+        while self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_first();
+        }
+        id
+    }
+
+    // None if either snapshot is unknown or was dropped
+    pub fn diff(&self, id_a: i64, id_b: i64) -> Option<Vec<ChangedRange>> {
+        let a = self.snapshots.get(&id_a)?;
+        let b = self.snapshots.get(&id_b)?;
+        Some(diff(a, b))
+    }
+
+    pub fn release(&mut self, id: i64) -> bool {
+        self.snapshots.remove(&id).is_some()
+    }
+
+    // Changes since the previous call, nothing on the first one
+    pub fn changed_since_last(&mut self, memory: Vec<u8>) -> Vec<ChangedRange> {
+        let changes = match &self.last_seen {
+            Some(last_seen) => diff(last_seen, &memory),
+            None => Vec::new(),
+        };
+        self.last_seen = Some(memory);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RoutineTest;
+
+    const SOURCE: &str = "
+.org $0600
+store:
+    LDA #$AA
+    STA $10
+    STA $11
+    STA $20
+    RTS
+";
+
+    #[test]
+    fn test_diff_ranges() {
+        let old = [0, 1, 2, 3, 4, 5];
+        let new = [0, 9, 9, 3, 4, 8];
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                ChangedRange {
+                    start: 1,
+                    old: vec![1, 2],
+                    new: vec![9, 9],
+                },
+                ChangedRange {
+                    start: 5,
+                    old: vec![5],
+                    new: vec![8],
+                },
+            ]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_snapshots_of_a_routine() {
+        let mut test = RoutineTest::new(SOURCE).unwrap();
+        let mut snapshots = Snapshots::default();
+        let before = snapshots.take(test.cpu().read_all());
+        assert!(snapshots
+            .changed_since_last(test.cpu().read_all())
+            .is_empty());
+
+        test.call("store").unwrap();
+        let after = snapshots.take(test.cpu().read_all());
+        let changes = snapshots.diff(before, after).unwrap();
+        let written: Vec<_> = changes.iter().filter(|range| range.start < 0x100).collect();
+        assert_eq!(written.len(), 2);
+        assert_eq!(
+            (written[0].start, &written[0].new),
+            (0x10, &vec![0xAA, 0xAA])
+        );
+        assert_eq!((written[1].start, &written[1].old), (0x20, &vec![0x00]));
+
+        let since_last = snapshots.changed_since_last(test.cpu().read_all());
+        assert!(since_last.iter().any(|range| range.start == 0x10));
+        assert!(snapshots
+            .changed_since_last(test.cpu().read_all())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_old_snapshots_are_dropped() {
+        let mut snapshots = Snapshots::default();
+        let first = snapshots.take(vec![0; 4]);
+        for _ in 0..MAX_SNAPSHOTS {
+            snapshots.take(vec![1; 4]);
+        }
+        assert_eq!(snapshots.diff(first, first + 1), None);
+        assert!(snapshots.release(first + 1));
+        assert!(!snapshots.release(first + 1));
+    }
+}
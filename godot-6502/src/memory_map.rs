@@ -0,0 +1,203 @@
+// Memory map of the attached devices, for programs and for the web UI.
+//
+// Every device, register and bit field gets a constant named after it: the device's base address
+// as PREFIX, each register's address as PREFIX_REGISTER and each bit's mask as
+// PREFIX_REGISTER_FIELD. The prefix is the device name in upper snake case, "StarTracker" becomes
+// STAR_TRACKER, and devices sharing a prefix are numbered from the second one on.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::asm6502::DirectiveValue;
+use crate::devices::DeviceInfo;
+
+fn constant_name(name: &str) -> String {
+    let mut constant = String::new();
+    let mut previous = '_';
+    for c in name.chars() {
+        let c = if c.is_ascii_alphanumeric() { c } else { '_' };
+        let word_start =
+            c.is_ascii_uppercase() && (previous.is_ascii_lowercase() || previous.is_ascii_digit());
+        if (word_start || c == '_') && !constant.ends_with('_') && !constant.is_empty() {
+            constant.push('_');
+        }
+        if c != '_' {
+            constant.push(c.to_ascii_uppercase());
+        }
+        previous = c;
+    }
+    let constant = constant.trim_end_matches('_').to_string();
+
+    match constant.chars().next() {
+        Some(first) if !first.is_ascii_digit() => constant,
+        _ => format!("DEVICE_{constant}"),
+    }
+}
+
+// Constant prefix of each device, in the order given
+fn prefixes(devices: &[DeviceInfo]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    devices
+        .iter()
+        .map(|device| {
+            let prefix = constant_name(&device.name);
+            let count = seen.entry(prefix.clone()).or_default();
+            *count += 1;
+            match *count {
+                1 => prefix,
+                n => format!("{prefix}_{n}"),
+            }
+        })
+        .collect()
+}
+
+fn device_constants(device: &DeviceInfo, prefix: &str) -> Vec<(String, DirectiveValue)> {
+    let mut constants = vec![(
+        prefix.to_string(),
+        DirectiveValue::Word(device.base_address),
+    )];
+    for register in &device.registers {
+        let name = format!("{prefix}_{}", constant_name(register.name));
+        let address = device.base_address + register.offset;
+        constants.push((name.clone(), DirectiveValue::Word(address)));
+        for field in register.fields {
+            let field_name = format!("{name}_{}", constant_name(field.0));
+            constants.push((field_name, DirectiveValue::Byte(field.1)));
+        }
+    }
+    constants
+}
+
+pub fn constants(devices: &[DeviceInfo]) -> Vec<(String, DirectiveValue)> {
+    devices
+        .iter()
+        .zip(prefixes(devices))
+        .flat_map(|(device, prefix)| device_constants(device, &prefix))
+        .collect()
+}
+
+// The constants as assembler source, one device per paragraph
+pub fn to_asm(devices: &[DeviceInfo]) -> String {
+    let mut source = String::from("; Memory map of the attached devices\n");
+    for (device, prefix) in devices.iter().zip(prefixes(devices)) {
+        source.push_str(&format!(
+            "\n; {} at ${:04X}, {} bytes\n",
+            device.name, device.base_address, device.size
+        ));
+        for (name, value) in device_constants(device, &prefix) {
+            match value {
+                DirectiveValue::Byte(byte) => source.push_str(&format!("{name} = ${byte:02X}\n")),
+                DirectiveValue::Word(word) => source.push_str(&format!("{name} = ${word:04X}\n")),
+                _ => {}
+            }
+        }
+    }
+    source
+}
+
+pub fn to_json(devices: &[DeviceInfo]) -> String {
+    let devices: Vec<Value> = devices
+        .iter()
+        .zip(prefixes(devices))
+        .map(|(device, prefix)| {
+            let registers: Vec<Value> = device
+                .registers
+                .iter()
+                .map(|register| {
+                    let constant = format!("{prefix}_{}", constant_name(register.name));
+                    let fields: Vec<Value> = register
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            json!({
+                                "name": field.0,
+                                "constant": format!("{constant}_{}", constant_name(field.0)),
+                                "mask": field.1,
+                            })
+                        })
+                        .collect();
+                    json!({
+                        "name": register.name,
+                        "constant": constant,
+                        "address": device.base_address + register.offset,
+                        "offset": register.offset,
+                        "size": register.size,
+                        "access": register.access.as_str(),
+                        "fields": fields,
+                    })
+                })
+                .collect();
+            json!({
+                "name": device.name,
+                "constant": prefix,
+                "base_address": device.base_address,
+                "size": device.size,
+                "registers": registers,
+            })
+        })
+        .collect();
+    json!({ "devices": devices }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm6502;
+    use crate::cpu_wrapper::CPUWrapper;
+    use crate::devices::region::Region;
+    use crate::devices::via::Via;
+
+    fn devices() -> Vec<DeviceInfo> {
+        let cpu = CPUWrapper::new(0x0600, vec![], Default::default());
+        cpu.attach_device(Box::new(Via::new(0x0200))).unwrap();
+        cpu.attach_device(Box::new(Region::new("StarTracker", 0x0220, 4)))
+            .unwrap();
+        cpu.attach_device(Box::new(Region::new("StarTracker", 0x0230, 4)))
+            .unwrap();
+        cpu.device_info()
+    }
+
+    #[test]
+    fn test_constant_names() {
+        assert_eq!(constant_name("StarTracker"), "STAR_TRACKER");
+        assert_eq!(constant_name("Main engine 2"), "MAIN_ENGINE_2");
+        assert_eq!(constant_name("T1_PB7"), "T1_PB7");
+        assert_eq!(constant_name("3D printer"), "DEVICE_3_D_PRINTER");
+    }
+
+    #[test]
+    fn test_asm_include() {
+        let source = to_asm(&devices());
+        assert!(source.contains("\n; via at $0200, 16 bytes\nVIA = $0200\n"));
+        assert!(source.contains("\nVIA_IFR = $020D\nVIA_IFR_ANY = $80\nVIA_IFR_T1 = $40\n"));
+        assert!(source.contains("\nSTAR_TRACKER_DATA = $0220\n"));
+        assert!(source.contains("\nSTAR_TRACKER_2_DATA = $0230\n"));
+
+        let output = asm6502::assemble_string(&format!("{source}\nLDA STAR_TRACKER_2_DATA\n"));
+        assert_eq!(output.unwrap().bytes, vec![0xAD, 0x30, 0x02]);
+    }
+
+    #[test]
+    fn test_json() {
+        let map: Value = serde_json::from_str(&to_json(&devices())).unwrap();
+        let via = &map["devices"][0];
+        assert_eq!(via["constant"], "VIA");
+        assert_eq!(via["registers"][13]["name"], "IFR");
+        assert_eq!(via["registers"][13]["address"], 0x020D);
+        assert_eq!(via["registers"][13]["fields"][1]["constant"], "VIA_IFR_T1");
+        assert_eq!(via["registers"][13]["fields"][1]["mask"], 0x40);
+        assert_eq!(map["devices"][2]["constant"], "STAR_TRACKER_2");
+    }
+
+    #[test]
+    fn test_injected_constants() {
+        let constants = constants(&devices());
+        let code = "VIA = $0300\nLDA VIA_IFR\nAND #VIA_IFR_T1\nSTA VIA\n";
+        let output = asm6502::assemble_string_with(code, &constants).unwrap();
+        assert_eq!(
+            output.bytes,
+            vec![0xAD, 0x0D, 0x02, 0x29, 0x40, 0x8D, 0x00, 0x03]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+// Resident monitor ROM, assembled from rom/monitor.asm by build.rs.
+//
+// The monitor sits at the top of memory and talks over the ACIA at MONITOR_ACIA, the ship's
+// antenna. With `take_reset` installing also points the reset vector at it, so a reset (e.g. by
+// the watchdog) lands in the monitor instead of restarting the program.
+
+use crate::cpu_wrapper::CPUWrapper;
+use crate::devices::RESET_VECTOR;
+
+include!(concat!(env!("OUT_DIR"), "/monitor.rs"));
+
+pub const MONITOR_ROM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/monitor.bin"));
+pub const MONITOR_ACIA: u16 = 0x0215;
+
+pub fn install(cpu: &CPUWrapper, take_reset: bool) {
+    for (i, byte) in MONITOR_ROM.iter().enumerate() {
+        cpu.write_byte(MONITOR_ORIGIN + i as u16, *byte);
+    }
+    if take_reset {
+        cpu.write_byte(RESET_VECTOR, MONITOR_ENTRY as u8);
+        cpu.write_byte(RESET_VECTOR + 1, (MONITOR_ENTRY >> 8) as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::acia::Acia;
+    use std::collections::HashMap;
+
+    fn monitor() -> CPUWrapper {
+        let cpu = CPUWrapper::new(MONITOR_ENTRY, Vec::new(), HashMap::new());
+        install(&cpu, true);
+        cpu.attach_device(Box::new(Acia::new(MONITOR_ACIA, 1_000_000)))
+            .unwrap();
+        cpu
+    }
+
+    fn send(cpu: &CPUWrapper, line: &str) -> String {
+        cpu.with_device("acia", |acia: &mut Acia| {
+            acia.push_received(line.as_bytes())
+        });
+        cpu.run_cycles(line.len() as u32 * 2_000);
+        let bytes = cpu
+            .with_device("acia", |acia: &mut Acia| acia.pop_transmitted())
+            .unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_rom_fits_below_vectors() {
+        assert!(MONITOR_ORIGIN as usize + MONITOR_ROM.len() <= 0xFFFA);
+    }
+
+    #[test]
+    fn test_install_keeps_reset_vector() {
+        let cpu = CPUWrapper::new(0x0600, vec![0xEA], HashMap::new());
+        cpu.write_byte(RESET_VECTOR, 0x00);
+        cpu.write_byte(RESET_VECTOR + 1, 0x06);
+        install(&cpu, false);
+        assert_eq!(cpu.read_byte(MONITOR_ORIGIN), MONITOR_ROM[0]);
+        assert_eq!(cpu.read_byte(RESET_VECTOR), 0x00);
+        assert_eq!(cpu.read_byte(RESET_VECTOR + 1), 0x06);
+
+        install(&cpu, true);
+        assert_eq!(cpu.read_byte(RESET_VECTOR), MONITOR_ENTRY as u8);
+        assert_eq!(cpu.read_byte(RESET_VECTOR + 1), (MONITOR_ENTRY >> 8) as u8);
+    }
+
+    #[test]
+    fn test_upload_examine_and_run() {
+        let cpu = monitor();
+        send(&cpu, "\n");
+
+        // LDA #$42, STA $10, RTS
+        send(&cpu, "0600: a9 42 85 10 60\n");
+        let output = send(&cpu, "0600.0604\n");
+        assert!(output.contains("0600: A9 42 85 10 60"), "{:?}", output);
+
+        send(&cpu, "0600 R\n");
+        assert_eq!(cpu.read_byte(0x10), 0x42);
+
+        // back in the monitor after the RTS
+        let output = send(&cpu, "0010\n");
+        assert!(output.contains("0010: 42"), "{:?}", output);
+    }
+}
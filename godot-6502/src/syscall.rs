@@ -0,0 +1,145 @@
+// Syscalls: numbered calls from a ship program into the game.
+//
+// A program makes syscall n either with `BRK #n`, BRK followed by its signature byte, or with a
+// JSR to the trap address followed by the byte n. Instead of executing the instruction the CPU
+// calls the handler registered for n, which may change A, X, Y and the flags, and carries on after
+// the signature byte. Numbers without a handler execute normally, so BRK still reaches the IRQ
+// vector.
+
+use std::rc::Rc;
+
+const BRK: u8 = 0x00;
+const JSR: u8 = 0x20;
+
+// Cycles a syscall takes: those of BRK, and of JSR plus the RTS returning from it
+pub const BRK_CYCLES: u32 = 7;
+pub const TRAP_CYCLES: u32 = 12;
+
+// Trap address until the program sets another, just below the monitor ROM
+pub const DEFAULT_TRAP_ADDRESS: u16 = 0xFDF0;
+
+// The registers a handler sees and may change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallRegisters {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+}
+
+pub type SyscallHandler = Rc<dyn Fn(u8, &mut SyscallRegisters)>;
+
+// A syscall about to be made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Syscall {
+    pub number: u8,
+    pub resume_address: u16, // after the signature byte
+    pub cycles: u32,
+}
+
+// The syscall the instruction at `pc` makes, if any. `read` reads a byte of memory and `handled`
+// tells whether a number has a handler.
+pub fn decode(
+    pc: u16,
+    trap_address: Option<u16>,
+    mut read: impl FnMut(u16) -> u8,
+    handled: impl Fn(u8) -> bool,
+) -> Option<Syscall> {
+    let syscall = match read(pc) {
+        BRK => Syscall {
+            number: read(pc.wrapping_add(1)),
+            resume_address: pc.wrapping_add(2),
+            cycles: BRK_CYCLES,
+        },
+        JSR => {
+            let target = read(pc.wrapping_add(1)) as u16 | (read(pc.wrapping_add(2)) as u16) << 8;
+            if Some(target) != trap_address {
+                return None;
+            }
+            Syscall {
+                number: read(pc.wrapping_add(3)),
+                resume_address: pc.wrapping_add(4),
+                cycles: TRAP_CYCLES,
+            }
+        }
+        _ => return None,
+    };
+    handled(syscall.number).then_some(syscall)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RoutineTest, FLAG_CARRY};
+
+    const TRAP: u16 = 0x0300;
+
+    const SOURCE: &str = "
+.org $0600
+brk_call:
+    LDA #$05
+    LDX #$07
+    BRK #$01
+    STA $10
+    STX $11
+    RTS
+
+trap_call:
+    LDA #$05
+    JSR $0300
+    .byte $01
+    STA $10
+    RTS
+
+unhandled:
+    BRK #$02
+    RTS
+
+irq:
+    LDA #$EE
+    STA $12
+    PLA
+    PLA
+    PLA
+    RTS
+";
+
+    // Syscall 1 adds X to A and sets carry
+    fn test() -> RoutineTest {
+        let test = RoutineTest::new(SOURCE).unwrap();
+        test.cpu().set_syscall_trap(Some(TRAP));
+        test.cpu().register_syscall(
+            1,
+            Rc::new(|_, registers: &mut SyscallRegisters| {
+                registers.a = registers.a.wrapping_add(registers.x);
+                registers.p |= 0x01;
+            }),
+        );
+        test
+    }
+
+    #[test]
+    fn test_brk_syscall() {
+        let mut test = test();
+        test.call("brk_call").unwrap();
+        test.assert_memory(0x10, &[0x0C, 0x07]);
+        test.assert_flag(FLAG_CARRY, true);
+    }
+
+    #[test]
+    fn test_trap_syscall() {
+        let mut test = test();
+        let result = test.call("trap_call").unwrap();
+        test.assert_memory(0x10, &[0x05]);
+        assert_eq!(result.cycles, 2 + TRAP_CYCLES as u64 + 3 + 6);
+    }
+
+    #[test]
+    fn test_unhandled_numbers_execute() {
+        let mut test = test();
+        let irq = test.symbol("irq").unwrap();
+        test.write_memory(0xFFFE, &[irq as u8, (irq >> 8) as u8]);
+        test.call("unhandled").unwrap();
+        test.assert_memory(0x12, &[0xEE]);
+    }
+}
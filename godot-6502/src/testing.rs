@@ -0,0 +1,419 @@
+// Unit-testing harness for 6502 subroutines.
+//
+// A `RoutineTest` assembles a program, loads it into a fresh CPU and lets a test set registers
+// and memory, call a subroutine by label and assert on the result:
+//
+//     let mut test = RoutineTest::new(SOURCE).unwrap();
+//     test.set_a(3).set_x(4);
+//     test.call("multiply").unwrap();
+//     test.assert_a(12);
+//
+// Failed assertions report the source line of the last instruction the routine executed.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::asm6502::{self, AssemblyOutput};
+use crate::cpu_wrapper::CPUWrapper;
+#[cfg(test)]
+use crate::devices::ShipDevice;
+
+// Return address pushed before jumping into a routine. When the PC lands here with the stack
+// back where it started, the routine executed its matching RTS.
+pub const RETURN_TRAP: u16 = 0xFFF0;
+pub const DEFAULT_CYCLE_LIMIT: u64 = 100_000;
+
+// Status register bits
+pub const FLAG_CARRY: u8 = 0b0000_0001;
+pub const FLAG_ZERO: u8 = 0b0000_0010;
+pub const FLAG_INTERRUPT: u8 = 0b0000_0100;
+pub const FLAG_DECIMAL: u8 = 0b0000_1000;
+pub const FLAG_OVERFLOW: u8 = 0b0100_0000;
+pub const FLAG_NEGATIVE: u8 = 0b1000_0000;
+
+#[derive(Debug, PartialEq)]
+pub enum TestError {
+    Assembly(String),
+    UnknownLabel(String),
+    CycleLimit {
+        label: String,
+        cycles: u64,
+        pc: u16,
+        line: Option<u32>,
+    },
+}
+
+impl fmt::Display for TestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestError::Assembly(message) => write!(f, "Assembly failed: {}", message),
+            TestError::UnknownLabel(label) => write!(f, "Label '{}' not found", label),
+            TestError::CycleLimit {
+                label,
+                cycles,
+                pc,
+                line,
+            } => write!(
+                f,
+                "'{}' did not return within {} cycles (PC=${:04X}{})",
+                label,
+                cycles,
+                pc,
+                format_line(*line)
+            ),
+        }
+    }
+}
+
+fn format_line(line: Option<u32>) -> String {
+    match line {
+        Some(line) => format!(", line {}", line + 1),
+        None => String::new(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CallResult {
+    pub cycles: u64,
+    pub instructions: u64,
+}
+
+pub struct RoutineTest {
+    cpu: CPUWrapper,
+    symbols: HashMap<String, u16>,
+    cycle_limit: u64,
+    label: String,
+    last_pc: Option<u16>,
+}
+
+impl RoutineTest {
+    pub fn new(source: &str) -> Result<Self, TestError> {
+        let output = asm6502::assemble_string(source)
+            .map_err(|diagnostics| TestError::Assembly(asm6502::describe(&diagnostics)))?;
+        Ok(Self::from_output(output))
+    }
+
+    pub fn from_output(output: AssemblyOutput) -> Self {
+        Self {
+            cpu: CPUWrapper::new(output.start_address, output.bytes, output.offset_to_line),
+            symbols: output.symbols,
+            cycle_limit: DEFAULT_CYCLE_LIMIT,
+            label: String::new(),
+            last_pc: None,
+        }
+    }
+
+    pub fn cpu(&self) -> &CPUWrapper {
+        &self.cpu
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn with_cycle_limit(mut self, cycle_limit: u64) -> Self {
+        self.cycle_limit = cycle_limit;
+        self
+    }
+
+    pub fn set_a(&mut self, value: u8) -> &mut Self {
+        self.cpu.get_cpu().borrow_mut().regs.a = value;
+        self
+    }
+
+    pub fn set_x(&mut self, value: u8) -> &mut Self {
+        self.cpu.get_cpu().borrow_mut().regs.x = value;
+        self
+    }
+
+    pub fn set_y(&mut self, value: u8) -> &mut Self {
+        self.cpu.get_cpu().borrow_mut().regs.y = value;
+        self
+    }
+
+    pub fn set_flag(&mut self, flag: u8, value: bool) -> &mut Self {
+        let status = self.cpu.get_status();
+        self.cpu.set_status(match value {
+            true => status | flag,
+            false => status & !flag,
+        });
+        self
+    }
+
+    pub fn write_memory(&mut self, address: u16, bytes: &[u8]) -> &mut Self {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.cpu.write_byte(address.wrapping_add(i as u16), *byte);
+        }
+        self
+    }
+
+    pub fn a(&self) -> u8 {
+        self.cpu.get_cpu().borrow().regs.a
+    }
+
+    pub fn x(&self) -> u8 {
+        self.cpu.get_cpu().borrow().regs.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.cpu.get_cpu().borrow().regs.y
+    }
+
+    pub fn flag(&self, flag: u8) -> bool {
+        self.cpu.get_status() & flag != 0
+    }
+
+    pub fn read_memory(&self, address: u16, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.cpu.read_byte(address.wrapping_add(i as u16)))
+            .collect()
+    }
+
+    // JSR to `label` and run until its matching RTS or the cycle limit
+    pub fn call(&mut self, label: &str) -> Result<CallResult, TestError> {
+        let address = self
+            .symbol(label)
+            .ok_or_else(|| TestError::UnknownLabel(label.to_string()))?;
+        self.call_address(label, address)
+    }
+
+    pub fn call_address(&mut self, label: &str, address: u16) -> Result<CallResult, TestError> {
+        self.label = label.to_string();
+        self.last_pc = None;
+
+        let return_address = RETURN_TRAP.wrapping_sub(1);
+        self.push((return_address >> 8) as u8);
+        self.push(return_address as u8);
+        let stack = self.cpu.get_cpu().borrow().regs.s;
+        self.cpu.get_cpu().borrow_mut().regs.pc = address;
+
+        let start_cycles = self.cpu.get_cycles();
+        let mut instructions = 0;
+
+        loop {
+            let (pc, sp) = {
+                let cpu = self.cpu.get_cpu();
+                let cpu = cpu.borrow();
+                (cpu.regs.pc, cpu.regs.s)
+            };
+
+            if pc == RETURN_TRAP && sp == stack.wrapping_add(2) {
+                return Ok(CallResult {
+                    cycles: self.cpu.get_cycles() - start_cycles,
+                    instructions,
+                });
+            }
+
+            let cycles = self.cpu.get_cycles() - start_cycles;
+            if cycles >= self.cycle_limit {
+                return Err(TestError::CycleLimit {
+                    label: self.label.clone(),
+                    cycles,
+                    pc,
+                    line: self.cpu.get_line_number(pc),
+                });
+            }
+
+            self.last_pc = Some(pc);
+            self.cpu.run_step();
+            instructions += 1;
+        }
+    }
+
+    fn push(&mut self, value: u8) {
+        let cpu = self.cpu.get_cpu();
+        let mut cpu = cpu.borrow_mut();
+        let address = 0x0100 | cpu.regs.s as usize;
+        let _ = cpu.bus.get_memory().write_byte(address, value);
+        cpu.regs.s = cpu.regs.s.wrapping_sub(1);
+    }
+
+    // Source line of the last instruction executed by the previous call
+    pub fn last_line(&self) -> Option<u32> {
+        self.last_pc.and_then(|pc| self.cpu.get_line_number(pc))
+    }
+
+    fn failure(&self, what: &str, expected: String, actual: String) -> String {
+        format!(
+            "'{}': expected {} = {}, got {} (last instruction{})",
+            self.label,
+            what,
+            expected,
+            actual,
+            match self.last_line() {
+                Some(line) => format!(" at line {}", line + 1),
+                None => " unknown".to_string(),
+            }
+        )
+    }
+
+    #[track_caller]
+    pub fn assert_a(&self, expected: u8) {
+        if self.a() != expected {
+            panic!(
+                "{}",
+                self.failure(
+                    "A",
+                    format!("${:02X}", expected),
+                    format!("${:02X}", self.a())
+                )
+            );
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_x(&self, expected: u8) {
+        if self.x() != expected {
+            panic!(
+                "{}",
+                self.failure(
+                    "X",
+                    format!("${:02X}", expected),
+                    format!("${:02X}", self.x())
+                )
+            );
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_y(&self, expected: u8) {
+        if self.y() != expected {
+            panic!(
+                "{}",
+                self.failure(
+                    "Y",
+                    format!("${:02X}", expected),
+                    format!("${:02X}", self.y())
+                )
+            );
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_flag(&self, flag: u8, expected: bool) {
+        if self.flag(flag) != expected {
+            panic!(
+                "{}",
+                self.failure(
+                    &format!("flag %{:08b}", flag),
+                    expected.to_string(),
+                    self.flag(flag).to_string()
+                )
+            );
+        }
+    }
+
+    #[track_caller]
+    pub fn assert_memory(&self, address: u16, expected: &[u8]) {
+        let actual = self.read_memory(address, expected.len());
+        if actual != expected {
+            panic!(
+                "{}",
+                self.failure(
+                    &format!("memory at ${:04X}", address),
+                    format!("{:02X?}", expected),
+                    format!("{:02X?}", actual)
+                )
+            );
+        }
+    }
+}
+
+// A fresh CPU running `source` with `device` attached, for devices that run alongside the whole
+// program rather than being called like a routine. Panics if either step fails.
+#[cfg(test)]
+pub fn device_cpu(source: &str, device: Box<dyn ShipDevice>) -> (CPUWrapper, AssemblyOutput) {
+    let output = asm6502::assemble_string(source).unwrap();
+    let cpu = CPUWrapper::new(
+        output.start_address,
+        output.bytes.clone(),
+        output.offset_to_line.clone(),
+    );
+    cpu.attach_device(device).unwrap();
+    (cpu, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+RESULT = $0010
+
+.org $0600
+
+; A = X * Y, clobbers RESULT
+multiply:
+    LDA #$00
+    CPY #$00
+    BEQ done
+loop:
+    CLC
+    STX RESULT
+    ADC RESULT
+    DEY
+    BNE loop
+done:
+    RTS
+
+; counts down X forever
+hang:
+    DEX
+    JMP hang
+
+; stores A at RESULT through a nested call
+store:
+    JSR store_inner
+    RTS
+store_inner:
+    STA RESULT
+    RTS
+";
+
+    #[test]
+    fn test_call_returns_registers() {
+        let mut test = RoutineTest::new(SOURCE).unwrap();
+        test.set_x(3).set_y(4);
+        let result = test.call("multiply").unwrap();
+
+        test.assert_a(12);
+        test.assert_y(0);
+        test.assert_flag(FLAG_ZERO, true);
+        assert!(result.cycles > 0);
+    }
+
+    #[test]
+    fn test_call_nested_subroutine() {
+        let mut test = RoutineTest::new(SOURCE).unwrap();
+        test.set_a(0x42);
+        test.call("store").unwrap();
+        test.assert_memory(0x0010, &[0x42]);
+    }
+
+    #[test]
+    fn test_cycle_limit_reports_line() {
+        let mut test = RoutineTest::new(SOURCE).unwrap().with_cycle_limit(100);
+        match test.call("hang") {
+            Err(TestError::CycleLimit { line, .. }) => assert!(matches!(line, Some(21) | Some(22))),
+            _ => panic!("expected the cycle limit to be hit"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_label() {
+        let mut test = RoutineTest::new(SOURCE).unwrap();
+        assert_eq!(
+            test.call("missing"),
+            Err(TestError::UnknownLabel("missing".to_string()))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "line 18")]
+    fn test_failure_reports_line() {
+        let mut test = RoutineTest::new(SOURCE).unwrap();
+        test.set_x(2).set_y(2);
+        test.call("multiply").unwrap();
+        test.assert_a(5);
+    }
+}
@@ -6,8 +6,20 @@ var emulator: Emulator6502
 var program: String = ""
 var pause: bool = false
 
+# Syscalls ship programs can make with BRK #n, see godot-6502/src/syscall.rs
+const SYSCALL_LOG = 1 # print A, X and Y
+
 func _init() -> void:
 	emulator = Emulator6502.create_cpu(10)
+	# Programs can use the ship's device constants, like VIA_IFR, without defining them
+	emulator.set_memory_map_injection(true)
+	emulator.register_syscall(SYSCALL_LOG, _syscall_log)
+
+	# Replay a flight with the same random numbers with: godot -- --seed=1234
+	# The debug adapter, --dap=4711, follows the active ship, see main.gd
+	for arg in OS.get_cmdline_user_args():
+		if arg.begins_with("--seed="):
+			emulator.set_seed(int(arg.trim_prefix("--seed=")))
 
 func _process(delta: float) -> void:
 	if (Engine.get_process_frames() == 0):
@@ -15,6 +27,8 @@ func _process(delta: float) -> void:
 		for addr in range(0x200, 0x300):
 			emulator.set_memory(addr, 0)
 
+	emulator.dap_poll()
+
 	if !pause:
 		emulator.wait_until_done()
 		
@@ -24,10 +38,26 @@ func _process(delta: float) -> void:
 	if !pause:
 		emulator.execute_cycles_for_duration(delta)
 
+func _syscall_log(context: SyscallContext) -> void:
+	print("Ship program: A=$%02X X=$%02X Y=$%02X" % [context.get_a(), context.get_x(), context.get_y()])
+
 func load_program_from_string(program_string: String) -> void:
 	program = program_string
 	emulator.load_program_from_string(program_string, 0x600)
 
+# Swap in new code without restarting the ship. Returns why it could not, or "" when it did.
+func hot_reload(program_string: String) -> String:
+	var reason = emulator.hot_reload(program_string)
+	if reason.is_empty():
+		program = program_string
+	return reason
+
+# Copy the monitor ROM to $FE00, it talks over the antenna's ACIA at $0215.
+# With enter the program stops and the monitor takes over right away. With take_reset a reset
+# (e.g. by the watchdog) lands in the monitor instead of restarting the program.
+func install_monitor(enter: bool = false, take_reset: bool = false) -> void:
+	emulator.install_monitor(enter, take_reset)
+
 func pause_emulator() -> void:
 	pause = true
 
@@ -39,3 +69,15 @@ func step() -> void:
 
 func add_component(component: Node3D) -> void:
 	shipComponents.append(component)
+
+# Persist an EEPROM attached with emulator.attach_eeprom, e.g. to "user://eeprom.bin"
+func save_eeprom(base_address: int, path: String) -> void:
+	var file = FileAccess.open(path, FileAccess.WRITE)
+	if file:
+		file.store_buffer(PackedByteArray(emulator.eeprom_get_image(base_address)))
+
+func load_eeprom(base_address: int, path: String) -> bool:
+	if not FileAccess.file_exists(path):
+		return false
+	var image = Array(FileAccess.get_file_as_bytes(path))
+	return emulator.eeprom_set_image(base_address, image)
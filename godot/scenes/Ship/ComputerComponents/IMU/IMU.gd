@@ -1,4 +1,8 @@
-extends ShipComponent
+extends SensorComponent
+
+# Acceleration x, y, z then angular velocity x, y, z. In 8 bits each reading is remapped from
+# -MAX_ACCEL to MAX_ACCEL (acceleration) or -MAX_ANG_VEL to MAX_ANG_VEL (angular velocity) to 0-255;
+# the other encodings share the range of MAX_ACCEL.
 
 var rigid_body: RigidBody3D
 var planet_node: Node3D
@@ -12,6 +16,8 @@ var acceleration: Vector3
 
 func _init() -> void:
 	memory_size = 6
+	channels = 6
+	sensor_range = MAX_ACCEL
 
 func _ready() -> void:
 	var parent = get_parent()
@@ -58,6 +64,12 @@ func _physics_process(delta: float) -> void:
 
 
 func run_logic(_delta: float) -> void:
+	if high_resolution():
+		var angular_velocity = rigid_body.angular_velocity
+		set_readings([acceleration.x, acceleration.y, acceleration.z,
+			angular_velocity.x, angular_velocity.y, angular_velocity.z])
+		return
+
 	addressBuffer[0] = round(clamp(remap(acceleration.x, -MAX_ACCEL, MAX_ACCEL, 0, 255), 0, 255))
 	addressBuffer[1] = round(clamp(remap(acceleration.y, -MAX_ACCEL, MAX_ACCEL, 0, 255), 0, 255))
 	addressBuffer[2] = round(clamp(remap(acceleration.z, -MAX_ACCEL, MAX_ACCEL, 0, 255), 0, 255))
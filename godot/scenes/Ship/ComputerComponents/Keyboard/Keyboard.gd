@@ -0,0 +1,22 @@
+extends ShipComponent
+
+# Keyboard and gamepad of the ship's computer, emulated by the Rust crate:
+#   +$00 STATUS, +$01 CONTROL, +$02 ASCII, +$03 SCANCODE, +$04 KEY_FLAGS, +$05 NEXT,
+#   +$06 AXES (4 bytes), +$0A BUTTONS (2 bytes) (see godot-6502/src/devices/input.rs)
+
+func _init() -> void:
+	memory_size = 0x0C
+
+func attach() -> bool:
+	return emulator.attach_input(memory_address)
+
+# The device keeps its own registers up to date, there is nothing to copy every frame
+func _physics_process(_delta: float) -> void:
+	pass
+
+# Only the ship the player is watching gets the keys
+func _unhandled_input(event: InputEvent) -> void:
+	var main = get_tree().current_scene
+	if "active_ship" in main and main.active_ship != find_ship():
+		return
+	emulator.input_event(memory_address, event)
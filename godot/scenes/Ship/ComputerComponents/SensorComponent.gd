@@ -0,0 +1,44 @@
+extends ShipComponent
+
+class_name SensorComponent
+
+# Base for sensors that can report in more than 8 bits. With UNSIGNED_8 the component keeps its
+# original layout of one byte per reading; any other encoding attaches the sensor device emulated
+# by the Rust crate instead:
+#   +$00 LATCH, +$01 ENCODING, +$02 CHANNELS, +$03 RANGE, +$05 RESOLUTION, +$09 VALUES
+# (see godot-6502/src/devices/sensor.rs). Write LATCH before reading VALUES.
+
+enum Encoding { UNSIGNED_8, SIGNED_16, FIXED_8_8, FIXED_16_16 }
+
+const ENCODING_WIDTHS = [1, 2, 2, 4] # bytes per reading
+const SENSOR_VALUES = 0x09
+
+@export var encoding: Encoding = Encoding.UNSIGNED_8
+
+var channels: int = 1
+var sensor_range: float = 1.0 # readings go from -sensor_range to sensor_range
+
+func startup() -> void:
+	if encoding != Encoding.UNSIGNED_8:
+		memory_size = SENSOR_VALUES + channels * ENCODING_WIDTHS[encoding]
+	super.startup()
+
+func attach() -> bool:
+	if encoding == Encoding.UNSIGNED_8:
+		return super.attach()
+	return emulator.attach_sensor(name, memory_address, channels, encoding, sensor_range)
+
+# The sensor device keeps its registers itself, only the readings need updating
+func _physics_process(delta: float) -> void:
+	if encoding == Encoding.UNSIGNED_8:
+		super._physics_process(delta)
+	elif Engine.get_process_frames() % every_n_frames == 0:
+		run_logic(delta)
+
+func high_resolution() -> bool:
+	return encoding != Encoding.UNSIGNED_8
+
+# Hand one reading per channel to the sensor device, the program sees them once it latches
+func set_readings(readings: Array) -> void:
+	for channel in range(readings.size()):
+		emulator.sensor_set(memory_address, channel, readings[channel])
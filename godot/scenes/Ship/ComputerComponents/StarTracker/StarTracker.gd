@@ -1,10 +1,15 @@
-extends ShipComponent
+extends SensorComponent
+
+# Rotation x, y, z in radians, then the deviation of the noise. In 8 bits the rotation is remapped
+# from -PI to PI and the deviation from 0 to 10 to 0-255; the other encodings share a range of 10.
 
 var rigid_body: RigidBody3D
 
 
 func _init() -> void:
 	memory_size = 4
+	channels = 4
+	sensor_range = 10.0
 
 func _ready() -> void:
 	var parent = get_parent()
@@ -28,12 +33,14 @@ func run_logic(_delta: float) -> void:
 	# Cap the maximum deviation to avoid extreme values
 	deviation = min(deviation, 10.0)
 	
-	var rng = RandomNumberGenerator.new()
-	rng.randomize()
-	
-	var base_x = fmod(rigid_body.rotation.x + rng.randfn(0, deviation) + PI, 2 * PI) - PI
-	var base_y = fmod(rigid_body.rotation.y + rng.randfn(0, deviation) + PI, 2 * PI) - PI
-	var base_z = fmod(rigid_body.rotation.z + rng.randfn(0, deviation) + PI, 2 * PI) - PI
+	# Noise comes from the emulator's seeded stream so flights can be replayed
+	var base_x = fmod(rigid_body.rotation.x + emulator.rand_normal(0, deviation) + PI, 2 * PI) - PI
+	var base_y = fmod(rigid_body.rotation.y + emulator.rand_normal(0, deviation) + PI, 2 * PI) - PI
+	var base_z = fmod(rigid_body.rotation.z + emulator.rand_normal(0, deviation) + PI, 2 * PI) - PI
+
+	if high_resolution():
+		set_readings([base_x, base_y, base_z, deviation])
+		return
 
 	addressBuffer[0] = round(clamp(remap(base_x, -PI, PI, 0, 255), 0, 255))
 	addressBuffer[1] = round(clamp(remap(base_y, -PI, PI, 0, 255), 0, 255))
@@ -1,4 +1,21 @@
 extends ShipComponent
 
+# Serial link of the ship, a 6551 ACIA emulated by the Rust crate:
+#   +0 DATA, +1 STATUS, +2 COMMAND, +3 CONTROL (see godot-6502/src/devices/acia.rs)
+
+signal transmitted(bytes: Array) # bytes sent by the ship program
+
 func _init() -> void:
-	memory_size = 1
+	memory_size = 4
+
+func attach() -> bool:
+	return emulator.attach_acia(memory_address)
+
+# Send bytes to the ship, they are received at the configured baud rate
+func receive(bytes: Array) -> void:
+	emulator.acia_push_rx(memory_address, bytes)
+
+func run_logic(_delta: float) -> void:
+	var bytes = emulator.acia_pop_tx(memory_address)
+	if bytes.size() > 0:
+		transmitted.emit(bytes)
@@ -0,0 +1,25 @@
+extends ShipComponent
+
+# Cockpit screen, a bitmap display emulated by the Rust crate:
+#   +$00 CONTROL, +$01 STATUS, +$02 WIDTH, +$03 HEIGHT, +$04 DEPTH, +$05 FRAME,
+#   +$10 PALETTE, +$40 FRAMEBUFFER (see godot-6502/src/devices/display.rs)
+
+@export var width: int = 64
+@export var height: int = 48
+@export var depth: int = 4 # bits per pixel: 1, 2 or 4
+@export var screen: Sprite3D # shows the display, if set
+
+func _init() -> void:
+	memory_size = 0x40
+
+func attach() -> bool:
+	return emulator.attach_display(memory_address, width, height, depth)
+
+# The display lives in emulator memory, there is nothing to copy every frame
+func _physics_process(_delta: float) -> void:
+	pass
+
+func _process(_delta: float) -> void:
+	var texture = emulator.display_get_texture(memory_address)
+	if screen and texture:
+		screen.texture = texture
@@ -0,0 +1,32 @@
+extends ShipComponent
+
+# Sound generator emulated by the Rust crate: tone channels A, B and C and a noise channel at
+# +$00, +$04, +$08 and +$0C (FREQUENCY, VOLUME, WAVE), then the envelope's ATTACK, DECAY, SUSTAIN,
+# RELEASE and GATE at +$10 (see godot-6502/src/devices/sound.rs)
+
+const SAMPLE_RATE = 22050
+
+var playback: AudioStreamGeneratorPlayback
+
+func _init() -> void:
+	memory_size = 0x15
+
+func attach() -> bool:
+	var stream = AudioStreamGenerator.new()
+	stream.mix_rate = SAMPLE_RATE
+	stream.buffer_length = 0.25
+	var player = AudioStreamPlayer3D.new()
+	player.stream = stream
+	add_child(player)
+	player.play()
+	playback = player.get_stream_playback()
+	return emulator.attach_sound(memory_address, SAMPLE_RATE)
+
+# The device keeps its own registers, there is nothing to copy every frame
+func _physics_process(_delta: float) -> void:
+	pass
+
+func _process(_delta: float) -> void:
+	var frames = emulator.sound_pop_frames(memory_address, playback.get_frames_available())
+	if frames.size() > 0:
+		playback.push_buffer(frames)
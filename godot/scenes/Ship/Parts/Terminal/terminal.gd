@@ -0,0 +1,26 @@
+extends ShipComponent
+
+# 40x25 text terminal emulated by the Rust crate:
+#   +$00 CONTROL, +$01 CURSOR_X, +$02 CURSOR_Y, +$03 COLOR, +$04 PUTC, +$05 COMMAND,
+#   +$10 CHARS, +$3F8 COLORS (see godot-6502/src/devices/terminal.rs)
+
+@export var screen: Sprite3D # shows the terminal, if set
+
+func _init() -> void:
+	memory_size = 0x7E0
+
+func attach() -> bool:
+	return emulator.attach_terminal(memory_address)
+
+# What the terminal shows, one line per row
+func get_text() -> String:
+	return emulator.terminal_get_text(memory_address)
+
+# The terminal lives in emulator memory, there is nothing to copy every frame
+func _physics_process(_delta: float) -> void:
+	pass
+
+func _process(_delta: float) -> void:
+	var texture = emulator.terminal_get_texture(memory_address)
+	if screen and texture:
+		screen.texture = texture
@@ -5,11 +5,22 @@ class_name Ship
 const Computer = preload("res://scenes/Ship/Computer.gd")
 const IMU = preload("res://scenes/Ship/ComputerComponents/IMU/IMU.gd")
 const StarTracker = preload("res://scenes/Ship/ComputerComponents/StarTracker/StarTracker.gd")
+const Keyboard = preload("res://scenes/Ship/ComputerComponents/Keyboard/Keyboard.gd")
+const Display = preload("res://scenes/Ship/Parts/Display/display.gd")
+const Terminal = preload("res://scenes/Ship/Parts/Terminal/terminal.gd")
+const Speaker = preload("res://scenes/Ship/Parts/Speaker/speaker.gd")
 
 @export var planet_node: Node3D = null
 # Crash parameters
 @export var crash_vertical_speed_threshold: float = 30.0
 @export var crash_altitude_margin: float = 1.0
+# Resident monitor ROM at $FE00, to examine and patch memory over the antenna in flight.
+# The reset vector is left alone, so a watchdog reset still restarts the flight program.
+@export var resident_monitor: bool = true
+# How the IMU and star tracker report. UNSIGNED_8 keeps one byte per reading at $0200 and $0206;
+# the other encodings take more room (up to 9 + 6 * 4 bytes for the IMU), so those sensors sit past
+# the parts, at $0220 and $0250. The IMU and STAR_TRACKER constants hold the address either way.
+@export var sensor_encoding: SensorComponent.Encoding = SensorComponent.Encoding.UNSIGNED_8
 
 var computer: Computer = null
 var _has_exploded: bool = false
@@ -19,10 +30,6 @@ func _init() -> void:
 	computer.name = "Computer"
 	add_child(computer)
 
-	add_computer_component(IMU, 0x200)
-	add_computer_component(StarTracker, 0x206)
-	
-
 # Called when the node enters the scene tree for the first time.
 func _ready() -> void:
 	# Set center of mass to ship's geometric center to prevent unwanted torque
@@ -33,6 +40,20 @@ func _ready() -> void:
 	for address in range(0x200, 0x300):
 		computer.emulator.set_memory(address, 0)
 
+	# Added here rather than in _init, which runs before the exported sensor_encoding is set
+	var wide = sensor_encoding != SensorComponent.Encoding.UNSIGNED_8
+	add_sensor(IMU, "IMU", 0x220 if wide else 0x200)
+	add_sensor(StarTracker, "StarTracker", 0x250 if wide else 0x206)
+
+	# Cockpit devices. The display and terminal take whole pages, so they sit below the monitor ROM.
+	add_device(Speaker, "Speaker", 0x270)
+	add_device(Keyboard, "Keyboard", 0x288)
+	add_device(Display, "Display", 0xE800)
+	add_device(Terminal, "Terminal", 0xF000)
+
+	if resident_monitor:
+		computer.install_monitor()
+
 func set_initial_speed() -> void:
 	if not planet_node:
 		return
@@ -58,6 +79,21 @@ func add_computer_component(component, memory_address):
 	add_child(component)
 	component.startup()
 
+# For components that attach themselves in ShipComponent._ready
+func add_device(component, component_name: String, memory_address: int) -> void:
+	component = component.new()
+	component.name = component_name
+	component.memory_address = memory_address
+	add_child(component)
+
+func add_sensor(component, component_name: String, memory_address: int) -> void:
+	component = component.new()
+	component.name = component_name
+	component.encoding = sensor_encoding
+	component.memory_address = memory_address
+	add_child(component)
+	component.startup()
+
 func _physics_process(_delta: float) -> void:
 	if _has_exploded or planet_node == null:
 		return
@@ -26,16 +26,16 @@ func startup() -> void:
 	addressBuffer.resize(memory_size)
 	addressBuffer.fill(0)
 
-	# Check if every address the component will use in memory is set to 0
-	for i in range(memory_size):
-		assert(emulator.read_memory(memory_address + i) == 0, "Memory address " + str(memory_address + i) + " is not set to 0. Are components using the same memory address?")
-
-	# Set all addresses the component will use in memory to 255
-	for i in range(memory_size):
-		emulator.set_memory(memory_address + i, 255)
+	# The emulator's device registry refuses components whose addresses overlap
+	var attached = attach()
+	assert(attached, "Could not map " + name + " at 0x" + "%04X" % memory_address + ", see the error log")
 
 	add_to_group("ship_component")
 
+# Claim the component's memory in the emulator, components backed by a Rust device attach it here
+func attach() -> bool:
+	return emulator.register_region(name, memory_address, memory_size)
+
 func run_logic(_delta: float) -> void:
 	pass
 
@@ -6,6 +6,11 @@ extends Node3D
 var ships: Array[Node] = []
 var ship_idx: int = 0
 
+# Attach a Debug Adapter Protocol client with: godot -- --dap=4711
+# One listener on that port, moved to whichever ship is active
+var dap_port: int = 0
+var dap_ship: Node = null
+
 # Property to access the currently active ship
 @export var active_ship: Node:
 	get:
@@ -30,6 +35,10 @@ var ship_idx: int = 0
 
 # Called when the node enters the scene tree for the first time.
 func _ready() -> void:
+	for arg in OS.get_cmdline_user_args():
+		if arg.begins_with("--dap="):
+			dap_port = int(arg.trim_prefix("--dap="))
+
 	ships = get_tree().get_nodes_in_group("ships")
 	# If no ships exist, spawn one
 	if ships.is_empty():
@@ -110,6 +119,20 @@ func previous_ship() -> void:
 
 func _process(_delta: float) -> void:
 	update_camera()
+	update_debug_adapter()
+
+# Move the debug adapter to the active ship, a connected client has to attach again
+func update_debug_adapter() -> void:
+	if dap_port == 0:
+		return
+	var ship = active_ship
+	if ship == dap_ship:
+		return
+	if is_instance_valid(dap_ship):
+		dap_ship.computer.emulator.dap_stop()
+	dap_ship = ship
+	if ship:
+		ship.computer.emulator.dap_listen(dap_port)
 
 func update_camera() -> void:
 	var camera = $Camera3D as Camera3D
@@ -156,6 +179,23 @@ func js_respawnShipWithCode(source = ""):
 	
 	spawn_ship(source)
 	
+# Replace the active ship's code in place, keeping the ship and its memory
+func js_hotReload(source = ""):
+	# A crashed ship frees itself, there is nothing left to reload
+	if not is_instance_valid(active_ship):
+		return {"reloaded": false, "reason": "No ship is flying"}
+	var reason = active_ship.computer.hot_reload(source)
+	return {"reloaded": reason.is_empty(), "reason": reason}
+
+# Assembler errors and warnings for source, without loading it, as
+# {severity, file, line, column, end, message, code} with lines and columns from 0
+func js_checkProgram(source = ""):
+	return active_ship.computer.emulator.check_program(source)
+
+# The same for the code the active ship last assembled
+func js_getDiagnostics():
+	return active_ship.computer.emulator.get_diagnostics()
+
 func js_nextShip():
 	next_ship()
 	
@@ -209,6 +249,21 @@ func js_getState():
 		"shipIdx": ship_idx
 	} as Dictionary
 
+# Memory written since the previous call, as ranges of {start, old, new}
+func js_getChangedMemory():
+	return active_ship.computer.emulator.get_changed_memory()
+
+# Devices, registers and bit fields of the active ship as JSON
+func js_getMemoryMap():
+	return active_ship.computer.emulator.get_memory_map_json()
+
+# Text on the active ship's terminal, one string per row, empty without a terminal
+func js_getTerminalLines():
+	for component in active_ship.computer.shipComponents:
+		if component.has_method("get_text"):
+			return component.get_text().split("\n")
+	return []
+
 func js_getLineNumber(pc = -1):
 	if pc < 0:
 		var states = active_ship.computer.emulator.get_cpu_state()
@@ -39,6 +39,17 @@ declare global {
 		threads?: boolean;
 	}
 
+	// An assembler error or warning; line, column and end (the column after the token) count from 0
+	interface AsmDiagnostic {
+		severity: 'error' | 'warning';
+		file: string;
+		line: number;
+		column: number;
+		end: number;
+		message: string;
+		code: string;
+	}
+
 	declare class Engine {
 		constructor(config: EngineConfig);
 		static getMissingFeatures(features: EngineFeatureCheck): string[];
@@ -54,11 +65,33 @@ declare global {
 		static async previousShip(): Promise<void>;
 		static async getPage(page?: number): Promise<ArrayBuffer>;
 		static async respawnShipWithCode(source: string): Promise<void>;
+		static async hotReload(source: string): Promise<{ reloaded: boolean; reason: string }>;
+		static async checkProgram(source: string): Promise<AsmDiagnostic[]>;
+		static async getDiagnostics(): Promise<AsmDiagnostic[]>;
 		static async setFrequency(frequency: number): Promise<void>
 		static async pause(): Promise<void>
 		static async resume(): Promise<void>
 		static async step(): Promise<void>
 		static async getLineNumber(pc?: number): Promise<number>;
+		static async getTerminalLines(): Promise<string[]>;
+		static async getChangedMemory(): Promise<{ start: number; old: number[]; new: number[] }[]>;
+		static async getMemoryMap(): Promise<{
+			devices: {
+				name: string;
+				constant: string;
+				base_address: number;
+				size: number;
+				registers: {
+					name: string;
+					constant: string;
+					address: number;
+					offset: number;
+					size: number;
+					access: "read" | "write" | "read_write";
+					fields: { name: string; constant: string; mask: number }[];
+				}[];
+			}[];
+		}>;
 		static async getState(): Promise<{
 			code: string;
 			isPaused: boolean;